@@ -1,11 +1,89 @@
+pub mod reader;
 pub mod token;
 pub mod error;
 pub mod parser;
 pub mod evaluator;
+pub mod value;
+pub mod matrix;
+pub mod list;
+pub mod diagnostic;
+pub mod function;
+pub mod suggest;
+pub mod format;
+pub mod expression;
+pub mod export;
+pub mod latex;
+pub mod simplify;
+pub mod calculus;
+pub mod numeric;
+pub mod solver;
+pub mod environment;
+pub mod module;
+pub mod csv;
+pub mod sheet;
+pub mod table;
+pub mod trace;
 pub use token::Token;
+pub use token::Tokens;
 pub use token::TokenIterator;
+pub use token::Lexer;
+pub use latex::LatexLexer;
+pub use token::Position;
+pub use reader::ReadChars;
 pub use token::Associativity;
 pub use error::Error;
 pub use error::ErrorCode;
+pub use error::Expected;
 pub use parser::Parser;
-pub use evaluator::Evaluator;
\ No newline at end of file
+pub use evaluator::Evaluator;
+pub use evaluator::Angle;
+pub use evaluator::Recompute;
+pub use evaluator::Update;
+pub use value::Value;
+pub use matrix::Matrix;
+pub use function::Function;
+pub use expression::Expression;
+pub use sheet::Sheet;
+pub use trace::Step;
+
+pub fn parse(input: &str) -> Result<Tokens, Error> {
+    let mut parser = Parser::new(TokenIterator::new(input, 10));
+    parser.intermediate()
+}
+
+// What may come next at the end of the input, for completion UIs.
+pub fn expected(input: &str) -> std::collections::BTreeSet<Expected> {
+    let mut parser = Parser::new(TokenIterator::new(input, 10));
+    let _ = parser.script();
+    parser.expected().clone()
+}
+
+// Parses LaTeX math, as in `\frac{a+b}{2}\cdot\sqrt{x^2+1}`.
+pub fn parse_latex(input: &str) -> Result<Tokens, Error> {
+    let mut parser = Parser::new(LatexLexer::new(input));
+    parser.intermediate()
+}
+
+pub fn parse_script(input: &str) -> Result<Vec<Tokens>, Vec<Error>> {
+    let mut parser = Parser::new(TokenIterator::new(input, 10));
+    parser.script()
+}
+
+pub fn parse_reader<R: std::io::Read>(reader: R) -> Result<Vec<Tokens>, Vec<Error>> {
+    let mut parser = Parser::new(TokenIterator::from_reader(std::io::BufReader::new(reader), 10));
+    parser.script()
+}
+
+// Evaluates every statement in the input and returns the value of the last one.
+pub fn eval(input: &str, evaluator: &mut Evaluator) -> Result<Value, Error> {
+    let mut parser = Parser::new(TokenIterator::new(input, evaluator.radix()));
+    let statements = match parser.script() {
+        Ok(statements) => statements,
+        Err(mut errors) => return Err(errors.remove(0)),
+    };
+    let mut ans = Value::Number(0f64);
+    for tokens in statements {
+        ans = evaluator.evaluate(tokens)?;
+    }
+    Ok(ans)
+}
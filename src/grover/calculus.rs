@@ -0,0 +1,493 @@
+use crate::grover::{error, evaluator, expression, function, matrix, numeric, simplify, solver, token, value};
+use error::Error;
+use error::ErrorCode;
+use evaluator::Angle;
+use evaluator::Evaluator;
+use expression::Expression;
+use function::Definition;
+use matrix::Matrix;
+use token::Operator;
+use token::Token;
+use token::Tokens;
+use value::Value;
+
+pub const DIFF: &str = "diff";
+pub const SIMPLIFY: &str = "simplify";
+
+// A function taking an expression in a variable, which it evaluates wherever it needs to.
+pub struct Form {
+    pub name: &'static str,
+    // Argument counts it takes: the expression and the variable, then numbers.
+    pub arities: &'static [usize],
+    // Solves for its variable, so the expression may be an equation, and is passed along with its derivative.
+    pub solves: bool,
+    pub example: &'static str,
+}
+
+impl Form {
+    // How many lambdas the expression becomes.
+    pub fn lambdas(&self) -> usize {
+        if self.solves {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+pub const FORMS: [Form; 6] = [
+    Form { name: "integrate", arities: &[4], solves: false, example: "integrate($x ^ 2, $x, 0, 1)" },
+    Form { name: "nderiv", arities: &[3], solves: false, example: "nderiv(sin($x), $x, 0)" },
+    Form { name: "limit", arities: &[3], solves: false, example: "limit(sin($x) / $x, $x, 0)" },
+    Form { name: "solve", arities: &[2, 4], solves: true, example: "solve($x ^ 2 - 2 = 0, $x)" },
+    Form { name: "roots", arities: &[2, 4], solves: true, example: "roots($x ^ 2 - 2 = 0, $x)" },
+    Form { name: "root", arities: &[3], solves: true, example: "root(cos($x) - $x, $x, 1)" },
+];
+
+pub fn form(name: &str) -> Option<&'static Form> {
+    FORMS.iter().find(|form| form.name == name)
+}
+
+// Functions that, given a lambda in an index and its bounds as in `sum($i -> $i ^ 2, 1, 100)`, add up or multiply
+// the lambda's expression over the index rather than their arguments. A lambda can not be an argument of the plain
+// `sum($a, $b, $c)`, so the two do not mix.
+pub const SERIES: [&str; 2] = ["sum", "prod"];
+
+// The index and the expression of a series: a lambda in one parameter, then the bounds.
+pub fn series<'a>(name: &str, arguments: &'a [Expression]) -> Option<(&'a str, &'a Expression)> {
+    match arguments {
+        [Expression::Lambda(parameters, term), _, _] if parameters.len() == 1 && SERIES.contains(&name) => Some((&parameters[0], term)),
+        _ => None,
+    }
+}
+
+// Whether a statement uses a function that has to see its arguments unevaluated.
+pub fn is_symbolic(tokens: &Tokens) -> bool {
+    tokens.iter().any(|token| match token {
+        Token::Function(name, _) => name == DIFF || name == SIMPLIFY || form(name).is_some(),
+        _ => false,
+    })
+}
+
+// Whether a statement is just a derivative or a simplification, which can be shown as an expression when it has
+// variables that are not defined, as in `diff($x ^ 2, $x)`.
+pub fn is_rewrite(tokens: &Tokens) -> bool {
+    matches!(tokens.iter().last(), Some(Token::Function(name, _)) if name == DIFF || name == SIMPLIFY)
+}
+
+// Whether a statement is just an integral or a numerical derivative, whose value is shown with its error bound.
+pub fn is_estimate(tokens: &Tokens) -> bool {
+    matches!(tokens.iter().last(), Some(Token::Function(name, _)) if name == "integrate" || name == "nderiv")
+}
+
+// Takes derivatives and turns the expressions passed to forms into lambdas, before the statement is evaluated.
+pub fn prepare(evaluator: &Evaluator, tokens: &Tokens) -> Result<Tokens, Error> {
+    Ok(quote(evaluator, &expand(evaluator, &Expression::from_tokens(tokens)?)?)?.to_tokens())
+}
+
+// Evaluates a form, given the lambdas its expression became and its other arguments. Integrals and derivatives come
+// with a bound on their error.
+pub fn apply(evaluator: &mut Evaluator, name: &str, lambdas: &[(Vec<String>, Tokens)], arguments: &[f64]) -> Result<(Value, Option<f64>), Error> {
+    let evaluator = std::cell::RefCell::new(evaluator);
+    let lambda = |index: usize| {
+        let (parameters, body) = &lambdas[index];
+        let evaluator = &evaluator;
+        move |x: f64| evaluator.borrow_mut().apply(parameters, body, vec![Value::Number(x)]).and_then(|value| value.number())
+    };
+    let mut f = lambda(0);
+    let estimate = |estimate: numeric::Estimate| (Value::Number(estimate.value), Some(estimate.error));
+    let exact = |value: f64| (Value::Number(value), None);
+    // Every root, as a list.
+    let list = |roots: Vec<f64>| (Value::Matrix(Matrix::new(1, roots.len(), roots)), None);
+    let result = match (name, arguments) {
+        ("integrate", [a, b]) => numeric::integrate(&mut f, *a, *b).map(estimate),
+        ("nderiv", [x]) => numeric::derivative(&mut f, *x).map(estimate),
+        ("limit", [a]) => numeric::limit(&mut f, *a).map(exact),
+        ("solve", []) => solver::roots(&mut f, &mut lambda(1), &solver::everywhere()).and_then(|roots| nearest(roots, String::new())).map(exact),
+        ("solve", [a, b]) => {
+            solver::roots(&mut f, &mut lambda(1), &solver::span(*a, *b, 1000)).and_then(|roots| nearest(roots, format!(" between {} and {}", a, b))).map(exact)
+        }
+        ("roots", []) => solver::roots(&mut f, &mut lambda(1), &solver::everywhere()).map(list),
+        ("roots", [a, b]) => solver::roots(&mut f, &mut lambda(1), &solver::span(*a, *b, 1000)).map(list),
+        ("root", [guess]) => {
+            solver::newton(&mut f, &mut lambda(1), *guess).or_else(|_| solver::bracket(&mut f, *guess)).map(exact)
+        }
+        _ => Err(Error::new(ErrorCode::EvaluatorError, format!("Unhandled form \'{}\'.", name))),
+    };
+    result.map_err(|error| Error::new(error.code, format!("Could not evaluate \'{}\'.", name)).with_cause(error))
+}
+
+// Picks one of the roots found: the one closest to zero, or the positive one of a pair like ±√2. `roots` gives all
+// of them.
+fn nearest(roots: Vec<f64>, within: String) -> Result<f64, Error> {
+    let key = |root: &f64| (root.abs(), root.is_sign_negative());
+    match roots.into_iter().min_by(|a, b| key(a).partial_cmp(&key(b)).unwrap_or(std::cmp::Ordering::Equal)) {
+        Some(root) => Ok(root + 0f64),
+        None => Err(Error::new(ErrorCode::NoConvergence, format!("No real root was found{}.", within))),
+    }
+}
+
+// Replaces every `diff(expr, $x)` with the simplified derivative and every `simplify(expr)` with the simplified
+// expression, innermost first.
+pub fn expand(evaluator: &Evaluator, expression: &Expression) -> Result<Expression, Error> {
+    let expand = |expression: &Expression| self::expand(evaluator, expression);
+    Ok(match expression {
+        Expression::Call(name, arguments) if name == DIFF => {
+            let variable = variable(DIFF, arguments, &[2], "diff($x ^ 2, $x)")?;
+            let mut derivation = Derivation { evaluator, variable, inlined: Vec::new() };
+            simplify::simplify(evaluator, &derivation.derivative(&expand(&arguments[0])?)?)
+        }
+        Expression::Call(name, arguments) if name == SIMPLIFY => match arguments.as_slice() {
+            [argument] => simplify::simplify(evaluator, &expand(argument)?),
+            _ => {
+                return Err(Error::new(ErrorCode::ArityMismatch, format!("\'{}\' takes 1 argument but {} were given.", SIMPLIFY, arguments.len())));
+            }
+        },
+        Expression::Call(name, arguments) => Expression::Call(name.clone(), arguments.iter().map(expand).collect::<Result<_, _>>()?),
+        Expression::Negate(operand) => Expression::Negate(Box::new(expand(operand)?)),
+        Expression::Binary(operator, lhs, rhs) => Expression::Binary(operator, Box::new(expand(lhs)?), Box::new(expand(rhs)?)),
+        Expression::Block(statements) => Expression::Block(statements.iter().map(expand).collect::<Result<_, _>>()?),
+        Expression::Let(name, value) => Expression::Let(name.clone(), Box::new(expand(value)?)),
+        Expression::Lambda(parameters, body) => Expression::Lambda(parameters.clone(), Box::new(expand(body)?)),
+        Expression::Matrix(rows) => Expression::Matrix(rows.iter().map(|row| row.iter().map(expand).collect()).collect::<Result<_, _>>()?),
+        Expression::Index(value, indices) => Expression::Index(Box::new(expand(value)?), indices.iter().map(expand).collect::<Result<_, _>>()?),
+        _ => expression.clone(),
+    })
+}
+
+// Expands the derivatives in a parsed statement, keeping what it defines or binds.
+pub fn statement(evaluator: &Evaluator, tokens: &Tokens) -> Result<Tokens, Error> {
+    expression::rewrite(tokens, |expression| expand(evaluator, expression))
+}
+
+fn quote(evaluator: &Evaluator, expression: &Expression) -> Result<Expression, Error> {
+    let quote = |expression: &Expression| self::quote(evaluator, expression);
+    Ok(match expression {
+        Expression::Call(name, arguments) => match form(name) {
+            Some(form) => {
+                let variable = variable(name, arguments, form.arities, form.example)?;
+                let mut expression = quote(&arguments[0])?;
+                // An equation is solved as the difference of its sides.
+                if let Expression::Binary(operator, lhs, rhs) = &expression {
+                    if form.solves && **operator == token::ASSIGNMENT_OPERATOR {
+                        expression = subtract(*lhs.clone(), *rhs.clone());
+                    }
+                }
+                let mut quoted = vec![Expression::Lambda(vec![variable.to_string()], Box::new(expression.clone()))];
+                if form.solves {
+                    // Newton's method works best with the exact derivative, when there is one.
+                    let mut derivation = Derivation { evaluator, variable, inlined: Vec::new() };
+                    let derivative = match derivation.derivative(&expression) {
+                        Ok(derivative) => simplify::simplify(evaluator, &derivative),
+                        Err(_) => call("nderiv", vec![quoted[0].clone(), Expression::Variable(variable.to_string())]),
+                    };
+                    quoted.push(Expression::Lambda(vec![variable.to_string()], Box::new(derivative)));
+                }
+                for argument in arguments.iter().skip(2) {
+                    quoted.push(quote(argument)?);
+                }
+                Expression::Call(name.clone(), quoted)
+            }
+            None => Expression::Call(name.clone(), arguments.iter().map(quote).collect::<Result<_, _>>()?),
+        },
+        Expression::Negate(operand) => Expression::Negate(Box::new(quote(operand)?)),
+        Expression::Binary(operator, lhs, rhs) => Expression::Binary(operator, Box::new(quote(lhs)?), Box::new(quote(rhs)?)),
+        Expression::Block(statements) => Expression::Block(statements.iter().map(quote).collect::<Result<_, _>>()?),
+        Expression::Let(name, value) => Expression::Let(name.clone(), Box::new(quote(value)?)),
+        Expression::Lambda(parameters, body) => Expression::Lambda(parameters.clone(), Box::new(quote(body)?)),
+        Expression::Matrix(rows) => Expression::Matrix(rows.iter().map(|row| row.iter().map(quote).collect()).collect::<Result<_, _>>()?),
+        Expression::Index(value, indices) => Expression::Index(Box::new(quote(value)?), indices.iter().map(quote).collect::<Result<_, _>>()?),
+        _ => expression.clone(),
+    })
+}
+
+// The variable a form is taken in, which is always its second argument.
+fn variable<'a>(name: &str, arguments: &'a [Expression], arities: &[usize], example: &str) -> Result<&'a str, Error> {
+    if !arities.contains(&arguments.len()) {
+        let arities: Vec<String> = arities.iter().map(|arity| arity.to_string()).collect();
+        let arities = match arities.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+            _ => arities.concat(),
+        };
+        return Err(Error::new(ErrorCode::ArityMismatch, format!("\'{}\' takes {} arguments but {} were given.", name, arities, arguments.len())));
+    }
+    match &arguments[1] {
+        Expression::Variable(variable) => Ok(variable),
+        _ => Err(Error::new(ErrorCode::EvaluatorError, format!("The second argument of \'{}\' must be a variable, as in {}.", name, example))),
+    }
+}
+
+struct Derivation<'a> {
+    evaluator: &'a Evaluator,
+    variable: &'a str,
+    // User functions being inlined, to refuse recursive ones.
+    inlined: Vec<String>,
+}
+
+impl Derivation<'_> {
+    fn derivative(&mut self, expression: &Expression) -> Result<Expression, Error> {
+        if !self.depends(expression) {
+            return Ok(number(0f64));
+        }
+        Ok(match expression {
+            Expression::Variable(_) => number(1f64),
+            Expression::Negate(operand) => Expression::Negate(Box::new(self.derivative(operand)?)),
+            Expression::Binary(operator, u, v) => {
+                let (du, dv) = (self.derivative(u)?, self.derivative(v)?);
+                let (u, v) = (*u.clone(), *v.clone());
+                match operator.symbol {
+                    "+" => add(du, dv),
+                    "-" => subtract(du, dv),
+                    "*" => add(multiply(du, v), multiply(u, dv)),
+                    "/" => divide(subtract(multiply(du, v.clone()), multiply(u, dv)), power(v, number(2f64))),
+                    "^" if !self.depends(&v) => multiply(multiply(v.clone(), power(u, subtract(v, number(1f64)))), du),
+                    "^" if !self.depends(&u) => multiply(multiply(power(u.clone(), v), call("ln", vec![u])), dv),
+                    "^" => {
+                        let rate = add(multiply(dv, call("ln", vec![u.clone()])), divide(multiply(v.clone(), du), u.clone()));
+                        multiply(power(u, v), rate)
+                    }
+                    "%" => subtract(du, multiply(dv, call("floor", vec![divide(u, v)]))),
+                    _ => return Err(self.underivable(format!("\'{}\'", operator.symbol))),
+                }
+            }
+            Expression::Call(name, arguments) => self.call(name, arguments)?,
+            Expression::Block(_) | Expression::Let(_, _) => return Err(self.underivable("a block".to_string())),
+            Expression::Lambda(_, _) => return Err(self.underivable("a lambda".to_string())),
+            // Element by element.
+            Expression::Matrix(rows) => {
+                Expression::Matrix(rows.iter().map(|row| row.iter().map(|element| self.derivative(element)).collect()).collect::<Result<_, _>>()?)
+            }
+            // Picking out elements is linear too, as long as which ones does not depend on the variable.
+            Expression::Index(value, indices) if !indices.iter().any(|index| self.depends(index)) => {
+                Expression::Index(Box::new(self.derivative(value)?), indices.clone())
+            }
+            Expression::Index(_, _) => return Err(self.underivable("an index".to_string())),
+            _ => number(0f64),
+        })
+    }
+    // The chain rule, with the derivative of each built-in function.
+    fn call(&mut self, name: &str, arguments: &[Expression]) -> Result<Expression, Error> {
+        if form(name).is_some() {
+            return Err(self.underivable(format!("\'{}\'", name)));
+        }
+        // Term by term, as long as the bounds do not move.
+        if let Some((index, term)) = series(name, arguments) {
+            if self.depends(&arguments[1]) || self.depends(&arguments[2]) {
+                return Err(self.underivable(format!("\'{}\' over bounds that depend on it", name)));
+            }
+            let series = |name: &str, term: Expression| {
+                call(name, vec![Expression::Lambda(vec![index.to_string()], Box::new(term)), arguments[1].clone(), arguments[2].clone()])
+            };
+            // The index hides a variable of the same name, whose derivative is then 0.
+            let derivative = if index == self.variable { number(0f64) } else { self.derivative(term)? };
+            return Ok(match name {
+                "prod" => multiply(series("prod", term.clone()), series("sum", divide(derivative, term.clone()))),
+                _ => series("sum", derivative),
+            });
+        }
+        let function = match self.evaluator.function(name) {
+            Some(function) => function,
+            None => return Err(self.evaluator.undefined_function(name)),
+        };
+        if let Definition::User { ref parameters, ref body } = function.definition {
+            if self.inlined.iter().any(|inlined| inlined == name) {
+                return Err(self.underivable(format!("the recursive function \'{}\'", name)));
+            }
+            if parameters.len() != arguments.len() {
+                return Err(Error::new(ErrorCode::ArityMismatch, format!("\'{}\' takes {} arguments but {} were given.", name, parameters.len(), arguments.len())));
+            }
+            let body = expand(self.evaluator, &Expression::from_tokens(body)?)?;
+            let inlined = substitute(&body, parameters, arguments);
+            self.inlined.push(name.to_string());
+            let derivative = self.derivative(&inlined);
+            self.inlined.pop();
+            return derivative;
+        }
+        // Trigonometric functions work in degrees when the evaluator does.
+        let degrees = self.evaluator.angle() == Angle::Degrees;
+        let radians = |expression: Expression| {
+            if degrees {
+                multiply(expression, divide(Expression::Variable("$pi".to_string()), number(180f64)))
+            } else {
+                expression
+            }
+        };
+        let degrees = |expression: Expression| {
+            if degrees {
+                multiply(expression, divide(number(180f64), Expression::Variable("$pi".to_string())))
+            } else {
+                expression
+            }
+        };
+        // Transposing is linear.
+        if let ("transpose", [u]) = (name, arguments) {
+            return Ok(call("transpose", vec![self.derivative(u)?]));
+        }
+        if let [u] = arguments {
+            let du = self.derivative(u)?;
+            let u = u.clone();
+            let outer = match name {
+                "sqrt" => divide(number(1f64), multiply(number(2f64), call("sqrt", vec![u]))),
+                "cbrt" => divide(number(1f64), multiply(number(3f64), power(call("cbrt", vec![u]), number(2f64)))),
+                "abs" => divide(u.clone(), call("abs", vec![u])),
+                "exp" => call("exp", vec![u]),
+                "ln" => divide(number(1f64), u),
+                "log" => divide(number(1f64), multiply(u, call("ln", vec![number(10f64)]))),
+                "log2" => divide(number(1f64), multiply(u, call("ln", vec![number(2f64)]))),
+                "sin" => radians(call("cos", vec![u])),
+                "cos" => radians(Expression::Negate(Box::new(call("sin", vec![u])))),
+                "tan" => radians(divide(number(1f64), power(call("cos", vec![u]), number(2f64)))),
+                "asin" => degrees(divide(number(1f64), call("sqrt", vec![subtract(number(1f64), power(u, number(2f64)))]))),
+                "acos" => degrees(Expression::Negate(Box::new(divide(number(1f64), call("sqrt", vec![subtract(number(1f64), power(u, number(2f64)))]))))),
+                "atan" => degrees(divide(number(1f64), add(number(1f64), power(u, number(2f64))))),
+                "sinh" => call("cosh", vec![u]),
+                "cosh" => call("sinh", vec![u]),
+                "tanh" => divide(number(1f64), power(call("cosh", vec![u]), number(2f64))),
+                "floor" | "ceil" | "round" => number(0f64),
+                "sum" | "prod" | "mean" | "min" | "max" => number(1f64),
+                _ => return Err(self.underivable(format!("\'{}\'", name))),
+            };
+            return Ok(multiply(outer, du));
+        }
+        match name {
+            "atan2" => {
+                let (y, x) = (arguments[0].clone(), arguments[1].clone());
+                let (dy, dx) = (self.derivative(&y)?, self.derivative(&x)?);
+                let numerator = subtract(multiply(x.clone(), dy), multiply(y.clone(), dx));
+                Ok(degrees(divide(numerator, add(power(x, number(2f64)), power(y, number(2f64))))))
+            }
+            "sum" | "mean" => {
+                let mut derivative = number(0f64);
+                for argument in arguments {
+                    derivative = add(derivative, self.derivative(argument)?);
+                }
+                if name == "mean" {
+                    derivative = divide(derivative, number(arguments.len() as f64));
+                }
+                Ok(derivative)
+            }
+            _ => Err(self.underivable(format!("\'{}\'", name))),
+        }
+    }
+    fn depends(&self, expression: &Expression) -> bool {
+        match expression {
+            Expression::Variable(name) => name == self.variable,
+            Expression::Call(name, arguments) => {
+                arguments.iter().any(|argument| self.depends(argument))
+                    || self.evaluator.function(name).is_some_and(|function| match function.definition {
+                        // User functions may read the variable as a global.
+                        Definition::User { ref parameters, ref body } => {
+                            !parameters.iter().any(|parameter| parameter == self.variable)
+                                && body.iter().any(|token| matches!(token, Token::Identifier(name) if name == self.variable))
+                        }
+                        Definition::Native(_) | Definition::Values(_) => false,
+                    })
+            }
+            Expression::Negate(operand) | Expression::Let(_, operand) => self.depends(operand),
+            Expression::Binary(_, lhs, rhs) => self.depends(lhs) || self.depends(rhs),
+            Expression::Block(statements) => statements.iter().any(|statement| self.depends(statement)),
+            Expression::Lambda(parameters, body) => !parameters.iter().any(|parameter| parameter == self.variable) && self.depends(body),
+            Expression::Matrix(rows) => rows.iter().flatten().any(|element| self.depends(element)),
+            Expression::Index(value, indices) => self.depends(value) || indices.iter().any(|index| self.depends(index)),
+            _ => false,
+        }
+    }
+    fn underivable(&self, what: String) -> Error {
+        Error::new(ErrorCode::DomainError, format!("Can not differentiate {} with respect to \'{}\'.", what, self.variable))
+    }
+}
+
+// Replaces the parameters of a function body with the arguments of a call.
+fn substitute(expression: &Expression, parameters: &[String], arguments: &[Expression]) -> Expression {
+    let substitute = |expression: &Expression| self::substitute(expression, parameters, arguments);
+    match expression {
+        Expression::Variable(name) => match parameters.iter().position(|parameter| parameter == name) {
+            Some(index) => arguments[index].clone(),
+            None => expression.clone(),
+        },
+        Expression::Call(name, inner) => Expression::Call(name.clone(), inner.iter().map(substitute).collect()),
+        Expression::Negate(operand) => Expression::Negate(Box::new(substitute(operand))),
+        Expression::Binary(operator, lhs, rhs) => Expression::Binary(operator, Box::new(substitute(lhs)), Box::new(substitute(rhs))),
+        Expression::Block(statements) => Expression::Block(statements.iter().map(substitute).collect()),
+        Expression::Let(name, value) => Expression::Let(name.clone(), Box::new(substitute(value))),
+        Expression::Lambda(inner, body) => {
+            // Parameters of the lambda hide those of the function.
+            let (parameters, arguments) = unshadowed(parameters, arguments, inner);
+            Expression::Lambda(inner.clone(), Box::new(self::substitute(body, &parameters, &arguments)))
+        }
+        Expression::Matrix(rows) => Expression::Matrix(rows.iter().map(|row| row.iter().map(substitute).collect()).collect()),
+        Expression::Index(value, indices) => Expression::Index(Box::new(substitute(value)), indices.iter().map(substitute).collect()),
+        _ => expression.clone(),
+    }
+}
+
+fn unshadowed(parameters: &[String], arguments: &[Expression], hidden: &[String]) -> (Vec<String>, Vec<Expression>) {
+    parameters.iter().cloned().zip(arguments.iter().cloned()).filter(|(parameter, _)| !hidden.contains(parameter)).unzip()
+}
+
+fn number(value: f64) -> Expression {
+    Expression::Number(value)
+}
+
+fn call(name: &str, arguments: Vec<Expression>) -> Expression {
+    Expression::Call(name.to_string(), arguments)
+}
+
+fn binary(operator: &'static Operator, lhs: Expression, rhs: Expression) -> Expression {
+    Expression::Binary(operator, Box::new(lhs), Box::new(rhs))
+}
+
+fn add(lhs: Expression, rhs: Expression) -> Expression {
+    binary(&token::ADDITION_OPERATOR, lhs, rhs)
+}
+
+fn subtract(lhs: Expression, rhs: Expression) -> Expression {
+    binary(&token::SUBTRACTION_OPERATOR, lhs, rhs)
+}
+
+fn multiply(lhs: Expression, rhs: Expression) -> Expression {
+    binary(&token::MULTIPLICATION_OPERATOR, lhs, rhs)
+}
+
+fn divide(lhs: Expression, rhs: Expression) -> Expression {
+    binary(&token::DIVISION_OPERATOR, lhs, rhs)
+}
+
+fn power(lhs: Expression, rhs: Expression) -> Expression {
+    binary(&token::POWER_OPERATOR, lhs, rhs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::{eval, format, parse};
+
+    fn derivative(input: &str) -> String {
+        let evaluator = Evaluator::new();
+        let expanded = statement(&evaluator, &parse(input).unwrap()).unwrap();
+        format::statement(&simplify::statement(&evaluator, &expanded).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn applies_the_rules() {
+        assert_eq!(derivative("diff($x ^ 3 + 2 * $x, $x)"), "3 * $x ^ 2 + 2");
+        assert_eq!(derivative("diff(sin($x) * $x, $x)"), "$x * cos($x) + sin($x)");
+        assert_eq!(derivative("diff($x / ($x + 1), $x)"), "1 / ($x + 1) ^ 2");
+        assert_eq!(derivative("diff(exp(2 * $x), $x)"), "2 * exp(2 * $x)");
+        assert_eq!(derivative("diff(atan($x), $x)"), "1 / ($x ^ 2 + 1)");
+        assert_eq!(derivative("diff($y * $x, $x)"), "$y");
+    }
+
+    #[test]
+    fn evaluates_at_a_point() {
+        let mut evaluator = Evaluator::new();
+        assert_eq!(eval("$x = 2; diff($x ^ 3, $x)", &mut evaluator).unwrap(), Value::Number(12f64));
+        assert_eq!(eval("d($t) = diff($t ^ 2, $t); d(5)", &mut evaluator).unwrap(), Value::Number(10f64));
+    }
+
+    #[test]
+    fn needs_a_variable() {
+        let error = statement(&Evaluator::new(), &parse("diff(1, 2)").unwrap()).unwrap_err();
+        assert_eq!(error.message, "The second argument of 'diff' must be a variable, as in diff($x ^ 2, $x).");
+    }
+}
@@ -0,0 +1,88 @@
+use crate::grover::{error, token};
+use error::Error;
+use error::ErrorCode;
+use token::Position;
+
+// Splits CSV text into records of fields. Fields may be quoted, with `""` standing for a quote,
+// and quoted fields may span lines.
+pub fn parse(input: &str) -> Result<Vec<Vec<String>>, Error> {
+    let mut records = Vec::<Vec<String>>::new();
+    let mut record = Vec::<String>::new();
+    let mut field = String::new();
+    let mut position = Position { line: 1, column: 1 };
+    // Where the open quoted field started, if inside one.
+    let mut quoted: Option<Position> = None;
+    // Whether the current field was quoted, so nothing but a separator may follow it.
+    let mut closed = false;
+    let mut characters = input.chars().peekable();
+    while let Some(character) = characters.next() {
+        let here = position;
+        if character == '\n' {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+        if quoted.is_some() {
+            match character {
+                '"' if characters.peek() == Some(&'"') => {
+                    characters.next();
+                    position.column += 1;
+                    field.push('"');
+                }
+                '"' => {
+                    quoted = None;
+                    closed = true;
+                }
+                _ => field.push(character),
+            }
+            continue;
+        }
+        match character {
+            ',' => {
+                record.push(std::mem::take(&mut field));
+                closed = false;
+            }
+            '\r' if characters.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                closed = false;
+            }
+            '"' if field.is_empty() && !closed => quoted = Some(here),
+            _ if closed => {
+                return Err(Error::at(ErrorCode::FormatError, "Expected ',' or the end of the line after a quoted field.".to_string(), here));
+            }
+            '"' => {
+                return Err(Error::at(ErrorCode::FormatError, "Quotes inside a field must be escaped by quoting the whole field.".to_string(), here));
+            }
+            _ => field.push(character),
+        }
+    }
+    if let Some(start) = quoted {
+        return Err(Error::at(ErrorCode::FormatError, "Missing the closing quote.".to_string(), start));
+    }
+    if !field.is_empty() || !record.is_empty() || closed {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+pub fn write(records: &[Vec<String>]) -> String {
+    let mut output = String::new();
+    for record in records {
+        let fields: Vec<String> = record.iter().map(|field| quote(field)).collect();
+        output += &fields.join(",");
+        output.push('\n');
+    }
+    output
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
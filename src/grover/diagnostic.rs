@@ -0,0 +1,51 @@
+use crate::grover::error::Error;
+
+// Formats an error against the source it came from, pointing at the offending column:
+//
+// error[E100]: Invalid character '#'.
+//  --> 1:3
+//   |
+// 1 | 2 # 3
+//   |   ^
+pub fn render(source: &str, error: &Error) -> String {
+    let mut output = format!("error[{}]: {}\n", error.code, error.message);
+    output += &snippet(source, error);
+    if !error.suggestions.is_empty() {
+        let suggestions: Vec<String> = error.suggestions.iter().map(|name| format!("`{}`", name)).collect();
+        output += &format!("help: did you mean {}?\n", suggestions.join(" or "));
+    }
+    let mut cause = error.cause.as_deref();
+    while let Some(inner) = cause {
+        output += &format!("caused by: {}\n", inner);
+        cause = inner.cause.as_deref();
+    }
+    output
+}
+
+fn snippet(source: &str, error: &Error) -> String {
+    let position = match error.position {
+        Some(position) => position,
+        None => return String::new(),
+    };
+    let mut output = match error.file {
+        Some(ref file) => format!(" --> {}:{}\n", file.name, position),
+        None => format!(" --> {}\n", position),
+    };
+    // Errors from imported files are shown against the file, as it was when it was read, rather than the given source.
+    let source = error.file.as_ref().map_or(source, |file| &file.text);
+    let line = match source.lines().nth(position.line as usize - 1) {
+        Some(line) => line,
+        None => return output,
+    };
+    let number = position.line.to_string();
+    let gutter = " ".repeat(number.len());
+    let padding: String = line
+        .chars()
+        .take(position.column as usize - 1)
+        .map(|character| if character == '\t' { '\t' } else { ' ' })
+        .collect();
+    output += &format!("{} |\n", gutter);
+    output += &format!("{} | {}\n", number, line);
+    output += &format!("{} | {}^\n", gutter, padding);
+    output
+}
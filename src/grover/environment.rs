@@ -0,0 +1,480 @@
+use crate::grover::{error, evaluator, format, function, module, parse_script, parser, token, value};
+use error::Error;
+use error::ErrorCode;
+use evaluator::Angle;
+use evaluator::Evaluator;
+use evaluator::Recompute;
+use function::Definition;
+use parser::Parser;
+use token::Position;
+use token::Token;
+use token::TokenIterator;
+use token::Tokens;
+
+// Bumped whenever a file written by this version could be misread by an older one.
+pub const VERSION: u32 = 1;
+
+// Writes the environment as a small TOML document:
+//
+// version = 1
+//
+// [settings]
+// radix = 10
+// angle = "radians"
+// lenient = false
+//
+// [variables]
+// "$width" = 3
+// "$m" = "[1, 2; 3, 4]"
+//
+// [constants]
+// "$g" = 9.81
+//
+// [functions]
+// area = "area($w, $h) = ($w * $h)"
+//
+// [bindings]
+// "$area" = "$area := ($width * $height)"
+//
+// Functions and bindings are written in the radix of the settings, and read back in it.
+pub fn save(evaluator: &Evaluator) -> Result<String, Error> {
+    let mut output = format!("version = {}\n", VERSION);
+    let radix = evaluator.radix();
+
+    output += "\n[settings]\n";
+    output += &format!("radix = {}\n", evaluator.radix());
+    output += &format!("angle = {}\n", quote(match evaluator.angle() {
+        Angle::Radians => "radians",
+        Angle::Degrees => "degrees",
+    }));
+    output += &format!("lenient = {}\n", evaluator.is_lenient());
+    output += &format!("recompute = {}\n", quote(match evaluator.recompute() {
+        Recompute::Eager => "eager",
+        Recompute::Lazy => "lazy",
+    }));
+
+    let variables = evaluator.variables();
+    output += "\n[variables]\n";
+    for (name, value, _) in variables.iter().filter(|(name, _, constant)| !constant && !evaluator.is_bound(name)) {
+        output += &format!("{} = {}\n", quote(name), written(value));
+    }
+    output += "\n[constants]\n";
+    for (name, value, _) in variables.iter().filter(|(_, _, constant)| *constant) {
+        output += &format!("{} = {}\n", quote(name), written(value));
+    }
+
+    output += "\n[functions]\n";
+    for (name, function) in evaluator.functions() {
+        if let Definition::User { ref parameters, ref body } = function.definition {
+            let definition = format!("{}({}) = {}", name, parameters.join(", "), format::infix_in(body, radix)?);
+            let tokens: Tokens = std::iter::once(Token::Definition(name.to_string(), parameters.clone())).chain(body.iter().cloned()).collect();
+            output += &format!("{} = {}\n", name, quote(&source(name, definition, &tokens, radix)?));
+        }
+    }
+
+    // Bindings are written after the ones they read so that each can be computed as it loads.
+    let bindings = evaluator.bindings();
+    let mut order = Vec::<&str>::new();
+    let mut pending: Vec<(&str, bool)> = bindings.iter().rev().map(|(name, _)| (*name, false)).collect();
+    while let Some((name, expanded)) = pending.pop() {
+        if order.contains(&name) {
+            continue;
+        }
+        if expanded {
+            order.push(name);
+            continue;
+        }
+        pending.push((name, true));
+        if let Some((_, binding)) = bindings.iter().find(|(bound, _)| *bound == name) {
+            for dependency in binding.dependencies.iter().rev() {
+                if let Some((bound, _)) = bindings.iter().find(|(bound, _)| bound == dependency) {
+                    pending.push((bound, false));
+                }
+            }
+        }
+    }
+    output += "\n[bindings]\n";
+    for name in order {
+        if let Some((_, binding)) = bindings.iter().find(|(bound, _)| *bound == name) {
+            let definition = format!("{} := {}", name, format::infix_in(&binding.formula, radix)?);
+            let tokens: Tokens = std::iter::once(Token::Binding(name.to_string())).chain(binding.formula.iter().cloned()).collect();
+            output += &format!("{} = {}\n", quote(name), quote(&source(name, definition, &tokens, radix)?));
+        }
+    }
+
+    Ok(output)
+}
+
+// Checks that a definition reads back as it was. Outside radix 10 fractions can not be written at all, and function
+// names such as `area` would read as a number.
+fn source(name: &str, definition: String, tokens: &Tokens, radix: u32) -> Result<String, Error> {
+    if radix == 10 {
+        return Ok(definition);
+    }
+    match parse(&definition, radix) {
+        Ok(statements) if statements.len() == 1 && statements[0] == *tokens => Ok(definition),
+        _ => Err(Error::new(ErrorCode::FormatError, format!("\'{}\' can not be written in radix {}.", name, radix))),
+    }
+}
+
+fn parse(input: &str, radix: u32) -> Result<Vec<Tokens>, Error> {
+    Parser::new(TokenIterator::new(input, radix)).script().map_err(|mut errors| errors.remove(0))
+}
+
+// Loads an environment written by save. Nothing changes unless the whole file loads.
+pub fn load(evaluator: &mut Evaluator, input: &str) -> Result<(), Error> {
+    evaluator.begin();
+    match apply(evaluator, input) {
+        Ok(settings) => {
+            evaluator.commit()?;
+            if let Some(radix) = settings.radix {
+                evaluator.set_radix(radix);
+            }
+            if let Some(angle) = settings.angle {
+                evaluator.set_angle(angle);
+            }
+            if let Some(lenient) = settings.lenient {
+                evaluator.set_lenient(lenient);
+            }
+            if let Some(recompute) = settings.recompute {
+                evaluator.set_recompute(recompute);
+            }
+            Ok(())
+        }
+        Err(error) => {
+            evaluator.rollback()?;
+            Err(error)
+        }
+    }
+}
+
+pub fn save_file(evaluator: &Evaluator, path: &str) -> Result<(), Error> {
+    let output = save(evaluator)?;
+    std::fs::write(path, output)
+        .map_err(|error| Error::new(ErrorCode::IoError, format!("Could not write \'{}\': {}", path, error)))
+}
+
+pub fn load_file(evaluator: &mut Evaluator, path: &str) -> Result<(), Error> {
+    let input = std::fs::read_to_string(path)
+        .map_err(|error| Error::new(ErrorCode::IoError, format!("Could not read \'{}\': {}", path, error)))?;
+    load(evaluator, &input).map_err(|error| error.in_file(path, input.as_str().into()))
+}
+
+#[derive(Default)]
+struct Settings {
+    radix: Option<u32>,
+    angle: Option<Angle>,
+    lenient: Option<bool>,
+    recompute: Option<Recompute>,
+}
+
+enum Value {
+    Number(f64),
+    Boolean(bool),
+    Text(String),
+}
+
+fn apply(evaluator: &mut Evaluator, input: &str) -> Result<Settings, Error> {
+    let mut settings = Settings::default();
+    let mut section = String::new();
+    let mut version = None;
+    // Whether any function or binding has been read, which the radix must come before.
+    let mut sources = false;
+    for (index, line) in input.lines().enumerate() {
+        let position = Position { line: index as u32 + 1, column: 1 };
+        let fail = |message: String| Error::at(ErrorCode::FormatError, message, position);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(fail("Expected \']\' after the section name.".to_string()));
+            }
+            section = line[1..line.len() - 1].trim().to_string();
+            continue;
+        }
+        let (key, value) = entry(line).map_err(fail)?;
+        if section.is_empty() {
+            match (key.as_str(), value) {
+                ("version", Value::Number(number)) if number == VERSION as f64 => version = Some(VERSION),
+                ("version", Value::Number(number)) => {
+                    return Err(fail(format!("Unsupported version {}, expected {}.", number, VERSION)));
+                }
+                _ => return Err(fail(format!("Unknown key \'{}\'.", key))),
+            }
+            continue;
+        }
+        if version.is_none() {
+            return Err(fail("The version must come before any section.".to_string()));
+        }
+        match (section.as_str(), key.as_str(), value) {
+            ("settings", "radix", _) if sources => return Err(fail("The radix must be set before any function or binding.".to_string())),
+            ("settings", "radix", Value::Number(radix)) if radix.fract() == 0f64 && (2f64..=36f64).contains(&radix) => {
+                settings.radix = Some(radix as u32);
+            }
+            ("settings", "radix", _) => return Err(fail("The radix must be a whole number from 2 to 36.".to_string())),
+            ("settings", "angle", Value::Text(ref angle)) if angle == "radians" => settings.angle = Some(Angle::Radians),
+            ("settings", "angle", Value::Text(ref angle)) if angle == "degrees" => settings.angle = Some(Angle::Degrees),
+            ("settings", "angle", _) => return Err(fail("The angle must be \"radians\" or \"degrees\".".to_string())),
+            ("settings", "lenient", Value::Boolean(lenient)) => settings.lenient = Some(lenient),
+            ("settings", "lenient", _) => return Err(fail("Lenient must be true or false.".to_string())),
+            ("settings", "recompute", Value::Text(ref recompute)) if recompute == "eager" => settings.recompute = Some(Recompute::Eager),
+            ("settings", "recompute", Value::Text(ref recompute)) if recompute == "lazy" => settings.recompute = Some(Recompute::Lazy),
+            ("settings", "recompute", _) => return Err(fail("Recompute must be \"eager\" or \"lazy\".".to_string())),
+            ("variables", _, Value::Number(value)) => {
+                variable(&key).map_err(fail)?;
+                evaluator.declare(&key, value::Value::Number(value)).map_err(|error| fail(error.message))?;
+            }
+            ("variables", _, Value::Text(ref literal)) => {
+                variable(&key).map_err(fail)?;
+                let value = matrix(evaluator, literal).map_err(|error| fail(format!("Invalid matrix \'{}\'.", key)).with_cause(error))?;
+                evaluator.declare(&key, value).map_err(|error| fail(error.message))?;
+            }
+            ("constants", _, Value::Number(value)) => {
+                variable(&key).map_err(fail)?;
+                if evaluator.is_constant(&key) {
+                    return Err(fail(format!("Can not reassign constant \'{}\'.", key)));
+                }
+                evaluator.constant(&key, value::Value::Number(value));
+            }
+            ("variables", _, _) => return Err(fail(format!("\'{}\' must be a number or a matrix in quotes.", key))),
+            ("constants", _, _) => return Err(fail(format!("\'{}\' must be a number.", key))),
+            ("functions", _, Value::Text(ref definition)) => {
+                sources = true;
+                define(evaluator, &key, definition, settings.radix.unwrap_or(10)).map_err(|error| fail(format!("Invalid definition of \'{}\'.", key)).with_cause(error))?;
+            }
+            ("functions", _, _) => return Err(fail(format!("\'{}\' must be a definition in quotes.", key))),
+            ("bindings", _, Value::Text(ref definition)) => {
+                sources = true;
+                bind(evaluator, &key, definition, settings.radix.unwrap_or(10)).map_err(|error| fail(format!("Invalid binding of \'{}\'.", key)).with_cause(error))?;
+            }
+            ("bindings", _, _) => return Err(fail(format!("\'{}\' must be a binding in quotes.", key))),
+            ("settings", _, _) => return Err(fail(format!("Unknown setting \'{}\'.", key))),
+            _ => return Err(fail(format!("Unknown section \'{}\'.", section))),
+        }
+    }
+    if version.is_none() {
+        return Err(Error::new(ErrorCode::FormatError, "Missing the version.".to_string()));
+    }
+    Ok(settings)
+}
+
+fn define(evaluator: &mut Evaluator, name: &str, definition: &str, radix: u32) -> Result<(), Error> {
+    let mut statements = parse(definition, radix)?;
+    let tokens = match statements.pop() {
+        Some(tokens) if statements.is_empty() => tokens,
+        _ => return Err(Error::new(ErrorCode::FormatError, format!("\'{}\' must be a single definition.", name))),
+    };
+    match tokens.first() {
+        Some(Token::Definition(defined, _)) if defined == name => {}
+        _ => return Err(Error::new(ErrorCode::FormatError, format!("\'{}\' does not define \'{}\'.", definition, name))),
+    }
+    evaluator.evaluate(tokens)?;
+    Ok(())
+}
+
+fn bind(evaluator: &mut Evaluator, name: &str, definition: &str, radix: u32) -> Result<(), Error> {
+    let mut statements = parse(definition, radix)?;
+    let tokens = match statements.pop() {
+        Some(tokens) if statements.is_empty() => tokens,
+        _ => return Err(Error::new(ErrorCode::FormatError, format!("\'{}\' must be a single binding.", name))),
+    };
+    match tokens.first() {
+        Some(Token::Binding(bound)) if bound == name => {}
+        _ => return Err(Error::new(ErrorCode::FormatError, format!("\'{}\' does not bind \'{}\'.", definition, name))),
+    }
+    evaluator.evaluate(tokens)?;
+    Ok(())
+}
+
+// A matrix literal such as `[1, 2; 3, 4]`, which may hold nothing but numbers.
+fn matrix(evaluator: &mut Evaluator, literal: &str) -> Result<value::Value, Error> {
+    let mut statements = parse_script(literal).map_err(|mut errors| errors.remove(0))?;
+    let tokens = match statements.pop() {
+        Some(tokens) if statements.is_empty() => tokens,
+        _ => return Err(Error::new(ErrorCode::FormatError, format!("\'{}\' must be a single matrix.", literal))),
+    };
+    // Negative numbers are parsed as a product with -1.
+    let numbers = tokens.iter().all(|token| matches!(token, Token::Number(_) | Token::Array(_) | &token::MULTIPLICATION_TOKEN));
+    if !numbers || !matches!(tokens.iter().last(), Some(Token::Array(_))) {
+        return Err(Error::new(ErrorCode::FormatError, format!("\'{}\' is not a matrix of numbers.", literal)));
+    }
+    evaluator.evaluate(tokens)
+}
+
+// A name such as `$rate`, or `$finance::rate` once imported.
+fn variable(name: &str) -> Result<(), String> {
+    let valid = name.strip_prefix('$').is_some_and(|name| name.split("::").all(module::is_name));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("\'{}\' is not a variable name.", name))
+    }
+}
+
+// Splits `key = value`, where the key may be bare or quoted.
+fn entry(line: &str) -> Result<(String, Value), String> {
+    let (key, rest) = if line.starts_with('"') {
+        let (key, length) = unquote(line)?;
+        (key, &line[length..])
+    } else {
+        match line.find('=') {
+            Some(index) => (line[..index].trim().to_string(), &line[index..]),
+            None => return Err("Expected \'key = value\'.".to_string()),
+        }
+    };
+    let rest = rest.trim_start();
+    if key.is_empty() || !rest.starts_with('=') {
+        return Err("Expected \'key = value\'.".to_string());
+    }
+    let rest = rest[1..].trim();
+    let value = if rest.starts_with('"') {
+        let (text, length) = unquote(rest)?;
+        if !rest[length..].trim().is_empty() && !rest[length..].trim().starts_with('#') {
+            return Err("Unexpected text after the closing quote.".to_string());
+        }
+        Value::Text(text)
+    } else {
+        let rest = match rest.find('#') {
+            Some(index) => rest[..index].trim(),
+            None => rest,
+        };
+        match rest {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            "nan" | "+nan" | "-nan" => Value::Number(f64::NAN),
+            "inf" | "+inf" => Value::Number(f64::INFINITY),
+            "-inf" => Value::Number(f64::NEG_INFINITY),
+            _ => match rest.replace('_', "").parse::<f64>() {
+                Ok(number) if number.is_finite() => Value::Number(number),
+                _ => return Err(format!("Invalid value \'{}\'.", rest)),
+            },
+        }
+    };
+    Ok((key, value))
+}
+
+fn quote(text: &str) -> String {
+    let mut output = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' => output += "\\\"",
+            '\\' => output += "\\\\",
+            '\n' => output += "\\n",
+            '\t' => output += "\\t",
+            _ => output.push(character),
+        }
+    }
+    output.push('"');
+    output
+}
+
+// Reads a quoted string at the start of the input and returns it with the number of bytes it took.
+fn unquote(input: &str) -> Result<(String, usize), String> {
+    let mut output = String::new();
+    let mut characters = input.char_indices().skip(1);
+    while let Some((index, character)) = characters.next() {
+        match character {
+            '"' => return Ok((output, index + 1)),
+            '\\' => match characters.next() {
+                Some((_, '"')) => output.push('"'),
+                Some((_, '\\')) => output.push('\\'),
+                Some((_, 'n')) => output.push('\n'),
+                Some((_, 't')) => output.push('\t'),
+                _ => return Err("Invalid escape sequence.".to_string()),
+            },
+            _ => output.push(character),
+        }
+    }
+    Err("Missing the closing quote.".to_string())
+}
+
+// Numbers as they are, and matrices as their literal in quotes.
+fn written(value: &value::Value) -> String {
+    match value {
+        value::Value::Number(number) => self::number(*number),
+        value::Value::Matrix(matrix) => quote(&matrix.to_string()),
+    }
+}
+
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0f64 { "inf" } else { "-inf" }.to_string()
+    } else if value.fract() == 0f64 && value.abs() < 1e15 {
+        format!("{}", value)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::{eval, Value};
+
+    fn reload(evaluator: &Evaluator) -> Evaluator {
+        let mut loaded = Evaluator::new();
+        load(&mut loaded, &save(evaluator).unwrap()).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn keeps_user_functions() {
+        let mut evaluator = Evaluator::new();
+        eval("area($w, $h) = $w * $h; double($x) = 2 * area($x, 1)", &mut evaluator).unwrap();
+        let mut loaded = reload(&evaluator);
+        assert_eq!(eval("double(area(2, 3))", &mut loaded).unwrap(), Value::Number(12f64));
+    }
+
+    #[test]
+    fn keeps_imported_names() {
+        let directory = std::env::temp_dir().join(format!("oscar-{}-saved-import", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = directory.join("finance.calc");
+        std::fs::write(&file, "$rate = 0.05\ngrow($x) = $x * (1 + $rate)\n").unwrap();
+        let mut evaluator = Evaluator::new();
+        eval(&format!("import {:?}", file.display().to_string()), &mut evaluator).unwrap();
+        let mut loaded = reload(&evaluator);
+        assert_eq!(eval("$finance::rate", &mut loaded).unwrap(), Value::Number(0.05));
+        assert_eq!(eval("finance::grow(100)", &mut loaded).unwrap(), Value::Number(105f64));
+    }
+
+    #[test]
+    fn keeps_the_angle_mode() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_angle(Angle::Degrees);
+        let mut loaded = reload(&evaluator);
+        assert!(matches!(loaded.angle(), Angle::Degrees));
+        assert_eq!(eval("sin(90)", &mut loaded).unwrap(), Value::Number(1f64));
+    }
+
+    #[test]
+    fn reads_functions_in_the_saved_radix() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_radix(16);
+        // `f` would be a digit.
+        eval("g($x) = $x + 10", &mut evaluator).unwrap();
+        let mut loaded = reload(&evaluator);
+        assert_eq!(loaded.radix(), 16);
+        assert_eq!(eval("g(0)", &mut loaded).unwrap(), Value::Number(16f64));
+    }
+
+    #[test]
+    fn refuses_what_the_radix_can_not_write() {
+        let mut evaluator = Evaluator::new();
+        eval("f($x) = $x + 0.5", &mut evaluator).unwrap();
+        evaluator.set_radix(16);
+        assert_eq!(save(&evaluator).unwrap_err().code, ErrorCode::FormatError);
+    }
+
+    #[test]
+    fn loads_nothing_from_a_bad_file() {
+        let mut evaluator = Evaluator::new();
+        let input = "version = 1\n\n[variables]\n\"$a\" = 1\n\n[functions]\nf = \"f($x) = $x +\"\n";
+        assert!(load(&mut evaluator, input).is_err());
+        assert_eq!(eval("$a", &mut evaluator).unwrap_err().code, ErrorCode::UndefinedVariable);
+    }
+}
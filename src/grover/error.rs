@@ -1,19 +1,219 @@
+use crate::grover::token::Position;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+// The numeric values are stable and may be relied on by callers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ErrorCode {
-    MalformedExpression,
-    LexerError,
-    ParserError,
-    EvaluatorError,
-    ArithmeticError,
-    ReassignConstant,
+    // Lexing and parsing
+    LexerError = 100,
+    MalformedExpression = 101,
+    ParserError = 102,
+    // Evaluation
+    EvaluatorError = 200,
+    UndefinedVariable = 201,
+    UndefinedFunction = 202,
+    ArityMismatch = 203,
+    ReassignConstant = 204,
+    CyclicBinding = 205,
+    IterationLimit = 206,
+    // Arithmetic
+    ArithmeticError = 300,
+    DivisionByZero = 301,
+    DomainError = 302,
+    Overflow = 303,
+    NoConvergence = 305,
+    ShapeMismatch = 306,
+    IndexOutOfRange = 307,
+    // Files and environments
+    IoError = 400,
+    FormatError = 401,
+    ModuleNotFound = 402,
+    ImportCycle = 403,
+}
+
+impl ErrorCode {
+    pub fn number(&self) -> u16 {
+        *self as u16
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "E{}", self.number())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Expected {
+    Number,
+    Identifier,
+    Function,
+    UnaryOperator,
+    LeftParenthesis,
+    LeftBrace,
+    LeftBracket,
+    ArithmeticOperator,
+    AssignmentOperator,
+    RightParenthesis,
+    RightBrace,
+    RightBracket,
+    Arrow,
+    Comma,
+    Separator,
+    End,
 }
 
+impl Expected {
+    pub fn description(&self) -> Option<&'static str> {
+        match self {
+            Expected::Number => Some("a number"),
+            Expected::Identifier => Some("variable"),
+            Expected::Function => Some("function"),
+            // Implied by the operands it may precede.
+            Expected::UnaryOperator => None,
+            Expected::LeftParenthesis => Some("'('"),
+            // Blocks are rare enough that listing them would only add noise.
+            Expected::LeftBrace => None,
+            // Matrices too, outside of them.
+            Expected::LeftBracket => None,
+            Expected::ArithmeticOperator => Some("an operator"),
+            Expected::AssignmentOperator => Some("an assignment"),
+            Expected::RightParenthesis => Some("')'"),
+            Expected::RightBrace => Some("'}'"),
+            Expected::RightBracket => Some("']'"),
+            Expected::Arrow => Some("'->'"),
+            Expected::Comma => Some("','"),
+            Expected::Separator => Some("';'"),
+            Expected::End => Some("the end of the expression"),
+        }
+    }
+    // Joins the descriptions into a list such as "a number, variable or '('".
+    pub fn describe(expected: &BTreeSet<Expected>) -> String {
+        let descriptions: Vec<&str> = expected.iter().filter_map(|expected| expected.description()).collect();
+        match descriptions.split_last() {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => "nothing".to_string(),
+        }
+    }
+}
+
+// A file an error was found in, with its text as it was read, for showing the error in context.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub name: Box<str>,
+    pub text: Arc<str>,
+}
+
+#[derive(Clone, PartialEq)]
 pub struct Error {
     pub code: ErrorCode,
     pub message: String,
+    pub position: Option<Position>,
+    // The file the position refers to, when the source was not the command line.
+    pub file: Option<Box<Source>>,
+    pub expected: BTreeSet<Expected>,
+    // Known names close to an undefined one.
+    pub suggestions: Vec<String>,
+    pub cause: Option<Box<Error>>,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: String) -> Self {
+        Error {
+            code,
+            message,
+            position: None,
+            file: None,
+            expected: BTreeSet::new(),
+            suggestions: Vec::new(),
+            cause: None,
+        }
+    }
+    pub fn at(code: ErrorCode, message: String, position: Position) -> Self {
+        Error {
+            code,
+            message,
+            position: Some(position),
+            file: None,
+            expected: BTreeSet::new(),
+            suggestions: Vec::new(),
+            cause: None,
+        }
+    }
+    pub fn with_cause(mut self, cause: Error) -> Self {
+        self.cause = Some(Box::new(cause));
+        self
+    }
+    pub fn in_file(mut self, file: &str, text: Arc<str>) -> Self {
+        self.file = Some(Box::new(Source { name: file.into(), text }));
+        self
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.file, self.position) {
+            (Some(file), Some(position)) => write!(f, "{}:{}: {}", file.name, position, self.message),
+            (Some(file), None) => write!(f, "{}: {}", file.name, self.message),
+            (None, Some(position)) => write!(f, "{}: {}", position, self.message),
+            (None, None) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.cause {
+            Some(ref cause) => Some(cause.as_ref()),
+            None => None,
+        }
+    }
 }
 
 impl std::fmt::Debug for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "[{}] {}", self.code, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::{eval, Evaluator};
+
+    #[test]
+    fn displays_position_and_code() {
+        let error = Error::at(ErrorCode::LexerError, "Invalid character '@'.".to_string(), Position { line: 2, column: 5 });
+        assert_eq!(error.to_string(), "2:5: Invalid character '@'.");
+        assert_eq!(format!("{:?}", error), "[E100] 2:5: Invalid character '@'.");
+        assert_eq!(ErrorCode::DivisionByZero.number(), 301);
+        assert_eq!(ErrorCode::ImportCycle.to_string(), "E403");
+    }
+
+    #[test]
+    fn chains_causes() {
+        let error = eval("integrate(1 / ($x - 1), $x, 0, 2)", &mut Evaluator::new()).unwrap_err();
+        let cause = std::error::Error::source(&error).expect("The failure of the integrand is kept.");
+        assert_eq!(error.message, "Could not evaluate 'integrate'.");
+        assert_ne!(cause.to_string(), error.to_string());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn converts_into_boxed_errors() {
+        fn run() -> Result<crate::grover::Value, Box<dyn std::error::Error>> {
+            Ok(eval("1 / 0", &mut Evaluator::new())?)
+        }
+        let error = run().unwrap_err();
+        assert_eq!(error.downcast_ref::<Error>().map(|error| error.code), Some(ErrorCode::DivisionByZero));
+    }
+
+    #[test]
+    fn tells_arithmetic_errors_apart() {
+        let mut evaluator = Evaluator::new();
+        for (input, code) in [("5 % 0", ErrorCode::DivisionByZero), ("10 ^ 400", ErrorCode::Overflow), ("10 ^ 300 / 10 ^ -300", ErrorCode::Overflow), ("sqrt(-1)", ErrorCode::DomainError)] {
+            assert_eq!(eval(input, &mut evaluator).unwrap_err().code, code, "{}", input);
+        }
+    }
+}
@@ -1,56 +1,514 @@
-use crate::grover::{error, parser, token};
+use crate::grover::{calculus, error, function, list, matrix, module, suggest, token, trace, value};
 use error::Error;
 use error::ErrorCode;
-use parser::Parser;
+use function::Angles;
+use function::Definition;
+use function::Function;
+use matrix::Matrix;
+use module::Modules;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::rc::Rc;
 use token::Token;
 use token::Tokens;
+use trace::Step;
+use value::finite;
+use value::Value;
+
+const BUILTIN_CONSTANTS: [&str; 3] = ["$pi", "$e", "$tau"];
+
+// Nested user function calls allowed before giving up.
+const MAX_DEPTH: usize = 256;
+
+// Terms a sum or product over an index may have, so a mistyped bound does not run on for hours.
+const MAX_ITERATIONS: usize = 1_000_000;
+const MAX_EXACT: f64 = 9007199254740992f64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Angle {
+    Radians,
+    Degrees,
+}
+
+// When bindings are recomputed after something they depend on changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recompute {
+    // Right away, so every value is always current.
+    Eager,
+    // The next time they are read.
+    Lazy,
+}
+
+// Reported to listeners as bindings follow their dependencies.
+#[derive(Debug, PartialEq)]
+pub enum Update<'a> {
+    Changed(&'a str, Value),
+    // Out of date until read again, in lazy mode.
+    Stale(&'a str),
+}
+
+type Listener = Box<dyn FnMut(&Update)>;
+
+// An update held back until the statement or transaction that caused it is kept.
+enum Pending {
+    Changed(String, Value),
+    Stale(String),
+}
+
+// A variable defined by a formula, as in `$area := $w * $h`.
+#[derive(Clone)]
+pub struct Binding {
+    pub formula: Tokens,
+    pub dependencies: BTreeSet<String>,
+}
+
+// Local bindings of a block or function call, which vanish when it ends.
+struct Scope {
+    variables: HashMap<String, Value>,
+    // Height of the value stack when the scope was entered.
+    base: usize,
+    // Function scopes hide the scopes of their caller.
+    function: bool,
+}
+
+// Previous state of something the evaluator changed, for rolling back.
+enum Change {
+    Variable(String, Option<Value>),
+    Constant(String, bool),
+    Function(String, Option<Function>),
+    Binding(String, Option<Binding>),
+    // Whether the binding was out of date.
+    Dirty(String, bool),
+}
 
 pub struct Evaluator {
-    variables: HashMap<String, f64>,
+    variables: HashMap<String, Value>,
     constants: HashSet<String>,
+    functions: HashMap<String, Function>,
     values: Tokens,
+    // Treat undefined variables as zero instead of failing.
+    lenient: bool,
+    journal: Vec<Change>,
+    // Journal and pending update lengths at each open transaction.
+    transactions: Vec<(usize, usize)>,
+    radix: u32,
+    angle: Angle,
+    depth: usize,
+    modules: Modules,
+    scopes: Vec<Scope>,
+    // Read-only environment shared with other evaluators.
+    parent: Option<Rc<Evaluator>>,
+    bindings: HashMap<String, Binding>,
+    // Bindings whose value is out of date.
+    dirty: HashSet<String>,
+    recompute: Recompute,
+    listeners: Vec<Listener>,
+    // Updates not yet sent to the listeners, as they may still be rolled back.
+    pending: Vec<Pending>,
+    // Whether a statement is being evaluated.
+    evaluating: bool,
+    // Steps taken so far, while tracing.
+    trace: Option<Vec<Step>>,
+    // The error bound of the last integral or numerical derivative.
+    estimate: Option<f64>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Evaluator {
     pub fn new() -> Self {
-        let evaluator = Evaluator {
-            variables: HashMap::<String, f64>::new(),
+        let mut evaluator = Evaluator {
+            variables: HashMap::<String, Value>::new(),
             constants: HashSet::<String>::new(),
+            functions: HashMap::<String, Function>::new(),
             values: Tokens::new(),
+            lenient: false,
+            journal: Vec::<Change>::new(),
+            transactions: Vec::<(usize, usize)>::new(),
+            radix: 10,
+            angle: Angle::Radians,
+            depth: 0,
+            modules: Modules::new(),
+            scopes: Vec::<Scope>::new(),
+            parent: None,
+            bindings: HashMap::<String, Binding>::new(),
+            dirty: HashSet::<String>::new(),
+            recompute: Recompute::Eager,
+            listeners: Vec::new(),
+            pending: Vec::<Pending>::new(),
+            evaluating: false,
+            trace: None,
+            estimate: None,
         };
 
+        evaluator.constant(BUILTIN_CONSTANTS[0], Value::Number(std::f64::consts::PI));
+        evaluator.constant(BUILTIN_CONSTANTS[1], Value::Number(std::f64::consts::E));
+        evaluator.constant(BUILTIN_CONSTANTS[2], Value::Number(std::f64::consts::TAU));
+
+        for (name, function) in function::builtins() {
+            evaluator.register(name, function);
+        }
+
+        evaluator.journal.clear();
+
         evaluator
     }
+    // An evaluator that reads the parent's variables, constants and functions but only ever writes its own.
+    // Assigning to a parent variable shadows it; parent constants can not be shadowed.
+    pub fn child(parent: Rc<Evaluator>) -> Self {
+        Evaluator {
+            variables: HashMap::<String, Value>::new(),
+            constants: HashSet::<String>::new(),
+            functions: HashMap::<String, Function>::new(),
+            values: Tokens::new(),
+            lenient: parent.lenient,
+            journal: Vec::<Change>::new(),
+            transactions: Vec::<(usize, usize)>::new(),
+            radix: parent.radix,
+            angle: parent.angle,
+            depth: 0,
+            modules: parent.modules.clone(),
+            scopes: Vec::<Scope>::new(),
+            bindings: HashMap::<String, Binding>::new(),
+            dirty: HashSet::<String>::new(),
+            recompute: parent.recompute,
+            listeners: Vec::new(),
+            pending: Vec::<Pending>::new(),
+            evaluating: false,
+            trace: None,
+            estimate: None,
+            parent: Some(parent),
+        }
+    }
+    pub fn parent(&self) -> Option<&Rc<Evaluator>> {
+        self.parent.as_ref()
+    }
+    pub fn constant(&mut self, name: &str, value: Value) {
+        self.set_variable(name, value);
+        let existed = !self.constants.insert(name.to_string());
+        self.journal.push(Change::Constant(name.to_string(), existed));
+    }
+    fn set_variable(&mut self, name: &str, value: Value) {
+        let previous = self.variables.insert(name.to_string(), value);
+        self.journal.push(Change::Variable(name.to_string(), previous));
+    }
+    // Undoes every change made since the journal was mark entries long.
+    fn undo(&mut self, mark: usize) {
+        while self.journal.len() > mark {
+            match self.journal.pop() {
+                Some(Change::Variable(name, Some(value))) => {
+                    self.variables.insert(name, value);
+                }
+                Some(Change::Variable(name, None)) => {
+                    self.variables.remove(&name);
+                }
+                Some(Change::Constant(name, existed)) => {
+                    if !existed {
+                        self.constants.remove(&name);
+                    }
+                }
+                Some(Change::Function(name, Some(function))) => {
+                    self.functions.insert(name, function);
+                }
+                Some(Change::Function(name, None)) => {
+                    self.functions.remove(&name);
+                }
+                Some(Change::Binding(name, Some(binding))) => {
+                    self.bindings.insert(name, binding);
+                }
+                Some(Change::Binding(name, None)) => {
+                    self.bindings.remove(&name);
+                }
+                Some(Change::Dirty(name, true)) => {
+                    self.dirty.insert(name);
+                }
+                Some(Change::Dirty(name, false)) => {
+                    self.dirty.remove(&name);
+                }
+                None => break,
+            }
+        }
+    }
+    // Forgets the journal when nothing could roll back to it, and sends the updates that can no longer be undone.
+    fn settle(&mut self) {
+        if self.transactions.is_empty() {
+            self.journal.clear();
+            for pending in std::mem::take(&mut self.pending) {
+                let update = match pending {
+                    Pending::Changed(ref name, ref value) => Update::Changed(name, value.clone()),
+                    Pending::Stale(ref name) => Update::Stale(name),
+                };
+                for listener in self.listeners.iter_mut() {
+                    listener(&update);
+                }
+            }
+        }
+    }
+    // Marks a binding as out of date or not, remembering how it was for rolling back.
+    fn set_dirty(&mut self, name: &str, dirty: bool) {
+        let was = if dirty { !self.dirty.insert(name.to_string()) } else { self.dirty.remove(name) };
+        self.journal.push(Change::Dirty(name.to_string(), was));
+    }
+    pub fn begin(&mut self) {
+        self.transactions.push((self.journal.len(), self.pending.len()));
+    }
+    pub fn commit(&mut self) -> Result<(), Error> {
+        if self.transactions.pop().is_none() {
+            return Err(Error::new(ErrorCode::EvaluatorError, "No transaction to commit.".to_string()));
+        }
+        self.settle();
+        Ok(())
+    }
+    pub fn rollback(&mut self) -> Result<(), Error> {
+        let (mark, pending) = match self.transactions.pop() {
+            Some(marks) => marks,
+            None => return Err(Error::new(ErrorCode::EvaluatorError, "No transaction to roll back.".to_string())),
+        };
+        self.undo(mark);
+        self.pending.truncate(pending);
+        self.settle();
+        Ok(())
+    }
+    pub fn in_transaction(&self) -> bool {
+        !self.transactions.is_empty()
+    }
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+    pub fn set_radix(&mut self, radix: u32) {
+        self.radix = radix;
+    }
+    pub fn radix(&self) -> u32 {
+        self.radix
+    }
+    pub fn set_angle(&mut self, angle: Angle) {
+        self.angle = angle;
+    }
+    pub fn angle(&self) -> Angle {
+        self.angle
+    }
+    pub fn set_recompute(&mut self, recompute: Recompute) {
+        self.recompute = recompute;
+    }
+    pub fn recompute(&self) -> Recompute {
+        self.recompute
+    }
+    // Calls the listener whenever a binding is recomputed or goes stale, once the statement or transaction that
+    // caused it has succeeded.
+    pub fn subscribe<F: FnMut(&Update) + 'static>(&mut self, listener: F) {
+        self.listeners.push(Box::new(listener));
+    }
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.trace = if tracing { Some(self.trace.take().unwrap_or_default()) } else { None };
+    }
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+    pub fn estimate(&self) -> Option<f64> {
+        self.estimate
+    }
+    // The steps recorded since the last call, leaving tracing on if it was.
+    pub fn take_trace(&mut self) -> Vec<Step> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+    fn step(&mut self, step: Step) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(step);
+        }
+    }
+    pub fn bindings(&self) -> Vec<(&str, &Binding)> {
+        let mut bindings: Vec<(&str, &Binding)> = self.bindings.iter().map(|(name, binding)| (name.as_str(), binding)).collect();
+        bindings.sort_by(|a, b| a.0.cmp(b.0));
+        bindings
+    }
+    pub fn is_bound(&self, name: &str) -> bool {
+        self.bindings.contains_key(name)
+    }
+    pub fn modules(&self) -> &Modules {
+        &self.modules
+    }
+    pub fn modules_mut(&mut self) -> &mut Modules {
+        &mut self.modules
+    }
+    // Variables and constants, excluding the built-in constants.
+    pub fn variables(&self) -> Vec<(&str, &Value, bool)> {
+        let mut variables: Vec<(&str, &Value, bool)> = self.variables.iter()
+            .filter(|(name, _)| !BUILTIN_CONSTANTS.contains(&name.as_str()))
+            .map(|(name, value)| (name.as_str(), value, self.constants.contains(name)))
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+    // Functions defined by the user rather than built in.
+    pub fn functions(&self) -> Vec<(&str, &Function)> {
+        let mut functions: Vec<(&str, &Function)> = self.functions.iter()
+            .filter(|(_, function)| function.is_user())
+            .map(|(name, function)| (name.as_str(), function))
+            .collect();
+        functions.sort_by(|a, b| a.0.cmp(b.0));
+        functions
+    }
+    // Assigns to the nearest visible binding. New names are local to the innermost scope, if any.
+    pub fn declare(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        if self.is_constant(&name.to_string()) {
+            return Err(Error::new(ErrorCode::ReassignConstant, format!("Can not reassign constant \'{}\'.", name)));
+        }
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(variable) = scope.variables.get_mut(name) {
+                *variable = value;
+                return Ok(());
+            }
+            if scope.function {
+                break;
+            }
+        }
+        if !self.variables.contains_key(name) {
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.variables.insert(name.to_string(), value);
+                return Ok(());
+            }
+        }
+        // A plain assignment replaces a formula with a snapshot.
+        if let Some(binding) = self.bindings.remove(name) {
+            self.set_dirty(name, false);
+            self.journal.push(Change::Binding(name.to_string(), Some(binding)));
+        }
+        self.set_variable(name, value);
+        let result = self.changed(name);
+        // Outside of a statement nothing else would send the updates this caused.
+        if !self.evaluating {
+            self.settle();
+        }
+        result
+    }
+    // Binds a name in the innermost scope, shadowing any outer binding.
+    pub fn bind(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        if self.is_constant(&name.to_string()) {
+            return Err(Error::new(ErrorCode::ReassignConstant, format!("Can not shadow constant \'{}\'.", name)));
+        }
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.variables.insert(name.to_string(), value);
+            }
+            None => self.set_variable(name, value),
+        }
+        Ok(())
+    }
+    fn local(&self, name: &str) -> Option<Value> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(value) = scope.variables.get(name) {
+                return Some(value.clone());
+            }
+            if scope.function {
+                break;
+            }
+        }
+        None
+    }
+    pub fn variable(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.local(name) {
+            return Some(value);
+        }
+        match self.variables.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref().and_then(|parent| parent.variable(name)),
+        }
+    }
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        match self.functions.get(name) {
+            Some(function) => Some(function),
+            None => self.parent.as_ref().and_then(|parent| parent.function(name)),
+        }
+    }
+    pub fn read(&mut self, name: &str) -> Result<Value, Error> {
+        if self.dirty.contains(name) && self.local(name).is_none() {
+            let value = self.refresh(name)?;
+            self.step(Step::Read(name.to_string(), value.clone()));
+            return Ok(value);
+        }
+        if let Some(value) = self.variable(name) {
+            self.step(Step::Read(name.to_string(), value.clone()));
+            return Ok(value);
+        }
+        if self.lenient {
+            self.set_variable(name, Value::Number(0f64));
+            self.step(Step::Read(name.to_string(), Value::Number(0f64)));
+            return Ok(Value::Number(0f64));
+        }
+        Err(self.undefined_variable(name))
+    }
+    fn assign(&mut self, name: String, value: Value) -> Result<(), Error> {
+        // Recorded first, so recomputed bindings follow the write that caused them.
+        self.step(Step::Write(name.clone(), value.clone()));
+        self.declare(&name, value.clone())?;
+        self.push_value(value.into_token());
+        Ok(())
+    }
+    pub fn register(&mut self, name: &str, function: Function) {
+        let previous = self.functions.insert(name.to_string(), function);
+        self.journal.push(Change::Function(name.to_string(), previous));
+    }
+    pub fn undefined_variable(&self, name: &str) -> Error {
+        let mut error = Error::new(ErrorCode::UndefinedVariable, format!("Undefined variable \'{}\'.", name));
+        let mut names: Vec<&str> = self.scopes.iter().flat_map(|scope| scope.variables.keys()).map(|name| name.as_str()).collect();
+        let mut evaluator = Some(self);
+        while let Some(current) = evaluator {
+            names.extend(current.variables.keys().map(|name| name.as_str()));
+            evaluator = current.parent.as_deref();
+        }
+        error.suggestions = suggest::suggest(name, names);
+        error
+    }
+    pub fn undefined_function(&self, name: &str) -> Error {
+        if let Some(factors) = matrix::factors(name) {
+            let calls: Vec<String> = factors.iter().map(|factor| format!("{}(A)", factor)).collect();
+            let mut error = Error::new(ErrorCode::UndefinedFunction, format!("\'{}\' gives more than one matrix, so each has its own function: {}.", name, calls.join(", ")));
+            error.suggestions = factors.iter().map(|factor| factor.to_string()).collect();
+            return error;
+        }
+        let mut error = Error::new(ErrorCode::UndefinedFunction, format!("Undefined function \'{}\'.", name));
+        let mut names = Vec::<&str>::new();
+        let mut evaluator = Some(self);
+        while let Some(current) = evaluator {
+            names.extend(current.functions.keys().map(|name| name.as_str()));
+            evaluator = current.parent.as_deref();
+        }
+        error.suggestions = suggest::suggest(name, names);
+        error
+    }
     pub fn pop_value(&mut self) -> Result<f64, Error> {
+        self.pop_operand()?.number()
+    }
+    // Pops a number or a matrix.
+    pub fn pop_operand(&mut self) -> Result<Value, Error> {
         let token = if let Some(number) = self.values.pop() {
             number
         } else {
-            return Err(Error{
-                code: ErrorCode::EvaluatorError,
-                message: format!("No tokens on stack."),
-            });
+            return Err(Error::new(ErrorCode::EvaluatorError, "No tokens on stack.".to_string()));
         };
         match token {
             Token::Identifier(ref identifier) => {
-                if let Some(number) = self.variables.get(identifier) {
-                    Ok(*number)
-                } else {
-                    Err(Error{
-                        code: ErrorCode::EvaluatorError,
-                        message: format!("Undefined variable."),
-                    })
-                }
+                self.read(identifier)
             }
             Token::Number(number) => {
-                Ok(number)
+                Ok(Value::Number(number))
+            }
+            Token::Matrix(matrix) => {
+                Ok(Value::Matrix(matrix))
+            }
+            Token::Lambda(_, _) => {
+                Err(Error::new(ErrorCode::EvaluatorError, "A lambda such as $x -> $x ^ 2 can only be passed to a function like map.".to_string()))
             }
             _ => {
-                Err(Error{
-                    code: ErrorCode::EvaluatorError,
-                    message: format!("Found non-value on value stack."),
-                })
+                Err(Error::new(ErrorCode::EvaluatorError, "Found non-value on value stack.".to_string()))
             }
         }
     }
@@ -58,215 +516,762 @@ impl Evaluator {
         let token = if let Some(number) = self.values.pop() {
             number
         } else {
-            return Err(Error{
-                code: ErrorCode::EvaluatorError,
-                message: format!("No tokens on stack."),
-            });
+            return Err(Error::new(ErrorCode::EvaluatorError, "No tokens on stack.".to_string()));
         };
         match token {
             Token::Identifier(ref identifier) => {
                 Ok(identifier.clone())
             }
             _ => {
-                Err(Error{
-                    code: ErrorCode::EvaluatorError,
-                    message: format!("Found non-ident on value stack."),
-                })
+                Err(Error::new(ErrorCode::EvaluatorError, "Found non-ident on value stack.".to_string()))
             }
         }
     }
     pub fn push_value(&mut self, value: Token) {
         self.values.push(value);
     }
+    fn operate(&mut self, operator: &'static token::Operator) -> Result<(), Error> {
+        let rhs = self.pop_operand()?;
+        let lhs = self.pop_operand()?;
+        let result = value::operate(operator, &lhs, &rhs)?;
+        self.step(Step::Operate(operator.symbol, lhs, rhs, result.clone()));
+        self.push_value(result.into_token());
+        Ok(())
+    }
+    // Applies the operator of a compound assignment such as `+=` to the variable.
+    fn update(&mut self, operator: &token::Operator) -> Result<(), Error> {
+        let rhs = self.pop_operand()?;
+        let lhs = self.pop_ident()?;
+        let value = value::operate(operator, &self.read(&lhs)?, &rhs)?;
+        self.assign(lhs, value)
+    }
     pub fn is_constant(&self, ident: &String) -> bool {
-        self.constants.contains(ident)
+        self.constants.contains(ident) || self.parent.as_ref().is_some_and(|parent| parent.is_constant(ident))
     }
-    pub fn evaluate(&mut self, tokens: Tokens) -> Result<f64, Error> {
-        let ans = 0f64;
-
+    // Evaluates a statement atomically: on error every change it made is undone.
+    pub fn evaluate(&mut self, tokens: Tokens) -> Result<Value, Error> {
+        let mark = self.journal.len();
+        let pending = self.pending.len();
+        self.evaluating = true;
+        let result = self.run(tokens);
+        self.evaluating = false;
+        self.values.clear();
+        self.scopes.clear();
+        if result.is_err() {
+            self.undo(mark);
+            self.pending.truncate(pending);
+        }
+        self.settle();
+        result
+    }
+    fn bind_formula(&mut self, tokens: Tokens) -> Result<Value, Error> {
+        let mut tokens = tokens.into_iter();
+        let name = match tokens.next() {
+            Some(Token::Binding(name)) => name,
+            _ => return Err(Error::new(ErrorCode::EvaluatorError, "Expected a binding.".to_string())),
+        };
+        if self.is_constant(&name) {
+            return Err(Error::new(ErrorCode::ReassignConstant, format!("Can not reassign constant \'{}\'.", name)));
+        }
+        let formula: Tokens = tokens.collect();
+        let dependencies = formula.variables();
+        if let Some(cycle) = self.cycle(&name, &dependencies) {
+            return Err(Error::new(ErrorCode::CyclicBinding, format!("\'{}\' would depend on itself: {}.", name, cycle.join(" -> "))));
+        }
+        let previous = self.bindings.insert(name.clone(), Binding { formula, dependencies });
+        self.journal.push(Change::Binding(name.clone(), previous));
+        let value = self.refresh(&name)?;
+        self.changed(&name)?;
+        Ok(value)
+    }
+    // Finds a path from the dependencies of a binding back to the binding itself.
+    fn cycle(&self, name: &str, dependencies: &BTreeSet<String>) -> Option<Vec<String>> {
+        let mut pending: Vec<Vec<String>> = dependencies.iter().map(|dependency| vec![name.to_string(), dependency.clone()]).collect();
+        let mut seen = HashSet::<String>::new();
+        while let Some(path) = pending.pop() {
+            let current = path.last().expect("Paths are never empty.");
+            if current == name {
+                return Some(path);
+            }
+            if !seen.insert(current.clone()) {
+                continue;
+            }
+            if let Some(binding) = self.bindings.get(current) {
+                for dependency in &binding.dependencies {
+                    let mut next = path.clone();
+                    next.push(dependency.clone());
+                    pending.push(next);
+                }
+            }
+        }
+        None
+    }
+    // Every binding that depends on the variable, directly or through other bindings.
+    fn dependents(&self, name: &str) -> Vec<String> {
+        let mut found = Vec::<String>::new();
+        let mut pending = vec![name.to_string()];
+        while let Some(current) = pending.pop() {
+            let mut direct: Vec<&String> = self
+                .bindings
+                .iter()
+                .filter(|(bound, binding)| binding.dependencies.contains(&current) && !found.contains(bound))
+                .map(|(bound, _)| bound)
+                .collect();
+            direct.sort();
+            for bound in direct {
+                found.push(bound.clone());
+                pending.push(bound.clone());
+            }
+        }
+        found
+    }
+    fn changed(&mut self, name: &str) -> Result<(), Error> {
+        let dependents = self.dependents(name);
+        for dependent in &dependents {
+            self.set_dirty(dependent, true);
+        }
+        for dependent in &dependents {
+            match self.recompute {
+                Recompute::Lazy => self.pending.push(Pending::Stale(dependent.clone())),
+                // Refreshing one binding may already have refreshed others it reads.
+                Recompute::Eager if self.dirty.contains(dependent) => {
+                    self.refresh(dependent)?;
+                }
+                Recompute::Eager => {}
+            }
+        }
+        Ok(())
+    }
+    // Evaluates a binding's formula and stores the result.
+    fn refresh(&mut self, name: &str) -> Result<Value, Error> {
+        let formula = match self.bindings.get(name) {
+            Some(binding) => binding.formula.clone(),
+            None => return Err(self.undefined_variable(name)),
+        };
+        self.set_dirty(name, false);
+        // Formulas only see global variables, whatever scope they are read from.
+        let height = self.scopes.len();
+        self.scopes.push(Scope {
+            variables: HashMap::<String, Value>::new(),
+            base: 0,
+            function: true,
+        });
+        let values = std::mem::take(&mut self.values);
+        self.step(Step::Enter(name.to_string(), Vec::new()));
+        let result = self.run(formula);
+        self.values = values;
+        self.scopes.truncate(height);
+        let value = match result {
+            Ok(value) => value,
+            Err(error) => {
+                self.set_dirty(name, true);
+                return Err(Error::new(error.code, format!("Could not recompute \'{}\'.", name)).with_cause(error));
+            }
+        };
+        self.step(Step::Leave(value.clone()));
+        self.set_variable(name, value.clone());
+        self.pending.push(Pending::Changed(name.to_string(), value.clone()));
+        Ok(value)
+    }
+    fn scope_base(&self) -> usize {
+        match self.scopes.last() {
+            Some(scope) if !scope.function => scope.base,
+            _ => 0,
+        }
+    }
+    fn call(&mut self, name: &str, function: &Function, arguments: Vec<Value>) -> Result<Value, Error> {
+        match function.definition {
+            // Variadic functions such as sum take in every element of a matrix.
+            Definition::Native(call) if function.arity.is_none() => {
+                let mut numbers = Vec::<f64>::with_capacity(arguments.len());
+                for argument in arguments {
+                    match argument {
+                        Value::Number(number) => numbers.push(number),
+                        Value::Matrix(matrix) => numbers.extend_from_slice(matrix.data()),
+                    }
+                }
+                if numbers.is_empty() {
+                    return Err(Error::new(ErrorCode::ArityMismatch, format!("'{}' takes at least one number, the matrix is empty.", name)));
+                }
+                Ok(Value::Number(self.native(name, function, call, numbers)?))
+            }
+            Definition::Native(call) => {
+                let shape = arguments.iter().find_map(|argument| match argument {
+                    Value::Matrix(matrix) => Some((matrix.rows(), matrix.columns())),
+                    Value::Number(_) => None,
+                });
+                let (rows, columns) = match shape {
+                    Some(shape) => shape,
+                    None => {
+                        let numbers = arguments.iter().map(Value::number).collect::<Result<Vec<f64>, Error>>()?;
+                        return Ok(Value::Number(self.native(name, function, call, numbers)?));
+                    }
+                };
+                // Element by element, with numbers standing in for every element.
+                let mut data = Vec::<f64>::with_capacity(rows * columns);
+                for index in 0..rows * columns {
+                    let mut numbers = Vec::<f64>::with_capacity(arguments.len());
+                    for argument in arguments.iter() {
+                        match argument {
+                            Value::Number(number) => numbers.push(*number),
+                            Value::Matrix(matrix) if matrix.rows() == rows && matrix.columns() == columns => numbers.push(matrix.data()[index]),
+                            Value::Matrix(matrix) => {
+                                return Err(Error::new(ErrorCode::ShapeMismatch, format!("'{}' was given a {} matrix and a {} by {} matrix.", name, matrix.shape(), rows, columns)));
+                            }
+                        }
+                    }
+                    data.push(self.native(name, function, call, numbers)?);
+                }
+                Ok(Value::Matrix(Matrix::new(rows, columns, data)))
+            }
+            Definition::Values(call) => call(&arguments),
+            Definition::User { ref parameters, ref body } => {
+                if self.depth >= MAX_DEPTH {
+                    return Err(Error::new(ErrorCode::EvaluatorError, format!("Too much recursion in \'{}\'.", name)));
+                }
+                self.step(Step::Enter(name.to_string(), arguments.clone()));
+                let scope = Scope {
+                    variables: parameters.iter().cloned().zip(arguments).collect(),
+                    base: 0,
+                    function: true,
+                };
+                let height = self.scopes.len();
+                self.scopes.push(scope);
+                let values = std::mem::take(&mut self.values);
+                self.depth += 1;
+                let result = self.run(body.clone());
+                self.depth -= 1;
+                self.values = values;
+                self.scopes.truncate(height);
+                if let Ok(ref value) = result {
+                    self.step(Step::Leave(value.clone()));
+                }
+                // Only the outermost call is named, so recursion does not bury the actual error.
+                if self.depth > 0 {
+                    return result;
+                }
+                result.map_err(|error| Error::new(error.code, format!("Could not evaluate \'{}\'.", name)).with_cause(error))
+            }
+        }
+    }
+    fn native(&self, name: &str, function: &Function, call: fn(&[f64]) -> f64, mut arguments: Vec<f64>) -> Result<f64, Error> {
+        if function.angles == Angles::Arguments && self.angle == Angle::Degrees {
+            for argument in arguments.iter_mut() {
+                *argument = argument.to_radians();
+            }
+        }
+        let mut result = call(&arguments);
+        if result.is_nan() {
+            let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+            return Err(Error::new(ErrorCode::DomainError, format!("{}({}) is not a real number.", name, arguments.join(", "))));
+        }
+        if function.angles == Angles::Result && self.angle == Angle::Degrees {
+            result = result.to_degrees();
+        }
+        finite(result)
+    }
+    // Evaluates a lambda, such as the expression of a form like integrate, with its parameters bound, seeing the scopes around it.
+    pub fn apply(&mut self, parameters: &[String], body: &Tokens, arguments: Vec<Value>) -> Result<Value, Error> {
+        let height = self.scopes.len();
+        self.scopes.push(Scope {
+            variables: parameters.iter().cloned().zip(arguments).collect(),
+            base: 0,
+            function: false,
+        });
+        let values = std::mem::take(&mut self.values);
+        // A form may evaluate its expression hundreds of times, which would bury the rest of a trace.
+        let trace = self.trace.take();
+        let result = self.execute(body.clone());
+        self.trace = trace;
+        self.values = values;
+        self.scopes.truncate(height);
+        result
+    }
+    // Adds up or multiplies a lambda over each whole index from one bound to the other, binding the index in a scope
+    // of its own for each term.
+    fn series(&mut self, name: &str, parameters: &[String], body: &Tokens, from: f64, to: f64) -> Result<Value, Error> {
+        let index = match parameters {
+            [index] => index,
+            _ => return Err(Error::new(ErrorCode::ArityMismatch, format!("The lambda passed to \'{}\' must take 1 parameter, the index.", name))),
+        };
+        if from.fract() != 0f64 || to.fract() != 0f64 {
+            return Err(Error::new(ErrorCode::DomainError, format!("The bounds of \'{}\' must be whole numbers, found {} and {}.", name, from, to)));
+        }
+        // Beyond 2^53 not every whole number is a float, so the index could not step by one.
+        if from.abs() > MAX_EXACT || to.abs() > MAX_EXACT {
+            return Err(Error::new(ErrorCode::DomainError, format!("The bounds of \'{}\' must be at most {} in size, found {} and {}.", name, MAX_EXACT, from, to)));
+        }
+        if to - from >= MAX_ITERATIONS as f64 {
+            return Err(Error::new(
+                ErrorCode::IterationLimit,
+                format!("\'{}\' from {} to {} would take more than {} iterations.", name, from, to, MAX_ITERATIONS),
+            ));
+        }
+        let (operator, mut total) = match name {
+            "prod" => (&token::MULTIPLICATION_OPERATOR, Value::Number(1f64)),
+            _ => (&token::ADDITION_OPERATOR, Value::Number(0f64)),
+        };
+        // Empty when the last bound is below the first.
+        let steps = if to < from { 0 } else { (to - from) as usize + 1 };
+        for step in 0..steps {
+            let value = from + step as f64;
+            total = self
+                .apply(parameters, body, vec![Value::Number(value)])
+                .and_then(|term| value::operate(operator, &total, &term))
+                .map_err(|error| Error::new(error.code, format!("Could not evaluate \'{}\' at {} = {}.", name, index, value)).with_cause(error))?;
+        }
+        Ok(total)
+    }
+    // Whether a call is to a higher-order function such as map, rather than a built-in of the same name like sum.
+    fn takes_lambda(&self, name: &str, arity: usize) -> bool {
+        list::higher_order(name).is_some()
+            && (self.function(name).is_none() || arity >= 2 && matches!(self.values.iter().rev().nth(arity - 2), Some(Token::Lambda(_, _))))
+    }
+    // Registers a user function, which may replace another user function but not a built-in one.
+    pub fn define(&mut self, name: &str, function: Function) -> Result<(), Error> {
+        if name == calculus::DIFF || name == calculus::SIMPLIFY || calculus::form(name).is_some() || list::higher_order(name).is_some() {
+            return Err(Error::new(ErrorCode::EvaluatorError, format!("Can not redefine built-in function \'{}\'.", name)));
+        }
+        if let Some(existing) = self.function(name) {
+            if !existing.is_user() {
+                return Err(Error::new(ErrorCode::EvaluatorError, format!("Can not redefine built-in function \'{}\'.", name)));
+            }
+        }
+        if let Definition::User { ref parameters, .. } = function.definition {
+            if let Some(parameter) = parameters.iter().find(|parameter| self.is_constant(parameter)) {
+                return Err(Error::new(ErrorCode::ReassignConstant, format!("Can not shadow constant \'{}\'.", parameter)));
+            }
+        }
+        self.register(name, function);
+        Ok(())
+    }
+    fn definition(&mut self, tokens: Tokens) -> Result<Value, Error> {
+        let mut tokens = tokens.into_iter();
+        let (name, parameters) = match tokens.next() {
+            Some(Token::Definition(name, parameters)) => (name, parameters),
+            _ => return Err(Error::new(ErrorCode::EvaluatorError, "Expected a definition.".to_string())),
+        };
+        self.define(&name, Function::user(parameters, tokens.collect()))?;
+        Ok(Value::Number(0f64))
+    }
+    fn run(&mut self, tokens: Tokens) -> Result<Value, Error> {
+        match tokens.first() {
+            Some(Token::Definition(_, _)) => return self.definition(tokens),
+            Some(Token::Binding(_)) => return self.bind_formula(tokens),
+            Some(Token::Import(path)) => {
+                let path = path.clone();
+                module::import(self, &path)?;
+                return Ok(Value::Number(0f64));
+            }
+            Some(Token::Use(name)) => {
+                let name = name.clone();
+                module::use_module(self, &name)?;
+                return Ok(Value::Number(0f64));
+            }
+            _ => {}
+        }
+        // Derivatives are taken, and the expressions of forms like integrate set aside, before anything is evaluated.
+        let tokens = if calculus::is_symbolic(&tokens) { calculus::prepare(self, &tokens)? } else { tokens };
+        self.execute(tokens)
+    }
+    fn execute(&mut self, tokens: Tokens) -> Result<Value, Error> {
         for token in tokens.into_iter() {
             match token {
-                Token::Number(_) => {
+                Token::Number(_) | Token::Matrix(_) => {
                     self.values.push(token);
                 }
-                Token::Identifier(ref identifier) => {
-                    if !self.variables.contains_key(identifier) {
-                        self.variables.insert(identifier.clone(), 0f64);
+                Token::Array(rows) => {
+                    let mut elements = Vec::<Matrix>::with_capacity(rows.iter().sum());
+                    for _ in 0..rows.iter().sum() {
+                        elements.push(self.pop_operand()?.matrix());
                     }
+                    elements.reverse();
+                    // Elements may themselves be matrices, which are joined into a larger one.
+                    let mut elements = elements.into_iter();
+                    let rows = rows.iter().map(|columns| Matrix::beside(&elements.by_ref().take(*columns).collect::<Vec<Matrix>>())).collect::<Result<Vec<Matrix>, Error>>()?;
+                    self.push_value(Token::Matrix(Matrix::stack(&rows)?));
+                }
+                Token::Identifier(_) => {
                     self.values.push(token);
                 }
-                Token::Operator(operator) => {
-                    match *operator {
-                        token::ADDITION_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_value()?;
-                            self.push_value(Token::Number(lhs + rhs));
-                        }
-                        token::SUBTRACTION_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_value()?;
-                            self.push_value(Token::Number(lhs - rhs));
+                Token::Lambda(_, _) => {
+                    self.values.push(token);
+                }
+                Token::Function(ref name, arity) if calculus::form(name).is_some() => {
+                    let lambdas = calculus::form(name).map_or(1, |form| form.lambdas());
+                    let mut arguments = Vec::<f64>::with_capacity(arity);
+                    for _ in lambdas..arity {
+                        arguments.push(self.pop_value()?);
+                    }
+                    arguments.reverse();
+                    let mut quoted = Vec::<(Vec<String>, Tokens)>::with_capacity(lambdas);
+                    for _ in 0..lambdas {
+                        match self.values.pop() {
+                            Some(Token::Lambda(parameters, body)) => quoted.push((parameters, body)),
+                            _ => return Err(Error::new(ErrorCode::EvaluatorError, "Found non-lambda on value stack.".to_string())),
                         }
-                        token::MULTIPLICATION_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_value()?;
-                            self.push_value(Token::Number(lhs * rhs));
+                    }
+                    quoted.reverse();
+                    let (result, error) = calculus::apply(self, name, &quoted, &arguments)?;
+                    self.estimate = error;
+                    let arguments = arguments.into_iter().map(Value::Number).collect();
+                    self.step(match error {
+                        Some(error) => Step::Estimate(name.clone(), arguments, result.clone(), error),
+                        None => Step::Apply(name.clone(), arguments, result.clone()),
+                    });
+                    self.push_value(result.into_token());
+                }
+                // A series, once its expression has been turned into a lambda, as in `sum($i -> $i ^ 2, 1, 100)`.
+                Token::Function(ref name, 3) if calculus::SERIES.contains(&name.as_str()) && matches!(self.values.iter().rev().nth(2), Some(Token::Lambda(_, _))) => {
+                    let to = self.pop_value()?;
+                    let from = self.pop_value()?;
+                    let (parameters, body) = match self.values.pop() {
+                        Some(Token::Lambda(parameters, body)) => (parameters, body),
+                        _ => return Err(Error::new(ErrorCode::EvaluatorError, "Found non-lambda on value stack.".to_string())),
+                    };
+                    let result = self.series(name, &parameters, &body, from, to)?;
+                    self.step(Step::Apply(name.clone(), vec![Value::Number(from), Value::Number(to)], result.clone()));
+                    self.push_value(result.into_token());
+                }
+                Token::Function(ref name, arity) if self.takes_lambda(name, arity) => {
+                    let function = match list::higher_order(name) {
+                        Some(function) => function,
+                        None => return Err(self.undefined_function(name)),
+                    };
+                    if !function.arities.contains(&arity) {
+                        let arities: Vec<String> = function.arities.iter().map(|arity| arity.to_string()).collect();
+                        return Err(Error::new(ErrorCode::ArityMismatch, format!("\'{}\' takes {} arguments but {} were given.", name, arities.join(" or "), arity)));
+                    }
+                    let mut rest = Vec::<Value>::with_capacity(arity - 2);
+                    for _ in 2..arity {
+                        rest.push(self.pop_operand()?);
+                    }
+                    rest.reverse();
+                    let (parameters, body) = match self.values.pop() {
+                        Some(Token::Lambda(parameters, body)) => (parameters, body),
+                        _ => {
+                            return Err(Error::new(ErrorCode::EvaluatorError, format!("The second argument of \'{}\' must be a lambda, as in {}.", name, function.example)));
                         }
-                        token::DIVISION_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_value()?;
-                            if rhs == 0f64 {
-                                return Err(Error{
-                                    code: ErrorCode::ArithmeticError,
-                                    message: format!("Division by zero is undefined.")
-                                });
-                            }
-                            self.push_value(Token::Number(lhs / rhs));
+                    };
+                    let list = self.pop_operand()?;
+                    let traced = self.is_tracing().then(|| std::iter::once(list.clone()).chain(rest.iter().cloned()).collect::<Vec<Value>>());
+                    let result = list::apply(self, function, list, &parameters, &body, rest)?;
+                    if let Some(arguments) = traced {
+                        self.step(Step::Apply(name.clone(), arguments, result.clone()));
+                    }
+                    self.push_value(result.into_token());
+                }
+                Token::Function(ref name, arity) => {
+                    let function = match self.function(name) {
+                        Some(function) => function.clone(),
+                        None => return Err(self.undefined_function(name)),
+                    };
+                    match function.arity {
+                        Some(expected) if expected != arity => {
+                            return Err(Error::new(ErrorCode::ArityMismatch, format!("\'{}\' takes {} argument{} but {} were given.", name, expected, if expected == 1 { "" } else { "s" }, arity)));
                         }
-                        token::REMAINDER_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_value()?;
-                            if rhs == 0f64 {
-                                return Err(Error{
-                                    code: ErrorCode::ArithmeticError,
-                                    message: format!("Division by zero is undefined.")
-                                });
-                            }
-                            self.push_value(Token::Number(lhs % rhs));
+                        None if arity == 0 => {
+                            return Err(Error::new(ErrorCode::ArityMismatch, format!("\'{}\' takes at least one argument.", name)));
                         }
-                        token::POWER_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_value()?;
-                            self.push_value(Token::Number(lhs.powf(rhs)));
+                        _ => {}
+                    }
+                    let mut arguments = Vec::<Value>::with_capacity(arity);
+                    for _ in 0..arity {
+                        arguments.push(self.pop_operand()?);
+                    }
+                    arguments.reverse();
+                    let traced = (self.is_tracing() && !function.is_user()).then(|| arguments.clone());
+                    let result = self.call(name, &function, arguments)?;
+                    if let Some(arguments) = traced {
+                        self.step(Step::Apply(name.clone(), arguments, result.clone()));
+                    }
+                    self.push_value(result.into_token());
+                }
+                Token::LeftBrace => {
+                    self.scopes.push(Scope {
+                        variables: HashMap::<String, Value>::new(),
+                        base: self.values.len(),
+                        function: false,
+                    });
+                }
+                Token::Separator => {
+                    // Statements before the last one in a block are only run for their effects.
+                    self.pop_operand()?;
+                    if self.values.len() != self.scope_base() {
+                        return Err(Error::new(ErrorCode::EvaluatorError, "Block statement left values on the stack.".to_string()));
+                    }
+                }
+                Token::RightBrace => {
+                    // Read the result before the scope it may refer to goes away.
+                    let value = self.pop_operand()?;
+                    if self.values.len() != self.scope_base() {
+                        return Err(Error::new(ErrorCode::EvaluatorError, "Block left values on the stack.".to_string()));
+                    }
+                    self.scopes.pop();
+                    self.push_value(value.into_token());
+                }
+                Token::Index(count) => {
+                    let mut indices = Vec::<Value>::with_capacity(count);
+                    for _ in 0..count {
+                        indices.push(self.pop_operand()?);
+                    }
+                    indices.reverse();
+                    let value = self.pop_operand()?;
+                    self.push_value(list::index(value, &indices)?.into_token());
+                }
+                Token::Cell(_) | Token::Range(_, _) => {
+                    return Err(Error::new(ErrorCode::EvaluatorError, "Cell references can only be used in a sheet.".to_string()));
+                }
+                Token::Let(ref name) => {
+                    let value = self.pop_operand()?;
+                    self.bind(name, value.clone())?;
+                    self.step(Step::Write(name.clone(), value.clone()));
+                    self.push_value(value.into_token());
+                }
+                Token::Operator(operator) => {
+                    match *operator {
+                        token::ADDITION_OPERATOR
+                        | token::SUBTRACTION_OPERATOR
+                        | token::MULTIPLICATION_OPERATOR
+                        | token::DIVISION_OPERATOR
+                        | token::REMAINDER_OPERATOR
+                        | token::POWER_OPERATOR
+                        | token::ELEMENTWISE_MULTIPLICATION_OPERATOR
+                        | token::ELEMENTWISE_DIVISION_OPERATOR
+                        | token::ELEMENTWISE_POWER_OPERATOR
+                        | token::RANGE_OPERATOR
+                        | token::LESS_OPERATOR
+                        | token::LESS_EQUAL_OPERATOR
+                        | token::GREATER_OPERATOR
+                        | token::GREATER_EQUAL_OPERATOR
+                        | token::EQUAL_OPERATOR
+                        | token::NOT_EQUAL_OPERATOR => {
+                            self.operate(operator)?;
                         }
                         token::ASSIGNMENT_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_ident()?;
-                            if self.is_constant(&lhs) {
-                                return Err(Error{
-                                    code: ErrorCode::ReassignConstant,
-                                    message: format!("Can not reassign constant.")
-                                });
+                            let rhs = self.pop_operand()?;
+                            if !matches!(self.values.iter().last(), Some(Token::Identifier(_))) {
+                                return Err(Error::new(ErrorCode::EvaluatorError, "Only a variable can be assigned to. An equation can be solved, as in solve($x ^ 2 - 2 = 0, $x).".to_string()));
                             }
-                            if !self.variables.contains_key(&lhs) {
-                                self.variables.insert(lhs, 0f64);
-                            } else {
-                                *self.variables.get_mut(&lhs).unwrap() = rhs;
-                            }
-                        }
-                        token::ADDITION_ASSIGNMENT_OPERATOR => {
-                            let rhs = self.pop_value()?;
                             let lhs = self.pop_ident()?;
-                            if self.is_constant(&lhs) {
-                                return Err(Error{
-                                    code: ErrorCode::ReassignConstant,
-                                    message: format!("Can not reassign constant.")
-                                });
-                            }
-                            if !self.variables.contains_key(&lhs) {
-                                self.variables.insert(lhs, 0f64);
-                            } else {
-                                *self.variables.get_mut(&lhs).unwrap() = rhs;
-                            }
-                        }
-                        token::SUBTRACTION_ASSIGNMENT_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_ident()?;
-                            if self.is_constant(&lhs) {
-                                return Err(Error{
-                                    code: ErrorCode::ReassignConstant,
-                                    message: format!("Can not reassign constant.")
-                                });
-                            }
-                            if !self.variables.contains_key(&lhs) {
-                                self.variables.insert(lhs, 0f64);
-                            } else {
-                                *self.variables.get_mut(&lhs).unwrap() -= rhs;
-                            }
-                        }
-                        token::MULTIPLICATION_ASSIGNMENT_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_ident()?;
-                            if self.is_constant(&lhs) {
-                                return Err(Error{
-                                    code: ErrorCode::ReassignConstant,
-                                    message: format!("Can not reassign constant.")
-                                });
-                            }
-                            if !self.variables.contains_key(&lhs) {
-                                self.variables.insert(lhs, 0f64);
-                            } else {
-                                *self.variables.get_mut(&lhs).unwrap() *= rhs;
-                            }
-                        }
-                        token::DIVISION_ASSIGNMENT_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_ident()?;
-                            if rhs == 0f64 {
-                                return Err(Error{
-                                    code: ErrorCode::ArithmeticError,
-                                    message: format!("Division by zero is undefined.")
-                                });
-                            }
-                            if self.is_constant(&lhs) {
-                                return Err(Error{
-                                    code: ErrorCode::ReassignConstant,
-                                    message: format!("Can not reassign constant.")
-                                });
-                            }
-                            if !self.variables.contains_key(&lhs) {
-                                self.variables.insert(lhs, 0f64);
-                            } else {
-                                *self.variables.get_mut(&lhs).unwrap() /= rhs;
-                            }
-                        }
-                        token::REMAINDER_ASSIGNMENT_OPERATOR => {
-                            let rhs = self.pop_value()?;
-                            let lhs = self.pop_ident()?;
-                            if rhs == 0f64 {
-                                return Err(Error{
-                                    code: ErrorCode::ArithmeticError,
-                                    message: format!("Division by zero is undefined.")
-                                });
-                            }
-                            if self.is_constant(&lhs) {
-                                return Err(Error{
-                                    code: ErrorCode::ReassignConstant,
-                                    message: format!("Can not reassign constant.")
-                                });
-                            }
-                            if !self.variables.contains_key(&lhs) {
-                                self.variables.insert(lhs, 0f64);
-                            } else {
-                                *self.variables.get_mut(&lhs).unwrap() %= rhs;
-                            }
+                            self.assign(lhs, rhs)?;
                         }
+                        token::ADDITION_ASSIGNMENT_OPERATOR => self.update(&token::ADDITION_OPERATOR)?,
+                        token::SUBTRACTION_ASSIGNMENT_OPERATOR => self.update(&token::SUBTRACTION_OPERATOR)?,
+                        token::MULTIPLICATION_ASSIGNMENT_OPERATOR => self.update(&token::MULTIPLICATION_OPERATOR)?,
+                        token::DIVISION_ASSIGNMENT_OPERATOR => self.update(&token::DIVISION_OPERATOR)?,
+                        token::REMAINDER_ASSIGNMENT_OPERATOR => self.update(&token::REMAINDER_OPERATOR)?,
                         _ => {
-                            return Err(Error{
-                                code: ErrorCode::EvaluatorError,
-                                message: format!("Unhandled operator."),
-                            });
+                            return Err(Error::new(ErrorCode::EvaluatorError, "Unhandled operator.".to_string()));
                         }
                     }
                 }
                 _ => {
-                    return Err(Error{
-                        code: ErrorCode::EvaluatorError,
-                        message: format!("Unhandled token."),
-                    });
+                    return Err(Error::new(ErrorCode::EvaluatorError, "Unhandled token.".to_string()));
                 }
             };
         }
 
         if self.values.len() != 1 {
-            return Err(Error{
-                code: ErrorCode::EvaluatorError,
-                message: format!("Evaluator does not have a stack size of one.")
-            });
+            return Err(Error::new(ErrorCode::EvaluatorError, "Evaluator does not have a stack size of one.".to_string()));
         }
 
-        let result = self.pop_value()?;
+        let result = self.pop_operand()?;
 
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::eval;
+
+    #[test]
+    fn compound_assignment_needs_the_variable_to_exist() {
+        let mut evaluator = Evaluator::new();
+        for input in ["$n += 3", "$n -= 3", "$n *= 3", "$n /= 3"] {
+            assert_eq!(eval(input, &mut evaluator).unwrap_err().code, ErrorCode::UndefinedVariable, "{}", input);
+        }
+        assert_eq!(eval("$n = 2; $n += 3; $n", &mut evaluator).unwrap(), Value::Number(5f64));
+    }
+
+    #[test]
+    fn compound_assignment_starts_new_variables_at_zero_when_lenient() {
+        for (input, expected) in [("$n += 3", 3f64), ("$n -= 3", -3f64), ("$n *= 3", 0f64), ("$n /= 3", 0f64)] {
+            let mut evaluator = Evaluator::new();
+            evaluator.set_lenient(true);
+            eval(input, &mut evaluator).unwrap();
+            assert_eq!(eval("$n", &mut evaluator).unwrap(), Value::Number(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn undoes_a_statement_that_fails() {
+        let mut evaluator = Evaluator::new();
+        eval("$a = 1", &mut evaluator).unwrap();
+        assert_eq!(eval("$a = ($c = 3) / 0", &mut evaluator).unwrap_err().code, ErrorCode::DivisionByZero);
+        assert_eq!(eval("$a", &mut evaluator).unwrap(), Value::Number(1f64));
+        assert_eq!(eval("$c", &mut evaluator).unwrap_err().code, ErrorCode::UndefinedVariable);
+        // Nothing is left on the stack for the next statement.
+        assert_eq!(eval("2 + 2", &mut evaluator).unwrap(), Value::Number(4f64));
+    }
+
+    #[test]
+    fn rolls_back_transactions() {
+        let mut evaluator = Evaluator::new();
+        eval("$a = 1", &mut evaluator).unwrap();
+        evaluator.begin();
+        eval("$a = 2; $b = 3; g($x) = $x * 2", &mut evaluator).unwrap();
+        evaluator.begin();
+        eval("$a = 4", &mut evaluator).unwrap();
+        evaluator.commit().unwrap();
+        assert_eq!(eval("$a", &mut evaluator).unwrap(), Value::Number(4f64));
+        evaluator.rollback().unwrap();
+        assert!(!evaluator.in_transaction());
+        assert_eq!(eval("$a", &mut evaluator).unwrap(), Value::Number(1f64));
+        assert_eq!(eval("$b", &mut evaluator).unwrap_err().code, ErrorCode::UndefinedVariable);
+        assert_eq!(eval("g(1)", &mut evaluator).unwrap_err().code, ErrorCode::UndefinedFunction);
+        assert_eq!(evaluator.commit().unwrap_err().code, ErrorCode::EvaluatorError);
+        assert_eq!(evaluator.rollback().unwrap_err().code, ErrorCode::EvaluatorError);
+    }
+
+    #[test]
+    fn keeps_let_bindings_to_their_block() {
+        let mut evaluator = Evaluator::new();
+        eval("$x = 1", &mut evaluator).unwrap();
+        assert_eq!(eval("{ let $x = 5; $x * 2 }", &mut evaluator).unwrap(), Value::Number(10f64));
+        assert_eq!(eval("{ let $x = 2; { let $x = 3; $x } + $x }", &mut evaluator).unwrap(), Value::Number(5f64));
+        assert_eq!(eval("$x", &mut evaluator).unwrap(), Value::Number(1f64));
+        eval("h($z) = { let $w = $z + 1; $w * 2 }", &mut evaluator).unwrap();
+        assert_eq!(eval("h(2)", &mut evaluator).unwrap(), Value::Number(6f64));
+        assert_eq!(eval("$w", &mut evaluator).unwrap_err().code, ErrorCode::UndefinedVariable);
+        // Assignment without `let` reaches the variable outside.
+        assert_eq!(eval("{ $x = 3; $x }; $x", &mut evaluator).unwrap(), Value::Number(3f64));
+    }
+
+    #[test]
+    fn writes_children_locally() {
+        let mut parent = Evaluator::new();
+        eval("$x = 3", &mut parent).unwrap();
+        let parent = Rc::new(parent);
+        let mut child = Evaluator::child(parent.clone());
+        assert_eq!(eval("$x + 1", &mut child).unwrap(), Value::Number(4f64));
+        eval("$x = 10; $new = 1", &mut child).unwrap();
+        assert_eq!(eval("$x", &mut child).unwrap(), Value::Number(10f64));
+        assert_eq!(parent.variable("$x"), Some(Value::Number(3f64)));
+        assert_eq!(parent.variable("$new"), None);
+        assert_eq!(eval("$pi = 3", &mut child).unwrap_err().code, ErrorCode::ReassignConstant);
+    }
+
+    // An evaluator that writes down every update it sends.
+    fn listened() -> (Evaluator, Rc<std::cell::RefCell<Vec<String>>>) {
+        let log = Rc::new(std::cell::RefCell::new(Vec::<String>::new()));
+        let mut evaluator = Evaluator::new();
+        let written = log.clone();
+        evaluator.subscribe(move |update| {
+            written.borrow_mut().push(match update {
+                Update::Changed(name, value) => format!("{} = {}", name, value),
+                Update::Stale(name) => format!("{} stale", name),
+            })
+        });
+        (evaluator, log)
+    }
+
+    #[test]
+    fn recomputes_bindings_in_order() {
+        let (mut evaluator, log) = listened();
+        eval("$w = 2; $h = 3; $area := $w * $h; $double := $area * 2", &mut evaluator).unwrap();
+        log.borrow_mut().clear();
+        eval("$h = 1", &mut evaluator).unwrap();
+        assert_eq!(*log.borrow(), ["$area = 2", "$double = 4"]);
+        assert_eq!(eval("$double", &mut evaluator).unwrap(), Value::Number(4f64));
+        let error = eval("$w := $double", &mut evaluator).unwrap_err();
+        assert_eq!(error.code, ErrorCode::CyclicBinding);
+        assert_eq!(error.message, "'$w' would depend on itself: $w -> $double -> $area -> $w.");
+    }
+
+    #[test]
+    fn holds_back_updates_until_kept() {
+        let (mut evaluator, log) = listened();
+        eval("$w = 2; $area := $w * 3", &mut evaluator).unwrap();
+        log.borrow_mut().clear();
+        evaluator.begin();
+        eval("$w = 100", &mut evaluator).unwrap();
+        evaluator.rollback().unwrap();
+        assert!(eval("$w = 1 / 0", &mut evaluator).is_err());
+        assert!(log.borrow().is_empty());
+        assert_eq!(eval("$area", &mut evaluator).unwrap(), Value::Number(6f64));
+    }
+
+    #[test]
+    fn recomputes_lazily_when_read() {
+        let (mut evaluator, log) = listened();
+        evaluator.set_recompute(Recompute::Lazy);
+        eval("$w = 2; $area := $w * 3", &mut evaluator).unwrap();
+        log.borrow_mut().clear();
+        eval("$w = 5", &mut evaluator).unwrap();
+        assert_eq!(*log.borrow(), ["$area stale"]);
+        assert_eq!(eval("$area", &mut evaluator).unwrap(), Value::Number(15f64));
+        assert_eq!(log.borrow().last().map(String::as_str), Some("$area = 15"));
+    }
+
+    fn suggestions(input: &str, evaluator: &mut Evaluator) -> (ErrorCode, Vec<String>) {
+        let error = eval(input, evaluator).unwrap_err();
+        (error.code, error.suggestions)
+    }
+
+    #[test]
+    fn suggests_variables_and_constants() {
+        let mut evaluator = Evaluator::new();
+        eval("$length = 3", &mut evaluator).unwrap();
+        assert_eq!(suggestions("$lenght + 1", &mut evaluator), (ErrorCode::UndefinedVariable, vec!["$length".to_string()]));
+        assert_eq!(suggestions("2 * $pj", &mut evaluator), (ErrorCode::UndefinedVariable, vec!["$pi".to_string()]));
+        assert_eq!(suggestions("$width", &mut evaluator), (ErrorCode::UndefinedVariable, Vec::new()));
+    }
+
+    #[test]
+    fn suggests_functions() {
+        let mut evaluator = Evaluator::new();
+        eval("area($r) = $pi * $r ^ 2", &mut evaluator).unwrap();
+        assert_eq!(suggestions("aera(2)", &mut evaluator), (ErrorCode::UndefinedFunction, vec!["area".to_string()]));
+        let (code, names) = suggestions("sinn(1)", &mut evaluator);
+        assert_eq!(code, ErrorCode::UndefinedFunction);
+        assert!(names.contains(&"sin".to_string()), "{:?}", names);
+    }
+
+    #[test]
+    fn sums_and_multiplies_over_an_index() {
+        let mut evaluator = Evaluator::new();
+        assert_eq!(eval("sum($i -> $i ^ 2, 1, 10)", &mut evaluator).unwrap(), Value::Number(385f64));
+        assert_eq!(eval("prod($k -> $k, 1, 5)", &mut evaluator).unwrap(), Value::Number(120f64));
+        assert_eq!(eval("sum($i -> $i, 5, 1)", &mut evaluator).unwrap(), Value::Number(0f64));
+        // The index is local to the series.
+        assert_eq!(eval("$i = 7; sum($i -> $i, 1, 3) + $i", &mut evaluator).unwrap(), Value::Number(13f64));
+    }
+
+    #[test]
+    fn limits_series() {
+        let mut evaluator = Evaluator::new();
+        assert_eq!(eval("sum($i -> 1, 1, 10000000)", &mut evaluator).unwrap_err().code, ErrorCode::IterationLimit);
+        assert_eq!(eval("sum($i -> 1, 100000000000000000, 100000000000000016)", &mut evaluator).unwrap_err().code, ErrorCode::DomainError);
+        assert_eq!(eval("sum($i -> 1, 1.5, 3)", &mut evaluator).unwrap_err().code, ErrorCode::DomainError);
+    }
+
+    #[test]
+    fn rebinds_on_variables_read_inside_lambdas() {
+        let mut evaluator = Evaluator::new();
+        eval("$k = 2; $f := map([1, 2], $x -> $x * $k)", &mut evaluator).unwrap();
+        eval("$k = 3", &mut evaluator).unwrap();
+        assert_eq!(eval("$f", &mut evaluator).unwrap(), eval("[3, 6]", &mut evaluator).unwrap());
+        assert!(!evaluator.bindings()[0].1.dependencies.contains("$x"));
+    }
+
+    #[test]
+    fn adds_up_arguments_that_are_not_a_series() {
+        let mut evaluator = Evaluator::new();
+        assert_eq!(eval("$a = 1; $b = 2; $c = 3; $d = 4; sum($a, $b, $c, $d)", &mut evaluator).unwrap(), Value::Number(10f64));
+        assert_eq!(eval("prod($a, $b, $c, $d)", &mut evaluator).unwrap(), Value::Number(24f64));
+        assert_eq!(eval("$w = [1, 2]; sum($w, 1, 2, 3)", &mut evaluator).unwrap(), Value::Number(9f64));
+    }
+}
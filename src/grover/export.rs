@@ -0,0 +1,370 @@
+use crate::grover::{calculus, error, expression, format, token, value};
+use error::Error;
+use expression::Expression;
+use token::Associativity;
+use token::Operator;
+use token::Token;
+use token::Tokens;
+use value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    Latex,
+    MathMl,
+}
+
+const GREEK: [(&str, &str); 22] = [
+    ("alpha", "α"), ("beta", "β"), ("gamma", "γ"), ("delta", "δ"), ("epsilon", "ε"), ("zeta", "ζ"), ("eta", "η"), ("theta", "θ"),
+    ("iota", "ι"), ("kappa", "κ"), ("lambda", "λ"), ("mu", "μ"), ("nu", "ν"), ("xi", "ξ"), ("pi", "π"), ("rho", "ρ"),
+    ("sigma", "σ"), ("tau", "τ"), ("phi", "φ"), ("chi", "χ"), ("psi", "ψ"), ("omega", "ω"),
+];
+
+// Functions LaTeX has an operator for, and their MathML names.
+const OPERATORS: [(&str, &str, &str); 15] = [
+    ("sin", "\\sin", "sin"), ("cos", "\\cos", "cos"), ("tan", "\\tan", "tan"),
+    ("asin", "\\arcsin", "arcsin"), ("acos", "\\arccos", "arccos"), ("atan", "\\arctan", "arctan"),
+    ("sinh", "\\sinh", "sinh"), ("cosh", "\\cosh", "cosh"), ("tanh", "\\tanh", "tanh"),
+    ("ln", "\\ln", "ln"), ("log", "\\log_{10}", "log"), ("log2", "\\log_{2}", "log"),
+    ("exp", "\\exp", "exp"), ("min", "\\min", "min"), ("max", "\\max", "max"),
+];
+
+// Renders an evaluated statement as `expr = result`.
+pub fn equation(tokens: &Tokens, result: &Value, notation: Notation) -> Result<String, Error> {
+    let (binding, expression) = split(tokens)?;
+    let result = match result {
+        Value::Number(number) => Expression::Number(*number),
+        Value::Matrix(matrix) => Expression::Matrix(
+            (0..matrix.rows()).map(|row| (0..matrix.columns()).map(|column| Expression::Number(matrix.get(row, column))).collect()).collect(),
+        ),
+    };
+    // A plain number, or a plain number being assigned, is its own result.
+    let plain = match &expression {
+        Expression::Binary(operator, _, value) if **operator == token::ASSIGNMENT_OPERATOR => **value == result,
+        _ => expression == result,
+    };
+    let sides: Vec<&Expression> = if plain { vec![&expression] } else { vec![&expression, &result] };
+    render(binding, sides, notation)
+}
+
+// Renders a statement without evaluating it, as when `diff($x ^ 2, $x)` has been rewritten to `2 * $x`.
+pub fn statement(tokens: &Tokens, notation: Notation) -> Result<String, Error> {
+    let (binding, expression) = split(tokens)?;
+    render(binding, vec![&expression], notation)
+}
+
+// The variable a statement binds, if any, and its expression.
+fn split(tokens: &Tokens) -> Result<(Option<&str>, Expression), Error> {
+    Ok(match tokens.first() {
+        Some(Token::Binding(name)) => {
+            let formula: Tokens = tokens.iter().skip(1).cloned().collect();
+            (Some(name.as_str()), Expression::from_tokens(&formula)?)
+        }
+        _ => (None, Expression::from_tokens(tokens)?),
+    })
+}
+
+fn render(binding: Option<&str>, sides: Vec<&Expression>, notation: Notation) -> Result<String, Error> {
+    match notation {
+        Notation::Latex => {
+            let mut parts: Vec<String> = sides.into_iter().map(latex).collect();
+            if let Some(name) = binding {
+                parts[0] = format!("{} := {}", variable_latex(name), parts[0]);
+            }
+            Ok(parts.join(" = "))
+        }
+        Notation::MathMl => {
+            let mut parts: Vec<String> = sides.into_iter().map(mathml).collect();
+            if let Some(name) = binding {
+                parts[0] = format!("{}<mo>:=</mo>{}", variable_mathml(name), parts[0]);
+            }
+            Ok(format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow>{}</mrow></math>", parts.join("<mo>=</mo>")))
+        }
+    }
+}
+
+pub fn latex(expression: &Expression) -> String {
+    match expression {
+        Expression::Number(value) => format::number(*value),
+        Expression::Variable(name) => variable_latex(name),
+        Expression::Cell(name) => format!("\\mathrm{{{}}}", name),
+        Expression::Range(start, end) => format!("\\mathrm{{{}{{:}}{}}}", start, end),
+        Expression::Call(name, arguments) if calculus::series(name, arguments).is_some() => {
+            let (index, term) = calculus::series(name, arguments).expect("Checked to be a series.");
+            let symbol = if name == "prod" { "\\prod" } else { "\\sum" };
+            let term = group_latex(term, is_sum(term));
+            format!("{}_{{{} = {}}}^{{{}}} {}", symbol, variable_latex(index), latex(&arguments[1]), latex(&arguments[2]), term)
+        }
+        Expression::Call(name, arguments) => {
+            let arguments: Vec<String> = arguments.iter().map(latex).collect();
+            let arguments = arguments.join(", ");
+            match name.as_str() {
+                "sqrt" => format!("\\sqrt{{{}}}", arguments),
+                "cbrt" => format!("\\sqrt[3]{{{}}}", arguments),
+                "abs" => format!("\\left| {} \\right|", arguments),
+                "floor" => format!("\\left\\lfloor {} \\right\\rfloor", arguments),
+                "ceil" => format!("\\left\\lceil {} \\right\\rceil", arguments),
+                _ => {
+                    let operator = match OPERATORS.iter().find(|(function, _, _)| function == name) {
+                        Some((_, operator, _)) => operator.to_string(),
+                        None => format!("\\operatorname{{{}}}", escape_latex(name)),
+                    };
+                    format!("{}\\left( {} \\right)", operator, arguments)
+                }
+            }
+        }
+        Expression::Negate(operand) => format!("-{}", group_latex(operand, negated(operand))),
+        Expression::Binary(operator, lhs, rhs) => match operator.symbol {
+            "/" => format!("\\frac{{{}}}{{{}}}", latex(lhs), latex(rhs)),
+            "^" => format!("{}^{{{}}}", group_latex(lhs, is_compound(lhs)), latex(rhs)),
+            ".^" => format!("{}^{{\\circ {}}}", group_latex(lhs, is_compound(lhs)), latex(rhs)),
+            symbol => {
+                let symbol = match symbol {
+                    "*" => "\\cdot",
+                    ".*" => "\\odot",
+                    "./" => "\\oslash",
+                    "%" => "\\bmod",
+                    "<=" => "\\le",
+                    ">=" => "\\ge",
+                    "==" => "=",
+                    "!=" => "\\ne",
+                    ".." => "\\ldots",
+                    "=" | "+" | "-" | "<" | ">" => symbol,
+                    _ => return format!("{} \\mathrel{{{}}} {}", latex(lhs), symbol, latex(rhs)),
+                };
+                format!("{} {} {}", group_latex(lhs, left(operator, lhs)), symbol, group_latex(rhs, right(operator, rhs)))
+            }
+        },
+        Expression::Block(statements) => {
+            let statements: Vec<String> = statements.iter().map(latex).collect();
+            format!("\\left\\{{ {} \\right\\}}", statements.join(";\\ "))
+        }
+        Expression::Let(name, value) => format!("\\mathbf{{let}}\\ {} = {}", variable_latex(name), latex(value)),
+        Expression::Lambda(parameters, body) => {
+            let parameters: Vec<String> = parameters.iter().map(|parameter| variable_latex(parameter)).collect();
+            match parameters.as_slice() {
+                [parameter] => format!("{} \\mapsto {}", parameter, latex(body)),
+                _ => format!("\\left( {} \\right) \\mapsto {}", parameters.join(", "), latex(body)),
+            }
+        }
+        Expression::Matrix(rows) => {
+            let rows: Vec<String> = rows.iter().map(|row| row.iter().map(latex).collect::<Vec<String>>().join(" & ")).collect();
+            format!("\\begin{{bmatrix}} {} \\end{{bmatrix}}", rows.join(" \\\\ "))
+        }
+        Expression::Index(value, indices) => {
+            let indices: Vec<String> = indices.iter().map(latex).collect();
+            format!("{}_{{{}}}", group_latex(value, is_compound(value)), indices.join(", "))
+        }
+    }
+}
+
+pub fn mathml(expression: &Expression) -> String {
+    match expression {
+        Expression::Number(value) if expression.is_negative() => format!("<mrow><mo>-</mo><mn>{}</mn></mrow>", format::number(-value)),
+        Expression::Number(value) => format!("<mn>{}</mn>", format::number(*value)),
+        Expression::Variable(name) => variable_mathml(name),
+        Expression::Cell(name) => format!("<mi mathvariant=\"normal\">{}</mi>", name),
+        Expression::Range(start, end) => format!("<mi mathvariant=\"normal\">{}:{}</mi>", start, end),
+        Expression::Call(name, arguments) if calculus::series(name, arguments).is_some() => {
+            let (index, term) = calculus::series(name, arguments).expect("Checked to be a series.");
+            let symbol = if name == "prod" { "&#x220F;" } else { "&#x2211;" };
+            let under = format!("<mrow>{}<mo>=</mo>{}</mrow>", variable_mathml(index), mathml(&arguments[1]));
+            let term = group_mathml(term, is_sum(term));
+            format!("<mrow><munderover><mo>{}</mo>{}{}</munderover>{}</mrow>", symbol, under, mathml(&arguments[2]), term)
+        }
+        Expression::Call(name, arguments) => {
+            let mut arguments: Vec<String> = arguments.iter().map(mathml).collect();
+            match name.as_str() {
+                "sqrt" => format!("<msqrt>{}</msqrt>", arguments.concat()),
+                "cbrt" => format!("<mroot><mrow>{}</mrow><mn>3</mn></mroot>", arguments.concat()),
+                "abs" => fence("|", &arguments.concat(), "|"),
+                "floor" => fence("⌊", &arguments.concat(), "⌋"),
+                "ceil" => fence("⌈", &arguments.concat(), "⌉"),
+                _ => {
+                    let operator = match (name.as_str(), OPERATORS.iter().find(|(function, _, _)| function == name)) {
+                        ("log", _) => "<msub><mi>log</mi><mn>10</mn></msub>".to_string(),
+                        ("log2", _) => "<msub><mi>log</mi><mn>2</mn></msub>".to_string(),
+                        (_, Some((_, _, operator))) => format!("<mi>{}</mi>", operator),
+                        (_, None) => format!("<mi>{}</mi>", escape_xml(name)),
+                    };
+                    for argument in arguments.iter_mut().skip(1) {
+                        argument.insert_str(0, "<mo>,</mo>");
+                    }
+                    format!("<mrow>{}<mo>&#x2061;</mo>{}</mrow>", operator, fence("(", &arguments.concat(), ")"))
+                }
+            }
+        }
+        Expression::Negate(operand) => format!("<mrow><mo>-</mo>{}</mrow>", group_mathml(operand, negated(operand))),
+        Expression::Binary(operator, lhs, rhs) => match operator.symbol {
+            "/" => format!("<mfrac>{}{}</mfrac>", mathml(lhs), mathml(rhs)),
+            "^" => format!("<msup>{}{}</msup>", group_mathml(lhs, is_compound(lhs)), mathml(rhs)),
+            ".^" => format!("<msup>{}<mrow><mo>&#x2218;</mo>{}</mrow></msup>", group_mathml(lhs, is_compound(lhs)), mathml(rhs)),
+            symbol => {
+                let symbol = match symbol {
+                    "*" => "&#x22C5;",
+                    ".*" => "&#x2299;",
+                    "./" => "&#x2298;",
+                    "%" => "mod",
+                    "<" => "&lt;",
+                    ">" => "&gt;",
+                    "<=" => "&#x2264;",
+                    ">=" => "&#x2265;",
+                    "==" => "=",
+                    "!=" => "&#x2260;",
+                    ".." => "&#x2026;",
+                    _ => symbol,
+                };
+                let lhs = group_mathml(lhs, left(operator, lhs));
+                let rhs = group_mathml(rhs, right(operator, rhs));
+                format!("<mrow>{}<mo>{}</mo>{}</mrow>", lhs, symbol, rhs)
+            }
+        },
+        Expression::Block(statements) => {
+            let statements: Vec<String> = statements.iter().map(mathml).collect();
+            fence("{", &statements.join("<mo>;</mo>"), "}")
+        }
+        Expression::Let(name, value) => {
+            format!("<mrow><mtext>let</mtext><mspace width=\"0.5em\"/>{}<mo>=</mo>{}</mrow>", variable_mathml(name), mathml(value))
+        }
+        Expression::Lambda(parameters, body) => {
+            let parameters: Vec<String> = parameters.iter().map(|parameter| variable_mathml(parameter)).collect();
+            let parameters = match parameters.as_slice() {
+                [parameter] => parameter.clone(),
+                _ => fence("(", &parameters.join("<mo>,</mo>"), ")"),
+            };
+            format!("<mrow>{}<mo>&#x21A6;</mo>{}</mrow>", parameters, mathml(body))
+        }
+        Expression::Matrix(rows) => {
+            let rows: Vec<String> = rows
+                .iter()
+                .map(|row| format!("<mtr>{}</mtr>", row.iter().map(|element| format!("<mtd>{}</mtd>", mathml(element))).collect::<String>()))
+                .collect();
+            fence("[", &format!("<mtable>{}</mtable>", rows.concat()), "]")
+        }
+        Expression::Index(value, indices) => {
+            let indices: Vec<String> = indices.iter().map(mathml).collect();
+            format!("<msub>{}<mrow>{}</mrow></msub>", group_mathml(value, is_compound(value)), indices.join("<mo>,</mo>"))
+        }
+    }
+}
+
+// Fractions group their parts by themselves, so they never need parentheses.
+fn precedence(expression: &Expression) -> Option<u8> {
+    match expression {
+        Expression::Binary(operator, _, _) if **operator == token::DIVISION_OPERATOR => None,
+        _ => expression.precedence(),
+    }
+}
+
+// Bases of powers are parenthesized unless they are a single symbol, number or call.
+fn is_compound(expression: &Expression) -> bool {
+    precedence(expression).is_some() || matches!(expression, Expression::Binary(_, _, _))
+}
+
+// The term of a series is parenthesized when it is a sum itself, or anything looser.
+fn is_sum(expression: &Expression) -> bool {
+    precedence(expression).is_some_and(|precedence| precedence >= token::ADDITION_OPERATOR.precedence)
+}
+
+fn negated(operand: &Expression) -> bool {
+    precedence(operand).is_some_and(|precedence| precedence >= expression::NEGATION_PRECEDENCE)
+}
+
+fn left(operator: &Operator, operand: &Expression) -> bool {
+    precedence(operand).is_some_and(|precedence| {
+        precedence > operator.precedence || precedence == operator.precedence && operator.associativity == Associativity::Right
+    })
+}
+
+fn right(operator: &Operator, operand: &Expression) -> bool {
+    !operand.is_negative()
+        && precedence(operand).is_some_and(|precedence| {
+            precedence > operator.precedence || precedence == operator.precedence && operator.associativity == Associativity::Left
+        })
+}
+
+fn group_latex(operand: &Expression, parenthesize: bool) -> String {
+    if parenthesize {
+        format!("\\left( {} \\right)", latex(operand))
+    } else {
+        latex(operand)
+    }
+}
+
+fn group_mathml(operand: &Expression, parenthesize: bool) -> String {
+    if parenthesize {
+        fence("(", &mathml(operand), ")")
+    } else {
+        mathml(operand)
+    }
+}
+
+fn fence(open: &str, inner: &str, close: &str) -> String {
+    format!("<mrow><mo>{}</mo>{}<mo>{}</mo></mrow>", open, inner, close)
+}
+
+// `$x` is written `x`, `$pi` as `\pi` and longer names in italics.
+fn variable_latex(name: &str) -> String {
+    let name = name.trim_start_matches('$');
+    if let Some((greek, _)) = GREEK.iter().find(|(greek, _)| *greek == name) {
+        return format!("\\{}", greek);
+    }
+    if name.chars().count() == 1 {
+        return name.to_string();
+    }
+    format!("\\mathit{{{}}}", escape_latex(name))
+}
+
+fn variable_mathml(name: &str) -> String {
+    let name = name.trim_start_matches('$');
+    if let Some((_, letter)) = GREEK.iter().find(|(greek, _)| *greek == name) {
+        return format!("<mi>{}</mi>", letter);
+    }
+    if name.chars().count() == 1 {
+        return format!("<mi>{}</mi>", escape_xml(name));
+    }
+    format!("<mi mathvariant=\"italic\">{}</mi>", escape_xml(name))
+}
+
+fn escape_latex(text: &str) -> String {
+    text.replace('_', "\\_")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::parse;
+
+    fn expression(input: &str) -> Expression {
+        Expression::from_tokens(&parse(input).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn writes_latex() {
+        assert_eq!(latex(&expression("(1 + $x) / 2")), "\\frac{1 + x}{2}");
+        assert_eq!(latex(&expression("sqrt($x_1 ^ 2 + 1)")), "\\sqrt{\\mathit{x\\_1}^{2} + 1}");
+        assert_eq!(latex(&expression("sin($alpha) * 2")), "\\sin\\left( \\alpha \\right) \\cdot 2");
+        assert_eq!(latex(&expression("$x ^ (2 + $y)")), "x^{2 + y}");
+    }
+
+    #[test]
+    fn writes_mathml() {
+        assert_eq!(mathml(&expression("(1 + $x) / 2")), "<mfrac><mrow><mn>1</mn><mo>+</mo><mi>x</mi></mrow><mn>2</mn></mfrac>");
+        assert_eq!(mathml(&expression("$x ^ 2")), "<msup><mi>x</mi><mn>2</mn></msup>");
+    }
+
+    #[test]
+    fn writes_statements_with_their_results() {
+        let three = Value::Number(3f64);
+        assert_eq!(equation(&parse("1 + 2").unwrap(), &three, Notation::Latex).unwrap(), "1 + 2 = 3");
+        assert_eq!(equation(&parse("3").unwrap(), &three, Notation::Latex).unwrap(), "3");
+        assert_eq!(equation(&parse("$y := $x / 4").unwrap(), &Value::Number(0.5), Notation::Latex).unwrap(), "y := \\frac{x}{4} = 0.5");
+        let written = equation(&parse("3").unwrap(), &three, Notation::MathMl).unwrap();
+        assert_eq!(written, "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow><mn>3</mn></mrow></math>");
+        // A rewritten derivative has no result to show.
+        assert_eq!(statement(&parse("2 * $x").unwrap(), Notation::Latex).unwrap(), "2 \\cdot x");
+    }
+}
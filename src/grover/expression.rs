@@ -0,0 +1,204 @@
+use crate::grover::{error, token};
+use error::Error;
+use error::ErrorCode;
+use token::Operator;
+use token::Token;
+use token::Tokens;
+
+// A parsed expression as a tree, rebuilt from its RPN tokens.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(f64),
+    Variable(String),
+    Cell(String),
+    Range(String, String),
+    Call(String, Vec<Expression>),
+    // Unary minus, which the parser emits as `-1 x *`.
+    Negate(Box<Expression>),
+    Binary(&'static Operator, Box<Expression>, Box<Expression>),
+    Block(Vec<Expression>),
+    Let(String, Box<Expression>),
+    // An expression in its parameters, as passed to `integrate`.
+    Lambda(Vec<String>, Box<Expression>),
+    // A matrix literal, row by row.
+    Matrix(Vec<Vec<Expression>>),
+    // A value and its indices, as in `$v[2]`.
+    Index(Box<Expression>, Vec<Expression>),
+}
+
+// How tightly unary minus binds: like `*`, which it is made of.
+pub const NEGATION_PRECEDENCE: u8 = token::MULTIPLICATION_OPERATOR.precedence;
+
+impl Expression {
+    pub fn from_tokens(tokens: &Tokens) -> Result<Self, Error> {
+        let mut operands = Vec::<Expression>::new();
+        // Statements of each open block, with the operand count at its start.
+        let mut blocks = Vec::<(Vec<Expression>, usize)>::new();
+        for token in tokens.iter() {
+            match token {
+                Token::LeftBrace => blocks.push((Vec::new(), operands.len())),
+                Token::Separator | Token::RightBrace => {
+                    let statement = operands.pop().ok_or_else(malformed)?;
+                    let (mut statements, base) = blocks.pop().ok_or_else(malformed)?;
+                    if operands.len() != base {
+                        return Err(malformed());
+                    }
+                    statements.push(statement);
+                    if *token == Token::Separator {
+                        blocks.push((statements, base));
+                    } else {
+                        operands.push(Expression::Block(statements));
+                    }
+                }
+                Token::Let(name) => {
+                    let value = operands.pop().ok_or_else(malformed)?;
+                    operands.push(Expression::Let(name.clone(), Box::new(value)));
+                }
+                Token::Number(number) => operands.push(Expression::Number(*number)),
+                Token::Identifier(name) => operands.push(Expression::Variable(name.clone())),
+                Token::Cell(name) => operands.push(Expression::Cell(name.clone())),
+                Token::Range(start, end) => operands.push(Expression::Range(start.clone(), end.clone())),
+                Token::Lambda(parameters, body) => {
+                    operands.push(Expression::Lambda(parameters.clone(), Box::new(Expression::from_tokens(body)?)));
+                }
+                Token::Array(rows) => {
+                    let count: usize = rows.iter().sum();
+                    if operands.len() < count {
+                        return Err(malformed());
+                    }
+                    let mut elements = operands.split_off(operands.len() - count).into_iter();
+                    let rows = rows.iter().map(|columns| elements.by_ref().take(*columns).collect()).collect();
+                    operands.push(Expression::Matrix(rows));
+                }
+                Token::Index(count) => {
+                    if operands.len() < count + 1 {
+                        return Err(malformed());
+                    }
+                    let indices = operands.split_off(operands.len() - count);
+                    let value = operands.pop().ok_or_else(malformed)?;
+                    operands.push(Expression::Index(Box::new(value), indices));
+                }
+                Token::Function(name, arity) => {
+                    if operands.len() < *arity {
+                        return Err(malformed());
+                    }
+                    let arguments = operands.split_off(operands.len() - arity);
+                    operands.push(Expression::Call(name.clone(), arguments));
+                }
+                Token::Operator(operator) => {
+                    let rhs = operands.pop().ok_or_else(malformed)?;
+                    let lhs = operands.pop().ok_or_else(malformed)?;
+                    let expression = match lhs {
+                        Expression::Number(sign) if sign == -1f64 && **operator == token::MULTIPLICATION_OPERATOR => {
+                            Expression::Negate(Box::new(rhs))
+                        }
+                        _ => Expression::Binary(operator, Box::new(lhs), Box::new(rhs)),
+                    };
+                    operands.push(expression);
+                }
+                _ => return Err(malformed()),
+            }
+        }
+        if operands.len() != 1 || !blocks.is_empty() {
+            return Err(malformed());
+        }
+        Ok(operands.remove(0))
+    }
+    // The RPN the parser would produce for this expression.
+    pub fn to_tokens(&self) -> Tokens {
+        let mut tokens = Tokens::new();
+        self.write(&mut tokens);
+        tokens
+    }
+    fn write(&self, tokens: &mut Tokens) {
+        match self {
+            Expression::Number(number) => tokens.push(Token::Number(*number)),
+            Expression::Variable(name) => tokens.push(Token::Identifier(name.clone())),
+            Expression::Cell(name) => tokens.push(Token::Cell(name.clone())),
+            Expression::Range(start, end) => tokens.push(Token::Range(start.clone(), end.clone())),
+            Expression::Call(name, arguments) => {
+                for argument in arguments {
+                    argument.write(tokens);
+                }
+                tokens.push(Token::Function(name.clone(), arguments.len()));
+            }
+            Expression::Negate(operand) => {
+                tokens.push(Token::Number(-1f64));
+                operand.write(tokens);
+                tokens.push(token::MULTIPLICATION_TOKEN);
+            }
+            Expression::Binary(operator, lhs, rhs) => {
+                lhs.write(tokens);
+                rhs.write(tokens);
+                tokens.push(Token::Operator(operator));
+            }
+            Expression::Block(statements) => {
+                tokens.push(Token::LeftBrace);
+                for (index, statement) in statements.iter().enumerate() {
+                    if index > 0 {
+                        tokens.push(Token::Separator);
+                    }
+                    statement.write(tokens);
+                }
+                tokens.push(Token::RightBrace);
+            }
+            Expression::Let(name, value) => {
+                value.write(tokens);
+                tokens.push(Token::Let(name.clone()));
+            }
+            Expression::Lambda(parameters, body) => tokens.push(Token::Lambda(parameters.clone(), body.to_tokens())),
+            Expression::Matrix(rows) => {
+                for element in rows.iter().flatten() {
+                    element.write(tokens);
+                }
+                tokens.push(Token::Array(rows.iter().map(|row| row.len()).collect()));
+            }
+            Expression::Index(value, indices) => {
+                value.write(tokens);
+                for index in indices {
+                    index.write(tokens);
+                }
+                tokens.push(Token::Index(indices.len()));
+            }
+        }
+    }
+    // Lower binds tighter, as with operators. None for anything that never needs parentheses.
+    pub fn precedence(&self) -> Option<u8> {
+        match self {
+            Expression::Binary(operator, _, _) => Some(operator.precedence),
+            Expression::Negate(_) => Some(NEGATION_PRECEDENCE),
+            Expression::Number(number) if number.is_sign_negative() && *number != 0f64 => Some(NEGATION_PRECEDENCE),
+            Expression::Let(_, _) | Expression::Lambda(_, _) => Some(token::ASSIGNMENT_OPERATOR.precedence),
+            _ => None,
+        }
+    }
+    // Written with a leading minus.
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Expression::Negate(_) => true,
+            Expression::Number(number) => number.is_sign_negative() && *number != 0f64,
+            _ => false,
+        }
+    }
+}
+
+// Rewrites the expression of a statement, keeping what it defines or binds.
+pub fn rewrite<F>(tokens: &Tokens, rewrite: F) -> Result<Tokens, Error>
+where
+    F: FnOnce(&Expression) -> Result<Expression, Error>,
+{
+    match tokens.first() {
+        Some(Token::Definition(_, _)) | Some(Token::Binding(_)) => {
+            let body: Tokens = tokens.iter().skip(1).cloned().collect();
+            let mut statement: Tokens = tokens.iter().take(1).cloned().collect();
+            rewrite(&Expression::from_tokens(&body)?)?.write(&mut statement);
+            Ok(statement)
+        }
+        Some(Token::Import(_)) | Some(Token::Use(_)) => Ok(tokens.clone()),
+        _ => Ok(rewrite(&Expression::from_tokens(tokens)?)?.to_tokens()),
+    }
+}
+
+pub fn malformed() -> Error {
+    Error::new(ErrorCode::MalformedExpression, "Can not format a malformed expression.".to_string())
+}
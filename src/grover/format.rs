@@ -0,0 +1,152 @@
+use crate::grover::{error, expression, token};
+use error::Error;
+use expression::Expression;
+use expression::NEGATION_PRECEDENCE;
+use token::Associativity;
+use token::Token;
+use token::Tokens;
+
+// Turns RPN back into canonical infix the parser reads back as the same tokens: single spaces around
+// operators and only the parentheses precedence and associativity call for.
+pub fn infix(tokens: &Tokens) -> Result<String, Error> {
+    infix_in(tokens, 10)
+}
+
+// Like `infix`, with whole numbers written in the radix. Fractions are still written in decimal, which the lexer
+// only reads in radix 10.
+pub fn infix_in(tokens: &Tokens, radix: u32) -> Result<String, Error> {
+    Ok(write(&Expression::from_tokens(tokens)?, radix))
+}
+
+// Like `infix`, for any statement, including definitions, bindings and imports.
+pub fn statement(tokens: &Tokens) -> Result<String, Error> {
+    let body = || -> Tokens { tokens.iter().skip(1).cloned().collect() };
+    match tokens.first() {
+        Some(Token::Definition(name, parameters)) => Ok(format!("{}({}) = {}", name, parameters.join(", "), infix(&body())?)),
+        Some(Token::Binding(name)) => Ok(format!("{} := {}", name, infix(&body())?)),
+        Some(Token::Import(path)) => Ok(format!("import {:?}", path)),
+        Some(Token::Use(name)) => Ok(format!("use {}", name)),
+        _ => infix(tokens),
+    }
+}
+
+pub fn expression(expression: &Expression) -> String {
+    write(expression, 10)
+}
+
+fn write(expression: &Expression, radix: u32) -> String {
+    match expression {
+        Expression::Number(value) => number_in(*value, radix),
+        Expression::Variable(name) | Expression::Cell(name) => name.clone(),
+        Expression::Range(start, end) => format!("{}:{}", start, end),
+        Expression::Call(name, arguments) => {
+            let arguments: Vec<String> = arguments.iter().map(|expression| write(expression, radix)).collect();
+            format!("{}({})", name, arguments.join(", "))
+        }
+        Expression::Negate(operand) => {
+            // `-$x ^ 2` already negates the whole power.
+            let parenthesize = operand.precedence().is_some_and(|precedence| precedence >= NEGATION_PRECEDENCE);
+            format!("-{}", group(operand, parenthesize, radix))
+        }
+        Expression::Binary(operator, lhs, rhs) => {
+            let left = lhs.precedence().is_some_and(|precedence| {
+                precedence > operator.precedence || precedence == operator.precedence && operator.associativity == Associativity::Right
+            });
+            // A negation on the right takes in everything up to the next looser operator, as the parser does.
+            let right = !rhs.is_negative()
+                && rhs.precedence().is_some_and(|precedence| {
+                    precedence > operator.precedence || precedence == operator.precedence && operator.associativity == Associativity::Left
+                });
+            format!("{} {} {}", group(lhs, left, radix), operator.symbol, group(rhs, right, radix))
+        }
+        Expression::Block(statements) => {
+            let statements: Vec<String> = statements.iter().map(|expression| write(expression, radix)).collect();
+            format!("{{ {} }}", statements.join("; "))
+        }
+        Expression::Let(name, value) => format!("let {} = {}", name, write(value, radix)),
+        Expression::Lambda(parameters, body) if parameters.len() == 1 => format!("{} -> {}", parameters[0], write(body, radix)),
+        Expression::Lambda(parameters, body) => format!("({}) -> {}", parameters.join(", "), write(body, radix)),
+        Expression::Matrix(rows) => {
+            let rows: Vec<String> = rows.iter().map(|row| row.iter().map(|expression| write(expression, radix)).collect::<Vec<String>>().join(", ")).collect();
+            format!("[{}]", rows.join("; "))
+        }
+        Expression::Index(value, indices) => {
+            let indices: Vec<String> = indices.iter().map(|expression| write(expression, radix)).collect();
+            format!("{}[{}]", group(value, value.precedence().is_some(), radix), indices.join(", "))
+        }
+    }
+}
+
+pub fn number(value: f64) -> String {
+    if value == 0f64 {
+        return "0".to_string();
+    }
+    value.to_string()
+}
+
+fn number_in(value: f64, radix: u32) -> String {
+    if radix == 10 || value.fract() != 0f64 || value.abs() >= 2f64.powi(53) {
+        return number(value);
+    }
+    let mut magnitude = value.abs() as u64;
+    let mut digits = Vec::<char>::new();
+    loop {
+        digits.push(std::char::from_digit((magnitude % radix as u64) as u32, radix).expect("A remainder is always a digit of its radix."));
+        magnitude /= radix as u64;
+        if magnitude == 0 {
+            break;
+        }
+    }
+    if value < 0f64 {
+        digits.push('-');
+    }
+    digits.into_iter().rev().collect()
+}
+
+fn group(operand: &Expression, parenthesize: bool, radix: u32) -> String {
+    if parenthesize {
+        format!("({})", write(operand, radix))
+    } else {
+        write(operand, radix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::parse;
+
+    fn round_trip(input: &str) -> String {
+        let tokens = parse(input).unwrap();
+        let written = infix(&tokens).unwrap();
+        assert_eq!(parse(&written).unwrap(), tokens, "{} was written as {}", input, written);
+        written
+    }
+
+    #[test]
+    fn keeps_precedence() {
+        assert_eq!(round_trip("1 + 2 * 3"), "1 + 2 * 3");
+        assert_eq!(round_trip("(1 + 2) * 3"), "(1 + 2) * 3");
+        assert_eq!(round_trip("1 - (2 - 3)"), "1 - (2 - 3)");
+        assert_eq!(round_trip("(1 - 2) - 3"), "1 - 2 - 3");
+        assert_eq!(round_trip("8 / (4 / 2)"), "8 / (4 / 2)");
+        round_trip("sin($x) * max(1, 2 + 3) % 4");
+    }
+
+    #[test]
+    fn keeps_power_right_associative() {
+        assert_eq!(round_trip("2 ^ 3 ^ 2"), "2 ^ 3 ^ 2");
+        assert_eq!(round_trip("2 ^ (3 ^ 2)"), "2 ^ 3 ^ 2");
+        assert_eq!(round_trip("(2 ^ 3) ^ 2"), "(2 ^ 3) ^ 2");
+    }
+
+    #[test]
+    fn keeps_unary_minus() {
+        round_trip("-$x ^ 2");
+        round_trip("(-$x) ^ 2");
+        round_trip("2 - -3");
+        round_trip("-(1 + 2)");
+        round_trip("2 ^ -$x");
+        round_trip("--$x");
+    }
+}
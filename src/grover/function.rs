@@ -0,0 +1,114 @@
+use crate::grover::{error, list, matrix, token, value};
+use error::Error;
+use token::Tokens;
+use value::Value;
+
+// How the evaluator's angle mode applies to a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Angles {
+    Unaffected,
+    // Arguments are angles, e.g. sin.
+    Arguments,
+    // The result is an angle, e.g. asin.
+    Result,
+}
+
+#[derive(Clone)]
+pub enum Definition {
+    // Applied element by element to matrices, except for variadic functions, which take in every element.
+    Native(fn(&[f64]) -> f64),
+    // Takes matrices as a whole, as `det` does.
+    Values(fn(&[Value]) -> Result<Value, Error>),
+    User {
+        parameters: Vec<String>,
+        body: Tokens,
+    },
+}
+
+#[derive(Clone)]
+pub struct Function {
+    // None accepts one or more arguments.
+    pub arity: Option<usize>,
+    pub definition: Definition,
+    pub angles: Angles,
+}
+
+impl Function {
+    pub fn new(arity: usize, call: fn(&[f64]) -> f64) -> Self {
+        Function {
+            arity: Some(arity),
+            definition: Definition::Native(call),
+            angles: Angles::Unaffected,
+        }
+    }
+    pub fn variadic(call: fn(&[f64]) -> f64) -> Self {
+        Function {
+            arity: None,
+            definition: Definition::Native(call),
+            angles: Angles::Unaffected,
+        }
+    }
+    pub fn values(arity: usize, call: fn(&[Value]) -> Result<Value, Error>) -> Self {
+        Function {
+            arity: Some(arity),
+            definition: Definition::Values(call),
+            angles: Angles::Unaffected,
+        }
+    }
+    pub fn user(parameters: Vec<String>, body: Tokens) -> Self {
+        Function {
+            arity: Some(parameters.len()),
+            definition: Definition::User { parameters, body },
+            angles: Angles::Unaffected,
+        }
+    }
+    pub fn with_angles(mut self, angles: Angles) -> Self {
+        self.angles = angles;
+        self
+    }
+    pub fn is_user(&self) -> bool {
+        matches!(self.definition, Definition::User { .. })
+    }
+}
+
+// Logarithms are undefined rather than infinite at zero.
+fn logarithm(x: f64, log: fn(f64) -> f64) -> f64 {
+    if x <= 0f64 {
+        f64::NAN
+    } else {
+        log(x)
+    }
+}
+
+pub fn builtins() -> Vec<(&'static str, Function)> {
+    let mut builtins = vec![
+        ("sqrt", Function::new(1, |x| x[0].sqrt())),
+        ("cbrt", Function::new(1, |x| x[0].cbrt())),
+        ("abs", Function::new(1, |x| x[0].abs())),
+        ("exp", Function::new(1, |x| x[0].exp())),
+        ("ln", Function::new(1, |x| logarithm(x[0], f64::ln))),
+        ("log", Function::new(1, |x| logarithm(x[0], f64::log10))),
+        ("log2", Function::new(1, |x| logarithm(x[0], f64::log2))),
+        ("sin", Function::new(1, |x| x[0].sin()).with_angles(Angles::Arguments)),
+        ("cos", Function::new(1, |x| x[0].cos()).with_angles(Angles::Arguments)),
+        ("tan", Function::new(1, |x| x[0].tan()).with_angles(Angles::Arguments)),
+        ("asin", Function::new(1, |x| x[0].asin()).with_angles(Angles::Result)),
+        ("acos", Function::new(1, |x| x[0].acos()).with_angles(Angles::Result)),
+        ("atan", Function::new(1, |x| x[0].atan()).with_angles(Angles::Result)),
+        ("atan2", Function::new(2, |x| x[0].atan2(x[1])).with_angles(Angles::Result)),
+        ("sinh", Function::new(1, |x| x[0].sinh())),
+        ("cosh", Function::new(1, |x| x[0].cosh())),
+        ("tanh", Function::new(1, |x| x[0].tanh())),
+        ("floor", Function::new(1, |x| x[0].floor())),
+        ("ceil", Function::new(1, |x| x[0].ceil())),
+        ("round", Function::new(1, |x| x[0].round())),
+        ("sum", Function::variadic(|x| x.iter().sum())),
+        ("prod", Function::variadic(|x| x.iter().product())),
+        ("mean", Function::variadic(|x| x.iter().sum::<f64>() / x.len() as f64)),
+        ("min", Function::variadic(|x| x.iter().cloned().fold(f64::INFINITY, f64::min))),
+        ("max", Function::variadic(|x| x.iter().cloned().fold(f64::NEG_INFINITY, f64::max))),
+    ];
+    builtins.extend(matrix::builtins());
+    builtins.extend(list::builtins());
+    builtins
+}
@@ -0,0 +1,420 @@
+use crate::grover::{error, token};
+use error::Error;
+use error::ErrorCode;
+use std::collections::VecDeque;
+use token::Chars;
+use token::Lexer;
+use token::Position;
+use token::Token;
+
+// Greek letters read as variables, so `\pi` is `$pi`.
+const GREEK: [&str; 22] = [
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa", "lambda", "mu", "nu", "xi", "pi",
+    "rho", "sigma", "tau", "phi", "chi", "psi", "omega",
+];
+
+// LaTeX operator names and the functions they stand for.
+const FUNCTIONS: [(&str, &str); 15] = [
+    ("sin", "sin"), ("cos", "cos"), ("tan", "tan"), ("arcsin", "asin"), ("arccos", "acos"), ("arctan", "atan"),
+    ("sinh", "sinh"), ("cosh", "cosh"), ("tanh", "tanh"), ("ln", "ln"), ("log", "log"), ("exp", "exp"),
+    ("min", "min"), ("max", "max"), ("operatorname", ""),
+];
+
+// Tokenizes a practical subset of LaTeX math into the tokens the lexer would produce for the same
+// expression in oscar's own syntax, e.g. `\frac{a+b}{2}\cdot\sqrt{x^2+1}` as `(($a+$b)/(2))*sqrt(($x^(2)+1))`.
+// Letters are variables, and juxtaposed operands are multiplied.
+pub struct LatexLexer {
+    tokens: VecDeque<(Token, Position)>,
+    start: Position,
+}
+
+impl LatexLexer {
+    pub fn new(input: &str) -> Self {
+        let mut translator = Translator {
+            chars: Chars::new(input.chars()),
+            tokens: Vec::new(),
+        };
+        if let Err(error) = translator.sequence(Close::End) {
+            let position = error.position.unwrap_or_else(|| translator.chars.position());
+            translator.tokens.push((Token::Error(error.message), position));
+        }
+        LatexLexer {
+            tokens: multiply(translator.tokens),
+            start: Position { line: 1, column: 1 },
+        }
+    }
+}
+
+impl Lexer for LatexLexer {
+    fn position(&self) -> Position {
+        self.start
+    }
+}
+
+impl Iterator for LatexLexer {
+    type Item = Token;
+    fn next(&mut self) -> Option<Self::Item> {
+        let (token, position) = self.tokens.pop_front()?;
+        self.start = position;
+        Some(token)
+    }
+}
+
+// What ends a run of LaTeX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Close {
+    End,
+    Brace,
+    Parenthesis,
+    Bracket,
+    // `\right` and its delimiter.
+    Right,
+}
+
+struct Translator<'a> {
+    chars: Chars<std::str::Chars<'a>>,
+    tokens: Vec<(Token, Position)>,
+}
+
+impl Translator<'_> {
+    fn emit(&mut self, token: Token, position: Position) {
+        self.tokens.push((token, position));
+    }
+    fn skip_space(&mut self) {
+        while self.chars.peek().is_some_and(|character| character.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+    fn fail<T>(&self, message: String, position: Position) -> Result<T, Error> {
+        Err(Error::at(ErrorCode::LexerError, message, position))
+    }
+    fn sequence(&mut self, close: Close) -> Result<(), Error> {
+        loop {
+            self.skip_space();
+            let position = self.chars.position();
+            let character = match self.chars.peek() {
+                Some(&character) => character,
+                None if close == Close::End => return Ok(()),
+                None => {
+                    let expected = match close {
+                        Close::Brace => "'}'",
+                        Close::Parenthesis => "')'",
+                        Close::Bracket => "']'",
+                        _ => "'\\right'",
+                    };
+                    return self.fail(format!("Expected {} before the end of the input.", expected), position);
+                }
+            };
+            match (character, close) {
+                ('}', Close::Brace) | (')', Close::Parenthesis) | (']', Close::Bracket) => {
+                    self.chars.next();
+                    return Ok(());
+                }
+                ('\\', Close::Right) if self.at_command("right") => return Ok(()),
+                ('}', _) | (')', _) | (']', _) => return self.fail(format!("Unmatched \'{}\'.", character), position),
+                _ => self.item()?,
+            }
+        }
+    }
+    // Whether `\name` comes next.
+    fn at_command(&mut self, name: &str) -> bool {
+        name.chars().enumerate().all(|(index, character)| self.chars.peek_nth(index + 1) == Some(&character))
+            && !self.chars.peek_nth(name.len() + 1).is_some_and(|character| character.is_alphabetic())
+    }
+    fn item(&mut self) -> Result<(), Error> {
+        let position = self.chars.position();
+        let character = match self.chars.next() {
+            Some(character) => character,
+            None => return self.fail("Expected an operand before the end of the input.".to_string(), position),
+        };
+        match character {
+            '{' | '(' | '[' => {
+                let close = match character {
+                    '{' => Close::Brace,
+                    '(' => Close::Parenthesis,
+                    _ => Close::Bracket,
+                };
+                self.emit(Token::LeftParenthesis, position);
+                self.sequence(close)?;
+                self.emit(Token::RightParenthesis, self.chars.position());
+            }
+            '+' => self.emit(token::ADDITION_TOKEN, position),
+            '-' => self.emit(token::SUBTRACTION_TOKEN, position),
+            '*' => self.emit(token::MULTIPLICATION_TOKEN, position),
+            '/' => self.emit(token::DIVISION_TOKEN, position),
+            '=' => self.emit(token::ASSIGNMENT_TOKEN, position),
+            ',' => self.emit(Token::Comma, position),
+            '^' => {
+                self.emit(token::POWER_TOKEN, position);
+                self.argument()?;
+            }
+            '\\' => self.command(position)?,
+            _ if character.is_ascii_digit() || character == '.' => {
+                let mut number = character.to_string();
+                while let Some(&character) = self.chars.peek() {
+                    if character.is_ascii_digit() || character == '.' {
+                        number.push(character);
+                        self.chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                match number.parse() {
+                    Ok(number) => self.emit(Token::Number(number), position),
+                    Err(_) => return self.fail(format!("Could not parse \'{}\' to f64.", number), position),
+                }
+            }
+            _ if character.is_alphabetic() => {
+                let name = self.subscripted(character.to_string())?;
+                self.emit(Token::Identifier(format!("${}", name)), position);
+            }
+            _ => return self.fail(format!("Unhandled character \'{}\'.", character), position),
+        }
+        Ok(())
+    }
+    // A single character or command, or a group in braces, as taken by `^`, `\frac` and `\sqrt`.
+    fn argument(&mut self) -> Result<(), Error> {
+        self.skip_space();
+        let position = self.chars.position();
+        match self.chars.peek() {
+            Some('{') => return self.item(),
+            Some(_) => {}
+            None => return self.fail("Expected an argument before the end of the input.".to_string(), position),
+        }
+        self.emit(Token::LeftParenthesis, position);
+        match self.chars.peek() {
+            // `x^23` is `x^{2}3`.
+            Some(&character) if character.is_ascii_digit() => {
+                self.chars.next();
+                self.emit(Token::Number(f64::from(character.to_digit(10).unwrap_or(0))), position);
+            }
+            _ => self.item()?,
+        }
+        self.emit(Token::RightParenthesis, self.chars.position());
+        Ok(())
+    }
+    // The subscript of a name, as in `x_1` or `x_{max}`, which becomes part of it.
+    fn subscripted(&mut self, mut name: String) -> Result<String, Error> {
+        if self.chars.peek() != Some(&'_') {
+            return Ok(name);
+        }
+        self.chars.next();
+        name.push('_');
+        let position = self.chars.position();
+        let braced = self.chars.peek() == Some(&'{');
+        if braced {
+            self.chars.next();
+        }
+        let start = name.len();
+        while let Some(&character) = self.chars.peek() {
+            if !character.is_alphanumeric() {
+                break;
+            }
+            name.push(character);
+            self.chars.next();
+            if !braced {
+                break;
+            }
+        }
+        if name.len() == start {
+            return self.fail("Expected a letter or digit as the subscript.".to_string(), position);
+        }
+        if braced {
+            if self.chars.peek() != Some(&'}') {
+                return self.fail("Subscripts may only contain letters and digits.".to_string(), self.chars.position());
+            }
+            self.chars.next();
+        }
+        Ok(name)
+    }
+    fn command(&mut self, position: Position) -> Result<(), Error> {
+        let mut name = String::new();
+        while let Some(&character) = self.chars.peek() {
+            if !character.is_alphabetic() {
+                break;
+            }
+            name.push(character);
+            self.chars.next();
+        }
+        if name.is_empty() {
+            if let Some(character) = self.chars.next() {
+                name.push(character);
+            }
+        }
+        match name.as_str() {
+            // Spacing.
+            "," | ";" | ":" | "!" | " " | "quad" | "qquad" => {}
+            "cdot" | "times" => self.emit(token::MULTIPLICATION_TOKEN, position),
+            "div" => self.emit(token::DIVISION_TOKEN, position),
+            "bmod" | "mod" => self.emit(token::REMAINDER_TOKEN, position),
+            "frac" | "dfrac" | "tfrac" => {
+                self.emit(Token::LeftParenthesis, position);
+                self.argument()?;
+                self.emit(token::DIVISION_TOKEN, position);
+                self.argument()?;
+                self.emit(Token::RightParenthesis, position);
+            }
+            "sqrt" => {
+                self.skip_space();
+                if self.chars.peek() != Some(&'[') {
+                    self.emit(Token::Function("sqrt".to_string(), 0), position);
+                    return self.argument();
+                }
+                // The n-th root is the power 1/n.
+                self.chars.next();
+                let mut index = Vec::new();
+                std::mem::swap(&mut self.tokens, &mut index);
+                let result = self.sequence(Close::Bracket);
+                std::mem::swap(&mut self.tokens, &mut index);
+                result?;
+                self.emit(Token::LeftParenthesis, position);
+                self.argument()?;
+                self.emit(token::POWER_TOKEN, position);
+                self.emit(Token::LeftParenthesis, position);
+                self.emit(Token::Number(1f64), position);
+                self.emit(token::DIVISION_TOKEN, position);
+                self.emit(Token::LeftParenthesis, position);
+                self.tokens.append(&mut index);
+                self.emit(Token::RightParenthesis, position);
+                self.emit(Token::RightParenthesis, position);
+                self.emit(Token::RightParenthesis, position);
+            }
+            "left" => {
+                self.skip_space();
+                let delimiter = self.delimiter(position)?;
+                if delimiter == "|" {
+                    self.emit(Token::Function("abs".to_string(), 0), position);
+                }
+                self.emit(Token::LeftParenthesis, position);
+                self.sequence(Close::Right)?;
+                let position = self.chars.position();
+                self.chars.nth("right".len());
+                self.skip_space();
+                self.delimiter(position)?;
+                self.emit(Token::RightParenthesis, position);
+            }
+            "right" => return self.fail("Unmatched '\\right'.".to_string(), position),
+            _ if GREEK.contains(&name.as_str()) => {
+                let name = self.subscripted(name)?;
+                self.emit(Token::Identifier(format!("${}", name)), position);
+            }
+            _ => match FUNCTIONS.iter().find(|(command, _)| *command == name) {
+                Some((_, function)) => self.function(function, position)?,
+                None => return self.fail(format!("Unsupported command \'\\{}\'.", name), position),
+            },
+        }
+        Ok(())
+    }
+    // A delimiter after `\left` or `\right`, where `.` stands for none.
+    fn delimiter(&mut self, position: Position) -> Result<String, Error> {
+        match self.chars.next() {
+            Some(character) if "()[].|".contains(character) => Ok(character.to_string()),
+            Some('\\') if matches!(self.chars.peek(), Some('{') | Some('}')) => Ok(self.chars.next().map(String::from).unwrap_or_default()),
+            _ => self.fail("Expected a delimiter after '\\left' or '\\right'.".to_string(), position),
+        }
+    }
+    // A function applied to the operand that follows it, as in `\sin x`, `\sin(x)` or `\sin^2 x`.
+    fn function(&mut self, function: &str, position: Position) -> Result<(), Error> {
+        let mut name = function.to_string();
+        if name.is_empty() {
+            self.skip_space();
+            if self.chars.next() != Some('{') {
+                return self.fail("Expected '{' after '\\operatorname'.".to_string(), position);
+            }
+            for character in self.chars.by_ref() {
+                if character == '}' {
+                    break;
+                }
+                name.push(character);
+            }
+        }
+        if name == "log" && self.chars.peek() == Some(&'_') {
+            name = match self.subscripted(name)?.as_str() {
+                "log_2" => "log2".to_string(),
+                "log_10" => "log".to_string(),
+                _ => return self.fail("Only logarithms to base 2 and 10 are supported.".to_string(), position),
+            };
+        }
+        self.skip_space();
+        let mut exponent = Vec::new();
+        if self.chars.peek() == Some(&'^') {
+            self.chars.next();
+            std::mem::swap(&mut self.tokens, &mut exponent);
+            let result = self.argument();
+            std::mem::swap(&mut self.tokens, &mut exponent);
+            result?;
+            self.emit(Token::LeftParenthesis, position);
+        }
+        self.emit(Token::Function(name, 0), position);
+        self.skip_space();
+        // Arguments in parentheses or braces are already grouped, and may be a list.
+        let grouped = match self.chars.peek() {
+            Some('(') | Some('{') => true,
+            Some('\\') => self.at_command("left"),
+            _ => false,
+        };
+        if grouped {
+            return self.item().and_then(|_| self.power(exponent, position));
+        }
+        self.emit(Token::LeftParenthesis, position);
+        self.item()?;
+        self.emit(Token::RightParenthesis, position);
+        self.power(exponent, position)
+    }
+    fn power(&mut self, mut exponent: Vec<(Token, Position)>, position: Position) -> Result<(), Error> {
+        if !exponent.is_empty() {
+            self.emit(Token::RightParenthesis, position);
+            self.emit(token::POWER_TOKEN, position);
+            self.tokens.append(&mut exponent);
+        }
+        Ok(())
+    }
+}
+
+// Makes the multiplication of juxtaposed operands, as in `2x` or `a(b + c)`, explicit.
+fn multiply(tokens: Vec<(Token, Position)>) -> VecDeque<(Token, Position)> {
+    let mut output = VecDeque::<(Token, Position)>::with_capacity(tokens.len());
+    for (token, position) in tokens {
+        let ends = matches!(output.back(), Some((Token::Number(_), _)) | Some((Token::Identifier(_), _)) | Some((Token::RightParenthesis, _)));
+        let starts = matches!(token, Token::Number(_) | Token::Identifier(_) | Token::LeftParenthesis | Token::Function(_, _));
+        if ends && starts {
+            output.push_back((token::MULTIPLICATION_TOKEN, position));
+        }
+        output.push_back((token, position));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grover::{eval, parse, parse_latex, ErrorCode, Evaluator, Value};
+
+    fn evaluate(input: &str) -> Value {
+        let mut evaluator = Evaluator::new();
+        eval("$a = 3; $b = 5; $x = 2; $x_1 = 4", &mut evaluator).unwrap();
+        evaluator.evaluate(parse_latex(input).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parses_to_the_same_tokens() {
+        assert_eq!(parse_latex("\\frac{a+b}{2}\\cdot\\sqrt{x^2+1}").unwrap(), parse("($a + $b) / 2 * sqrt($x ^ 2 + 1)").unwrap());
+        assert_eq!(parse_latex("2 \\times \\pi").unwrap(), parse("2 * $pi").unwrap());
+    }
+
+    #[test]
+    fn evaluates_common_notation() {
+        assert_eq!(evaluate("x_{1}^{2}"), Value::Number(16f64));
+        assert_eq!(evaluate("x_1"), Value::Number(4f64));
+        assert_eq!(evaluate("\\sin\\left( \\frac{\\pi}{2} \\right)"), Value::Number(1f64));
+        assert_eq!(evaluate("\\sqrt[3]{8}"), Value::Number(2f64));
+        // Juxtaposition multiplies.
+        assert_eq!(evaluate("2x + a b"), Value::Number(19f64));
+    }
+
+    #[test]
+    fn reports_what_it_can_not_read() {
+        let error = parse_latex("\\foo{1}").unwrap_err();
+        assert_eq!((error.code, error.message.as_str()), (ErrorCode::LexerError, "Unsupported command '\\foo'."));
+        assert_eq!(parse_latex("\\frac{1}{").unwrap_err().code, ErrorCode::LexerError);
+    }
+}
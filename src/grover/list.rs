@@ -0,0 +1,235 @@
+use crate::grover::{error, evaluator, function, matrix, token, value};
+use error::Error;
+use error::ErrorCode;
+use evaluator::Evaluator;
+use function::Function;
+use matrix::Matrix;
+use token::Tokens;
+use value::Value;
+
+// Lists are vectors, usually rows as in `[3, 1, 4]`.
+
+// The most elements a range may make.
+pub const MAX_LENGTH: usize = 1_000_000;
+
+// A function taking a list and a lambda, which it calls for the elements of the list.
+pub struct HigherOrder {
+    pub name: &'static str,
+    // Argument counts it takes: the list and the lambda, then anything else.
+    pub arities: &'static [usize],
+    // Parameters the lambda takes.
+    pub parameters: usize,
+    pub example: &'static str,
+}
+
+pub const HIGHER_ORDER: [HigherOrder; 6] = [
+    HigherOrder { name: "map", arities: &[2], parameters: 1, example: "map([1, 2, 3], $x -> $x ^ 2)" },
+    HigherOrder { name: "filter", arities: &[2], parameters: 1, example: "filter(1..10, $x -> $x % 2 == 0)" },
+    HigherOrder { name: "reduce", arities: &[2, 3], parameters: 2, example: "reduce([1, 2, 3], ($a, $b) -> $a * $b)" },
+    HigherOrder { name: "sort", arities: &[2], parameters: 1, example: "sort([3, -1, 2], $x -> abs($x))" },
+    HigherOrder { name: "sum", arities: &[2], parameters: 1, example: "sum(1..10, $x -> $x ^ 2)" },
+    HigherOrder { name: "prod", arities: &[2], parameters: 1, example: "prod(1..5, $x -> 1 + 1 / $x)" },
+];
+
+pub fn higher_order(name: &str) -> Option<&'static HigherOrder> {
+    HIGHER_ORDER.iter().find(|function| function.name == name)
+}
+
+// Calls a higher-order function, given its list, its lambda and any other arguments.
+pub fn apply(evaluator: &mut Evaluator, function: &HigherOrder, list: Value, parameters: &[String], body: &Tokens, rest: Vec<Value>) -> Result<Value, Error> {
+    let name = function.name;
+    if parameters.len() != function.parameters {
+        return Err(Error::new(
+            ErrorCode::ArityMismatch,
+            format!("The lambda passed to \'{}\' must take {} parameter{}, as in {}.", name, function.parameters, if function.parameters == 1 { "" } else { "s" }, function.example),
+        ));
+    }
+    let result = elements(evaluator, name, &list.matrix(), parameters, body, rest);
+    result.map_err(|error| Error::new(error.code, format!("Could not evaluate \'{}\'.", name)).with_cause(error))
+}
+
+fn elements(evaluator: &mut Evaluator, name: &str, list: &Matrix, parameters: &[String], body: &Tokens, rest: Vec<Value>) -> Result<Value, Error> {
+    let mut call = |arguments: Vec<Value>| evaluator.apply(parameters, body, arguments);
+    match name {
+        "map" => {
+            let mut data = Vec::<f64>::with_capacity(list.data().len());
+            for element in list.data() {
+                data.push(call(vec![Value::Number(*element)])?.number()?);
+            }
+            Ok(Value::Matrix(Matrix::new(list.rows(), list.columns(), data)))
+        }
+        "filter" => {
+            let mut data = Vec::<f64>::new();
+            for element in list.data() {
+                if call(vec![Value::Number(*element)])?.number()? != 0f64 {
+                    data.push(*element);
+                }
+            }
+            Ok(Value::Matrix(vector(list, data)))
+        }
+        "reduce" => {
+            let mut elements = list.data().iter().map(|element| Value::Number(*element));
+            let mut accumulator = match rest.into_iter().next().or_else(|| elements.next()) {
+                Some(initial) => initial,
+                None => return Err(Error::new(ErrorCode::DomainError, "An empty list can only be reduced from an initial value.".to_string())),
+            };
+            for element in elements {
+                accumulator = call(vec![accumulator, element])?;
+            }
+            Ok(accumulator)
+        }
+        "sort" => {
+            let mut keyed = Vec::<(f64, f64)>::with_capacity(list.data().len());
+            for element in list.data() {
+                keyed.push((call(vec![Value::Number(*element)])?.number()?, *element));
+            }
+            keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+            Ok(Value::Matrix(vector(list, keyed.into_iter().map(|(_, element)| element).collect())))
+        }
+        "sum" | "prod" => {
+            let (operator, mut total) = if name == "sum" { (&token::ADDITION_OPERATOR, Value::Number(0f64)) } else { (&token::MULTIPLICATION_OPERATOR, Value::Number(1f64)) };
+            for element in list.data() {
+                total = value::operate(operator, &total, &call(vec![Value::Number(*element)])?)?;
+            }
+            Ok(total)
+        }
+        _ => Err(Error::new(ErrorCode::EvaluatorError, format!("Unhandled function \'{}\'.", name))),
+    }
+}
+
+// Elements picked from a list, laid out as it was: a column stays a column.
+fn vector(list: &Matrix, data: Vec<f64>) -> Matrix {
+    if data.is_empty() {
+        Matrix::zeros(0, 0)
+    } else if list.columns() == 1 && list.rows() > 1 {
+        Matrix::new(data.len(), 1, data)
+    } else {
+        Matrix::new(1, data.len(), data)
+    }
+}
+
+// The numbers from start to stop, both included, step apart.
+pub fn range(start: f64, stop: f64, step: f64) -> Result<Matrix, Error> {
+    if step == 0f64 {
+        return Err(Error::new(ErrorCode::DomainError, "The step of a range can not be 0.".to_string()));
+    }
+    // Allowing for rounding, so 0.1 steps from 0 still reach 1.
+    let steps = ((stop - start) / step + 1e-9).floor();
+    if steps >= MAX_LENGTH as f64 {
+        return Err(Error::new(ErrorCode::DomainError, format!("The range from {} to {} by {} has more than {} elements.", start, stop, step, MAX_LENGTH)));
+    }
+    if steps < 0f64 {
+        return Ok(Matrix::zeros(0, 0));
+    }
+    // Dividing by a whole number of steps per unit gives 0.3 for the fourth step of 0.1, where multiplying gives 0.30000000000000004.
+    let per_unit = 1f64 / step;
+    let data = (0..=steps as usize)
+        .map(|index| if per_unit.fract() == 0f64 { start + index as f64 / per_unit } else { start + index as f64 * step })
+        .collect::<Vec<f64>>();
+    Ok(Matrix::new(1, data.len(), data))
+}
+
+// `start..stop`, counting down when stop is below start.
+pub fn span(start: f64, stop: f64) -> Result<Matrix, Error> {
+    range(start, stop, if stop < start { -1f64 } else { 1f64 })
+}
+
+// Picks out elements, counting from 1 at the start or from -1 at the end, where a list of indices picks out several.
+// One index counts along a vector, or picks out rows of a matrix.
+pub fn index(value: Value, indices: &[Value]) -> Result<Value, Error> {
+    let matrix = value.matrix();
+    let all = |length: usize| (0..length).collect::<Vec<usize>>();
+    let (rows, columns) = match indices {
+        [index] if matrix.rows() == 1 => (all(1), positions(index, matrix.columns())?),
+        [index] if matrix.columns() == 1 => (positions(index, matrix.rows())?, all(1)),
+        [index] => (positions(index, matrix.rows())?, all(matrix.columns())),
+        [row, column] => (positions(row, matrix.rows())?, positions(column, matrix.columns())?),
+        _ => {
+            return Err(Error::new(ErrorCode::ShapeMismatch, format!("A matrix takes one or two indices, {} were given.", indices.len())));
+        }
+    };
+    let selected = matrix.select(&rows, &columns);
+    // Single elements come out as numbers, though a list of one index still makes a list.
+    if selected.rows() == 1 && selected.columns() == 1 && indices.iter().all(|index| matches!(index, Value::Number(_))) {
+        return Ok(Value::Number(selected.get(0, 0)));
+    }
+    Ok(Value::Matrix(selected))
+}
+
+fn positions(index: &Value, length: usize) -> Result<Vec<usize>, Error> {
+    let numbers = match index {
+        Value::Number(number) => vec![*number],
+        Value::Matrix(matrix) => matrix.data().to_vec(),
+    };
+    numbers.into_iter().map(|number| position(number, length)).collect()
+}
+
+fn position(index: f64, length: usize) -> Result<usize, Error> {
+    if index.fract() != 0f64 {
+        return Err(Error::new(ErrorCode::IndexOutOfRange, format!("The index {} is not a whole number.", index)));
+    }
+    let position = if index < 0f64 { length as f64 + index } else { index - 1f64 };
+    if index == 0f64 || position < 0f64 || position >= length as f64 {
+        return Err(Error::new(ErrorCode::IndexOutOfRange, format!("The index {} is out of range for a length of {}.", index, length)));
+    }
+    Ok(position as usize)
+}
+
+fn list(value: &Value) -> Matrix {
+    value.clone().matrix()
+}
+
+pub fn builtins() -> Vec<(&'static str, Function)> {
+    vec![
+        ("len", Function::values(1, |x| Ok(Value::Number(list(&x[0]).data().len() as f64)))),
+        ("sort", Function::values(1, |x| {
+            let list = list(&x[0]);
+            let mut data = list.data().to_vec();
+            data.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            Ok(Value::Matrix(vector(&list, data)))
+        })),
+        ("range", Function::values(3, |x| Ok(Value::Matrix(range(x[0].number()?, x[1].number()?, x[2].number()?)?)))),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(values: &[f64]) -> Value {
+        Value::Matrix(Matrix::new(1, values.len(), values.to_vec()))
+    }
+
+    #[test]
+    fn counts_from_one() {
+        let values = list(&[10f64, 20f64, 30f64]);
+        assert_eq!(index(values.clone(), &[Value::Number(1f64)]).unwrap(), Value::Number(10f64));
+        assert_eq!(index(values, &[Value::Number(3f64)]).unwrap(), Value::Number(30f64));
+    }
+
+    #[test]
+    fn counts_back_from_the_end() {
+        let values = list(&[10f64, 20f64, 30f64]);
+        assert_eq!(index(values.clone(), &[Value::Number(-1f64)]).unwrap(), Value::Number(30f64));
+        assert_eq!(index(values, &[Value::Number(-3f64)]).unwrap(), Value::Number(10f64));
+    }
+
+    #[test]
+    fn picks_out_several() {
+        let values = list(&[10f64, 20f64, 30f64]);
+        assert_eq!(index(values.clone(), &[list(&[3f64, 1f64])]).unwrap(), list(&[30f64, 10f64]));
+        assert_eq!(index(values, &[list(&[2f64])]).unwrap(), list(&[20f64]));
+        let matrix = Value::Matrix(Matrix::new(2, 2, vec![1f64, 2f64, 3f64, 4f64]));
+        assert_eq!(index(matrix.clone(), &[Value::Number(2f64), Value::Number(1f64)]).unwrap(), Value::Number(3f64));
+        assert_eq!(index(matrix, &[Value::Number(2f64)]).unwrap(), list(&[3f64, 4f64]));
+    }
+
+    #[test]
+    fn refuses_indices_out_of_range() {
+        let values = list(&[10f64, 20f64, 30f64]);
+        for bad in [0f64, 4f64, -4f64, 1.5f64] {
+            assert_eq!(index(values.clone(), &[Value::Number(bad)]).unwrap_err().code, ErrorCode::IndexOutOfRange, "{}", bad);
+        }
+        assert_eq!(index(values, &[Value::Number(1f64), Value::Number(1f64), Value::Number(1f64)]).unwrap_err().code, ErrorCode::ShapeMismatch);
+    }
+}
@@ -0,0 +1,465 @@
+use crate::grover::{error, function, value};
+use error::Error;
+use error::ErrorCode;
+use function::Function;
+use value::Value;
+
+// A dense matrix of finite numbers, stored row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    columns: usize,
+    data: Vec<f64>,
+}
+
+// Pivots smaller than this, relative to the largest element, are taken to be zero.
+const TOLERANCE: f64 = 1e-12;
+
+// A factorization `P * A = L * U`, with the row swaps it took.
+pub struct Lu {
+    pub lower: Matrix,
+    pub upper: Matrix,
+    pub permutation: Matrix,
+    pub swaps: usize,
+}
+
+impl Matrix {
+    pub fn new(rows: usize, columns: usize, data: Vec<f64>) -> Self {
+        debug_assert_eq!(rows * columns, data.len());
+        Matrix { rows, columns, data }
+    }
+    pub fn zeros(rows: usize, columns: usize) -> Self {
+        Matrix::new(rows, columns, vec![0f64; rows * columns])
+    }
+    pub fn identity(size: usize) -> Self {
+        let mut identity = Matrix::zeros(size, size);
+        for index in 0..size {
+            identity.set(index, index, 1f64);
+        }
+        identity
+    }
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+    pub fn data(&self) -> &[f64] {
+        &self.data
+    }
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        self.data[row * self.columns + column]
+    }
+    pub fn set(&mut self, row: usize, column: usize, value: f64) {
+        self.data[row * self.columns + column] = value;
+    }
+    // As in "2 by 3", for error messages.
+    pub fn shape(&self) -> String {
+        format!("{} by {}", self.rows, self.columns)
+    }
+    pub fn is_square(&self) -> bool {
+        self.rows == self.columns
+    }
+    // The elements at the given rows and columns, in that order.
+    pub fn select(&self, rows: &[usize], columns: &[usize]) -> Matrix {
+        let data = rows.iter().flat_map(|row| columns.iter().map(move |column| self.get(*row, *column))).collect();
+        Matrix::new(rows.len(), columns.len(), data)
+    }
+    pub fn transpose(&self) -> Matrix {
+        let mut transpose = Matrix::zeros(self.columns, self.rows);
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                transpose.set(column, row, self.get(row, column));
+            }
+        }
+        transpose
+    }
+    // Joins matrices with the same number of rows side by side.
+    pub fn beside(parts: &[Matrix]) -> Result<Matrix, Error> {
+        let rows = parts.first().map_or(0, |part| part.rows);
+        if let Some(part) = parts.iter().find(|part| part.rows != rows) {
+            return Err(Error::new(ErrorCode::ShapeMismatch, format!("Can not put a {} matrix beside one with {} rows.", part.shape(), rows)));
+        }
+        let columns = parts.iter().map(|part| part.columns).sum();
+        let mut data = Vec::<f64>::with_capacity(rows * columns);
+        for row in 0..rows {
+            for part in parts {
+                data.extend_from_slice(&part.data[row * part.columns..(row + 1) * part.columns]);
+            }
+        }
+        Ok(Matrix::new(rows, columns, data))
+    }
+    // Stacks matrices with the same number of columns on top of each other.
+    pub fn stack(parts: &[Matrix]) -> Result<Matrix, Error> {
+        let columns = parts.first().map_or(0, |part| part.columns);
+        if let Some(part) = parts.iter().find(|part| part.columns != columns) {
+            return Err(Error::new(ErrorCode::ShapeMismatch, format!("Can not stack a {} matrix under one with {} columns.", part.shape(), columns)));
+        }
+        let rows = parts.iter().map(|part| part.rows).sum();
+        Ok(Matrix::new(rows, columns, parts.iter().flat_map(|part| part.data.iter().copied()).collect()))
+    }
+    pub fn product(&self, other: &Matrix) -> Result<Matrix, Error> {
+        if self.columns != other.rows {
+            return Err(Error::new(ErrorCode::ShapeMismatch, format!("Can not multiply a {} matrix by a {} matrix.", self.shape(), other.shape())));
+        }
+        let mut product = Matrix::zeros(self.rows, other.columns);
+        for row in 0..self.rows {
+            for column in 0..other.columns {
+                let sum: f64 = (0..self.columns).map(|index| self.get(row, index) * other.get(index, column)).sum();
+                product.set(row, column, value::finite(sum)?);
+            }
+        }
+        Ok(product)
+    }
+    // Whole powers, by repeated squaring. Negative powers are powers of the inverse.
+    pub fn power(&self, exponent: f64) -> Result<Matrix, Error> {
+        if !self.is_square() {
+            return Err(Error::new(ErrorCode::ShapeMismatch, format!("Can not raise a {} matrix to a power, it is not square.", self.shape())));
+        }
+        if exponent.fract() != 0f64 {
+            return Err(Error::new(ErrorCode::DomainError, format!("Can not raise a matrix to the power {}, only to whole powers.", exponent)));
+        }
+        let mut base = if exponent < 0f64 { self.inverse()? } else { self.clone() };
+        let mut remaining = exponent.abs();
+        let mut result = Matrix::identity(self.rows);
+        while remaining > 0f64 {
+            if remaining % 2f64 == 1f64 {
+                result = result.product(&base)?;
+            }
+            remaining = (remaining / 2f64).floor();
+            if remaining > 0f64 {
+                base = base.product(&base)?;
+            }
+        }
+        Ok(result)
+    }
+    fn scale(&self) -> f64 {
+        self.data.iter().fold(0f64, |largest, element| largest.max(element.abs()))
+    }
+    fn square(&self, operation: &str) -> Result<(), Error> {
+        if !self.is_square() {
+            return Err(Error::new(ErrorCode::ShapeMismatch, format!("Can not take the {} of a {} matrix, it is not square.", operation, self.shape())));
+        }
+        Ok(())
+    }
+    // Gaussian elimination with partial pivoting. A zero pivot leaves its column as it is.
+    pub fn lu(&self) -> Result<Lu, Error> {
+        self.square("LU decomposition")?;
+        let size = self.rows;
+        let tolerance = TOLERANCE * self.scale();
+        let mut upper = self.clone();
+        let mut lower = Matrix::identity(size);
+        let mut order: Vec<usize> = (0..size).collect();
+        let mut swaps = 0;
+        for column in 0..size {
+            let pivot = (column..size).max_by(|a, b| upper.get(*a, column).abs().total_cmp(&upper.get(*b, column).abs())).unwrap_or(column);
+            if upper.get(pivot, column).abs() <= tolerance {
+                continue;
+            }
+            if pivot != column {
+                swaps += 1;
+                order.swap(pivot, column);
+                for index in 0..size {
+                    let (a, b) = (upper.get(column, index), upper.get(pivot, index));
+                    upper.set(column, index, b);
+                    upper.set(pivot, index, a);
+                }
+                for index in 0..column {
+                    let (a, b) = (lower.get(column, index), lower.get(pivot, index));
+                    lower.set(column, index, b);
+                    lower.set(pivot, index, a);
+                }
+            }
+            for row in column + 1..size {
+                let factor = upper.get(row, column) / upper.get(column, column);
+                lower.set(row, column, factor);
+                for index in column..size {
+                    upper.set(row, index, upper.get(row, index) - factor * upper.get(column, index));
+                }
+                // Exactly zero below the pivot, rather than whatever rounding left.
+                upper.set(row, column, 0f64);
+            }
+        }
+        let mut permutation = Matrix::zeros(size, size);
+        for (row, original) in order.iter().enumerate() {
+            permutation.set(row, *original, 1f64);
+        }
+        Ok(Lu { lower, upper, permutation, swaps })
+    }
+    pub fn determinant(&self) -> Result<f64, Error> {
+        self.square("determinant")?;
+        let lu = self.lu()?;
+        let sign = if lu.swaps % 2 == 0 { 1f64 } else { -1f64 };
+        value::finite((0..self.rows).fold(sign, |product, index| product * lu.upper.get(index, index)))
+    }
+    // Solves `A * X = B` for each column of B.
+    pub fn solve(&self, rhs: &Matrix) -> Result<Matrix, Error> {
+        self.square("solution")?;
+        if rhs.rows != self.rows {
+            return Err(Error::new(ErrorCode::ShapeMismatch, format!("Can not solve for a {} right-hand side with a {} matrix.", rhs.shape(), self.shape())));
+        }
+        let lu = self.lu()?;
+        let size = self.rows;
+        let tolerance = TOLERANCE * self.scale();
+        if (0..size).any(|index| lu.upper.get(index, index).abs() <= tolerance) {
+            return Err(Error::new(ErrorCode::DomainError, "The matrix is singular, so the system has no single solution.".to_string()));
+        }
+        let mut solution = lu.permutation.product(rhs)?;
+        for column in 0..rhs.columns {
+            for row in 0..size {
+                let sum: f64 = (0..row).map(|index| lu.lower.get(row, index) * solution.get(index, column)).sum();
+                solution.set(row, column, solution.get(row, column) - sum);
+            }
+            for row in (0..size).rev() {
+                let sum: f64 = (row + 1..size).map(|index| lu.upper.get(row, index) * solution.get(index, column)).sum();
+                solution.set(row, column, value::finite((solution.get(row, column) - sum) / lu.upper.get(row, row))?);
+            }
+        }
+        Ok(solution)
+    }
+    pub fn inverse(&self) -> Result<Matrix, Error> {
+        self.square("inverse")?;
+        self.solve(&Matrix::identity(self.rows))
+            .map_err(|error| if error.code == ErrorCode::DomainError { Error::new(ErrorCode::DomainError, "The matrix is singular, so it has no inverse.".to_string()) } else { error })
+    }
+    // The number of rows left once elimination has cleared every row it can.
+    pub fn rank(&self) -> usize {
+        let tolerance = TOLERANCE * self.scale() * self.rows.max(self.columns) as f64;
+        let mut reduced = self.clone();
+        let mut rank = 0;
+        for column in 0..self.columns {
+            if rank == self.rows {
+                break;
+            }
+            let pivot = (rank..self.rows).max_by(|a, b| reduced.get(*a, column).abs().total_cmp(&reduced.get(*b, column).abs())).unwrap_or(rank);
+            if reduced.get(pivot, column).abs() <= tolerance {
+                continue;
+            }
+            for index in 0..self.columns {
+                let (a, b) = (reduced.get(rank, index), reduced.get(pivot, index));
+                reduced.set(rank, index, b);
+                reduced.set(pivot, index, a);
+            }
+            for row in rank + 1..self.rows {
+                let factor = reduced.get(row, column) / reduced.get(rank, column);
+                for index in column..self.columns {
+                    reduced.set(row, index, reduced.get(row, index) - factor * reduced.get(rank, index));
+                }
+            }
+            rank += 1;
+        }
+        rank
+    }
+    // Householder reflections, giving an orthogonal Q and an upper triangular R with `A = Q * R`.
+    pub fn qr(&self) -> (Matrix, Matrix) {
+        let (rows, columns) = (self.rows, self.columns);
+        let mut q = Matrix::identity(rows);
+        let mut r = self.clone();
+        for column in 0..columns.min(rows.saturating_sub(1)) {
+            let norm = (column..rows).map(|row| r.get(row, column).powi(2)).sum::<f64>().sqrt();
+            if norm == 0f64 {
+                continue;
+            }
+            let alpha = if r.get(column, column) > 0f64 { -norm } else { norm };
+            let mut reflector: Vec<f64> = (column..rows).map(|row| r.get(row, column)).collect();
+            reflector[0] -= alpha;
+            let length = reflector.iter().map(|element| element * element).sum::<f64>();
+            if length == 0f64 {
+                continue;
+            }
+            for index in 0..columns {
+                let dot: f64 = (column..rows).map(|row| reflector[row - column] * r.get(row, index)).sum();
+                for row in column..rows {
+                    r.set(row, index, r.get(row, index) - 2f64 * reflector[row - column] * dot / length);
+                }
+            }
+            for index in 0..rows {
+                let dot: f64 = (column..rows).map(|row| q.get(index, row) * reflector[row - column]).sum();
+                for row in column..rows {
+                    q.set(index, row, q.get(index, row) - 2f64 * dot * reflector[row - column] / length);
+                }
+            }
+            for row in column + 1..rows {
+                r.set(row, column, 0f64);
+            }
+        }
+        // With a non-negative diagonal, the factorization of a full rank matrix is unique.
+        for index in 0..rows.min(columns) {
+            if r.get(index, index) < 0f64 {
+                for column in 0..columns {
+                    r.set(index, column, -r.get(index, column));
+                }
+                for row in 0..rows {
+                    q.set(row, index, -q.get(row, index));
+                }
+            }
+        }
+        (q, r)
+    }
+    // Real eigenvalues in increasing order, from the characteristic polynomial of matrices up to 3 by 3.
+    pub fn eigenvalues(&self) -> Result<Vec<f64>, Error> {
+        self.square("eigenvalues")?;
+        let complex = || Error::new(ErrorCode::DomainError, "The matrix has complex eigenvalues.".to_string());
+        let mut eigenvalues = match self.rows {
+            0 => Vec::new(),
+            1 => vec![self.get(0, 0)],
+            2 => {
+                let half = (self.get(0, 0) + self.get(1, 1)) / 2f64;
+                let discriminant = half * half - self.determinant()?;
+                if discriminant < -TOLERANCE * half.abs().max(1f64).powi(2) {
+                    return Err(complex());
+                }
+                let root = discriminant.max(0f64).sqrt();
+                vec![half - root, half + root]
+            }
+            3 => {
+                let a = |row, column| self.get(row, column);
+                let trace = a(0, 0) + a(1, 1) + a(2, 2);
+                let minors = a(0, 0) * a(1, 1) - a(0, 1) * a(1, 0) + a(0, 0) * a(2, 2) - a(0, 2) * a(2, 0) + a(1, 1) * a(2, 2) - a(1, 2) * a(2, 1);
+                cubic(trace, minors, self.determinant()?).ok_or_else(complex)?
+            }
+            _ => {
+                return Err(Error::new(ErrorCode::ShapeMismatch, format!("Eigenvalues are only found for matrices up to 3 by 3, not {}.", self.shape())));
+            }
+        };
+        eigenvalues.sort_by(f64::total_cmp);
+        Ok(eigenvalues.into_iter().map(|eigenvalue| eigenvalue + 0f64).collect())
+    }
+}
+
+// The roots of `x^3 - a x^2 + b x - c` when all three are real, refined by Newton's method.
+fn cubic(a: f64, b: f64, c: f64) -> Option<Vec<f64>> {
+    let shift = a / 3f64;
+    // Substituting `x = t + a / 3` leaves `t^3 + p t + q`.
+    let p = b - a * a / 3f64;
+    let q = -2f64 * a.powi(3) / 27f64 + a * b / 3f64 - c;
+    let scale = a.abs().max(b.abs().sqrt()).max(c.abs().cbrt()).max(1f64);
+    let roots: Vec<f64> = if p.abs() <= TOLERANCE * scale * scale {
+        if q.abs() > TOLERANCE * scale.powi(3) {
+            return None;
+        }
+        vec![0f64; 3]
+    } else {
+        if p > 0f64 || 4f64 * p.powi(3) + 27f64 * q * q > TOLERANCE * scale.powi(6) {
+            return None;
+        }
+        let radius = 2f64 * (-p / 3f64).sqrt();
+        let angle = (3f64 * q / (p * radius)).clamp(-1f64, 1f64).acos() / 3f64;
+        (0..3).map(|k| radius * (angle - 2f64 * std::f64::consts::PI * k as f64 / 3f64).cos()).collect()
+    };
+    let polynomial = |x: f64| ((x - a) * x + b) * x - c;
+    let slope = |x: f64| (3f64 * x - 2f64 * a) * x + b;
+    Some(roots
+        .into_iter()
+        .map(|root| {
+            let mut x = root + shift;
+            for _ in 0..3 {
+                let step = polynomial(x) / slope(x);
+                if !step.is_finite() {
+                    break;
+                }
+                x -= step;
+            }
+            x
+        })
+        .collect())
+}
+
+impl std::fmt::Display for Matrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows: Vec<String> = (0..self.rows)
+            .map(|row| {
+                let elements: Vec<String> = (0..self.columns).map(|column| (self.get(row, column) + 0f64).to_string()).collect();
+                elements.join(", ")
+            })
+            .collect();
+        write!(f, "[{}]", rows.join("; "))
+    }
+}
+
+fn matrix(name: &str, value: &Value) -> Result<Matrix, Error> {
+    match value {
+        Value::Matrix(matrix) => Ok(matrix.clone()),
+        Value::Number(_) => Err(Error::new(ErrorCode::ShapeMismatch, format!("\'{}\' takes a matrix, not a number.", name))),
+    }
+}
+
+fn column(values: Vec<f64>) -> Value {
+    Value::Matrix(Matrix::new(values.len(), 1, values))
+}
+
+// Factorizations give more than one matrix, and a value holds only one, so each factor has its own function.
+pub const FACTORS: [(&str, &[&str]); 2] = [("lu", &["lu_l", "lu_u", "lu_p"]), ("qr", &["qr_q", "qr_r"])];
+
+pub fn factors(name: &str) -> Option<&'static [&'static str]> {
+    FACTORS.iter().find(|(factorization, _)| *factorization == name).map(|(_, factors)| *factors)
+}
+
+pub fn builtins() -> Vec<(&'static str, Function)> {
+    vec![
+        ("transpose", Function::values(1, |x| Ok(Value::Matrix(x[0].clone().matrix().transpose())))),
+        ("det", Function::values(1, |x| Ok(Value::Number(matrix("det", &x[0])?.determinant()?)))),
+        ("inv", Function::values(1, |x| Ok(Value::Matrix(matrix("inv", &x[0])?.inverse()?)))),
+        ("rank", Function::values(1, |x| Ok(Value::Number(x[0].clone().matrix().rank() as f64)))),
+        ("lu_l", Function::values(1, |x| Ok(Value::Matrix(matrix("lu_l", &x[0])?.lu()?.lower)))),
+        ("lu_u", Function::values(1, |x| Ok(Value::Matrix(matrix("lu_u", &x[0])?.lu()?.upper)))),
+        ("lu_p", Function::values(1, |x| Ok(Value::Matrix(matrix("lu_p", &x[0])?.lu()?.permutation)))),
+        ("qr_q", Function::values(1, |x| Ok(Value::Matrix(matrix("qr_q", &x[0])?.qr().0)))),
+        ("qr_r", Function::values(1, |x| Ok(Value::Matrix(matrix("qr_r", &x[0])?.qr().1)))),
+        ("eig", Function::values(1, |x| Ok(column(matrix("eig", &x[0])?.eigenvalues()?)))),
+        ("linsolve", Function::values(2, |x| Ok(Value::Matrix(matrix("linsolve", &x[0])?.solve(&x[1].clone().matrix())?)))),
+        ("identity", Function::values(1, |x| {
+            let size = x[0].number()?;
+            if size.fract() != 0f64 || !(0f64..=1000f64).contains(&size) {
+                return Err(Error::new(ErrorCode::DomainError, format!("identity({}) needs a whole size from 0 to 1000.", size)));
+            }
+            Ok(Value::Matrix(Matrix::identity(size as usize)))
+        })),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::{eval, Evaluator};
+
+    fn evaluate(input: &str) -> Result<Value, Error> {
+        let mut evaluator = Evaluator::new();
+        eval("$a = [1, 2; 3, 4]", &mut evaluator).unwrap();
+        eval(input, &mut evaluator)
+    }
+
+    fn close(input: &str, expected: &[f64]) {
+        let found = evaluate(input).unwrap().matrix();
+        assert_eq!(found.data().len(), expected.len(), "{}", input);
+        assert!(found.data().iter().zip(expected).all(|(a, b)| (a - b).abs() <= 1e-12), "{} is {:?}", input, found.data());
+    }
+
+    #[test]
+    fn multiplies_as_matrices_or_element_by_element() {
+        close("$a * $a", &[7f64, 10f64, 15f64, 22f64]);
+        close("$a ^ 2", &[7f64, 10f64, 15f64, 22f64]);
+        close("$a .* $a", &[1f64, 4f64, 9f64, 16f64]);
+        close("$a + 1", &[2f64, 3f64, 4f64, 5f64]);
+        close("transpose($a)", &[1f64, 3f64, 2f64, 4f64]);
+    }
+
+    #[test]
+    fn solves_and_decomposes() {
+        assert_eq!(evaluate("det($a)").unwrap(), Value::Number(-2f64));
+        assert_eq!(evaluate("rank([1, 2; 2, 4])").unwrap(), Value::Number(1f64));
+        close("linsolve($a, [5; 6])", &[-4f64, 4.5]);
+        close("inv($a) * $a", &[1f64, 0f64, 0f64, 1f64]);
+        close("lu_p($a) * $a - lu_l($a) * lu_u($a)", &[0f64; 4]);
+        close("qr_q($a) * qr_r($a) - $a", &[0f64; 4]);
+        close("eig([2, 1; 1, 2])", &[1f64, 3f64]);
+    }
+
+    #[test]
+    fn reports_shapes_and_singular_matrices() {
+        assert_eq!(evaluate("[1, 2] + [1, 2, 3]").unwrap_err().code, ErrorCode::ShapeMismatch);
+        assert_eq!(evaluate("[1, 2] * [1, 2]").unwrap_err().code, ErrorCode::ShapeMismatch);
+        assert_eq!(evaluate("inv([1, 2; 2, 4])").unwrap_err().message, "The matrix is singular, so it has no inverse.");
+        assert_eq!(evaluate("eig([0, -1; 1, 0])").unwrap_err().code, ErrorCode::DomainError);
+    }
+}
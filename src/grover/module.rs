@@ -0,0 +1,224 @@
+use crate::grover::{error, evaluator, function, parser, token};
+use error::Error;
+use error::ErrorCode;
+use evaluator::Evaluator;
+use function::Definition;
+use function::Function;
+use parser::Parser;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use token::Position;
+use token::Token;
+use token::TokenIterator;
+use token::Tokens;
+
+// Extension added to module names in `use` statements.
+pub const EXTENSION: &str = "calc";
+
+// Where imports are looked for, and which files are being imported right now.
+#[derive(Debug, Clone, Default)]
+pub struct Modules {
+    paths: Vec<PathBuf>,
+    // Canonical paths of the files being imported, outermost first.
+    stack: Vec<PathBuf>,
+}
+
+impl Modules {
+    pub fn new() -> Self {
+        Modules::default()
+    }
+    pub fn add_path<P: Into<PathBuf>>(&mut self, path: P) {
+        self.paths.push(path.into());
+    }
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+    // Looks next to the importing file (or in the working directory) first, then in the search paths.
+    // Returns the path as found, for messages, and its canonical form, for cycle detection.
+    fn resolve(&self, name: &str) -> Result<(PathBuf, PathBuf), Error> {
+        let path = Path::new(name);
+        let mut candidates = Vec::<PathBuf>::new();
+        if path.is_absolute() {
+            candidates.push(path.to_path_buf());
+        } else {
+            match self.stack.last().and_then(|file| file.parent()) {
+                Some(directory) => candidates.push(directory.join(path)),
+                None => candidates.push(path.to_path_buf()),
+            }
+            for directory in &self.paths {
+                candidates.push(directory.join(path));
+            }
+        }
+        for candidate in candidates {
+            if candidate.is_file() {
+                let canonical = candidate.canonicalize().map_err(|error| {
+                    Error::new(ErrorCode::IoError, format!("Could not read \'{}\': {}", candidate.display(), error))
+                })?;
+                return Ok((candidate, canonical));
+            }
+        }
+        Err(Error::new(ErrorCode::ModuleNotFound, format!("Could not find \'{}\'.", name)))
+    }
+}
+
+// `import "finance.calc"` defines everything in the file under `finance::`.
+pub fn import(evaluator: &mut Evaluator, name: &str) -> Result<(), Error> {
+    let (file, canonical) = evaluator.modules().resolve(name)?;
+    let namespace = match file.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) if is_name(stem) => stem.to_string(),
+        _ => {
+            return Err(Error::new(ErrorCode::ModuleNotFound, format!("\'{}\' can not be used as a namespace.", file.display())));
+        }
+    };
+    bring(evaluator, &file, canonical, Some(&namespace))
+}
+
+// `use units` defines everything in `units.calc` without a namespace.
+pub fn use_module(evaluator: &mut Evaluator, name: &str) -> Result<(), Error> {
+    let path = format!("{}.{}", name.replace("::", "/"), EXTENSION);
+    let (file, canonical) = evaluator.modules().resolve(&path)?;
+    bring(evaluator, &file, canonical, None)
+}
+
+fn bring(evaluator: &mut Evaluator, file: &Path, canonical: PathBuf, namespace: Option<&str>) -> Result<(), Error> {
+    let stack = &evaluator.modules().stack;
+    if let Some(index) = stack.iter().position(|open| *open == canonical) {
+        let mut chain: Vec<String> = stack[index..].iter().map(|open| short(open)).collect();
+        chain.push(short(&canonical));
+        return Err(Error::new(ErrorCode::ImportCycle, format!("Import cycle: {}.", chain.join(" -> "))));
+    }
+
+    let module = run(evaluator, file, canonical)?;
+
+    let prefix = |name: &str| match namespace {
+        Some(namespace) => match name.strip_prefix('$') {
+            Some(name) => format!("${}::{}", namespace, name),
+            None => format!("{}::{}", namespace, name),
+        },
+        None => name.to_string(),
+    };
+    let variables: HashSet<&str> = module.variables().iter().map(|(name, _, _)| *name).collect();
+    let functions: HashSet<&str> = module.functions().iter().map(|(name, _)| *name).collect();
+
+    for (name, value, constant) in module.variables() {
+        let name = prefix(name);
+        if !constant {
+            evaluator.declare(&name, value.clone())?;
+        } else if evaluator.is_constant(&name) {
+            return Err(Error::new(ErrorCode::ReassignConstant, format!("Can not reassign constant \'{}\'.", name)));
+        } else {
+            evaluator.constant(&name, value.clone());
+        }
+    }
+    for (name, function) in module.functions() {
+        if let Definition::User { ref parameters, ref body } = function.definition {
+            let body = rename(body, parameters, &|name| variables.contains(name), &|name| functions.contains(name), &prefix);
+            evaluator.define(&prefix(name), Function::user(parameters.clone(), body))?;
+        }
+    }
+    Ok(())
+}
+
+// References to the module's own names follow them into the namespace, inside lambdas too, unless a parameter hides
+// them.
+fn rename(
+    tokens: &Tokens,
+    parameters: &[String],
+    is_variable: &dyn Fn(&str) -> bool,
+    is_function: &dyn Fn(&str) -> bool,
+    prefix: &dyn Fn(&str) -> String,
+) -> Tokens {
+    tokens
+        .iter()
+        .map(|token| match token {
+            Token::Identifier(name) if is_variable(name) && !parameters.contains(name) => Token::Identifier(prefix(name)),
+            Token::Function(name, arity) if is_function(name) => Token::Function(prefix(name), *arity),
+            Token::Lambda(inner, body) => {
+                let hidden: Vec<String> = parameters.iter().chain(inner).cloned().collect();
+                Token::Lambda(inner.clone(), rename(body, &hidden, is_variable, is_function, prefix))
+            }
+            _ => token.clone(),
+        })
+        .collect()
+}
+
+// Evaluates a module on its own, with the importer's settings but none of its names.
+fn run(evaluator: &Evaluator, file: &Path, canonical: PathBuf) -> Result<Evaluator, Error> {
+    let name = file.display().to_string();
+    let input: Arc<str> = std::fs::read_to_string(file)
+        .map_err(|error| Error::new(ErrorCode::IoError, format!("Could not read \'{}\': {}", name, error)))?
+        .into();
+
+    let mut module = Evaluator::new();
+    module.set_radix(evaluator.radix());
+    module.set_angle(evaluator.angle());
+    module.set_lenient(evaluator.is_lenient());
+    *module.modules_mut() = evaluator.modules().clone();
+    module.modules_mut().stack.push(canonical);
+
+    let mut parser = Parser::new(TokenIterator::new(&input, module.radix()));
+    loop {
+        let tokens = parser.intermediate().map_err(|error| locate(error, &name, &input, None))?;
+        if tokens.is_empty() {
+            break;
+        }
+        let start = parser.start();
+        module.evaluate(tokens).map_err(|error| locate(error, &name, &input, Some(start)))?;
+    }
+    Ok(module)
+}
+
+// Points an error into the file it came from, unless it already points into a nested import.
+fn locate(error: Error, file: &str, input: &Arc<str>, start: Option<Position>) -> Error {
+    if error.file.is_some() {
+        return error;
+    }
+    let mut error = error.in_file(file, input.clone());
+    if error.position.is_none() {
+        error.position = start;
+    }
+    error
+}
+
+fn short(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => path.display().to_string(),
+    }
+}
+
+pub fn is_name(name: &str) -> bool {
+    let mut characters = name.chars();
+    characters.next().is_some_and(|character| character.is_alphabetic() || character == '_')
+        && characters.all(|character| character.is_alphanumeric() || character == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::{eval, Value};
+
+    // A module file in a directory of its own, so that tests running at once do not share files.
+    fn module(test: &str, name: &str, source: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("oscar-{}-{}", std::process::id(), test));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = directory.join(format!("{}.{}", name, EXTENSION));
+        std::fs::write(&file, source).unwrap();
+        file
+    }
+
+    #[test]
+    fn imports_functions_that_use_lambdas() {
+        let file = module("lambdas", "squares", "$offset = 1\nsq($x) = $x ^ 2 + $offset\nmapped($l) = map($l, $x -> sq($x))\n");
+        let mut evaluator = Evaluator::new();
+        eval(&format!("import {:?}", file.display().to_string()), &mut evaluator).unwrap();
+        let expected = eval("[2, 5, 10]", &mut evaluator).unwrap();
+        assert_eq!(eval("squares::mapped([1, 2, 3])", &mut evaluator).unwrap(), expected);
+        // A lambda's parameter hides the module variable of the same name.
+        let file = module("lambdas", "shadows", "$offset = 10\nshift($l) = map($l, $offset -> $offset + 1)\n");
+        eval(&format!("import {:?}", file.display().to_string()), &mut evaluator).unwrap();
+        assert_eq!(eval("sum(shadows::shift([1, 2]))", &mut evaluator).unwrap(), Value::Number(5f64));
+    }
+}
@@ -0,0 +1,346 @@
+use crate::grover::{error, solver};
+use error::Error;
+use error::ErrorCode;
+
+// A numerical result and a bound on how far off it may be.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub value: f64,
+    pub error: f64,
+}
+
+// Nodes of the 15-point Kronrod rule on [-1, 1], the odd ones being those of the 7-point Gauss rule.
+const KRONROD_NODES: [f64; 8] = [
+    0.9914553711208126, 0.9491079123427585, 0.8648644233597691, 0.7415311855993945, 0.5860872354676911, 0.4058451513773972,
+    0.20778495500789848, 0f64,
+];
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224, 0.06309209262997856, 0.10479001032225019, 0.14065325971552592, 0.1690047266392679, 0.19035057806478542,
+    0.20443294007529889, 0.20948214108472782,
+];
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.1294849661688697, 0.27970539148927664, 0.3818300505051189, 0.4179591836734694,
+];
+
+const INTEGRATION_TOLERANCE: f64 = 1e-10;
+// Subintervals allowed before an integral is taken not to converge.
+const MAX_INTERVALS: usize = 1000;
+
+// Adaptive Gauss-Kronrod quadrature, splitting whichever interval is furthest off until the estimate is good enough.
+pub fn integrate<F>(f: &mut F, a: f64, b: f64) -> Result<Estimate, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    if a == b {
+        return Ok(Estimate { value: 0f64, error: 0f64 });
+    }
+    let mut intervals = vec![(a, b, kronrod(f, a, b)?)];
+    loop {
+        let value: f64 = intervals.iter().map(|(_, _, estimate)| estimate.value).sum();
+        let error: f64 = intervals.iter().map(|(_, _, estimate)| estimate.error).sum();
+        if error <= INTEGRATION_TOLERANCE * value.abs().max(1f64) {
+            return Ok(Estimate { value, error });
+        }
+        let worst = (0..intervals.len()).max_by(|i, j| intervals[*i].2.error.total_cmp(&intervals[*j].2.error)).unwrap_or(0);
+        let (start, end, _) = intervals[worst];
+        let middle = (start + end) / 2f64;
+        if intervals.len() >= MAX_INTERVALS || middle == start || middle == end {
+            if value.is_finite() && error <= value.abs().max(1f64).sqrt() * 1e-6 {
+                // Close enough to trust, as with a singularity at an end.
+                return Ok(Estimate { value, error });
+            }
+            return Err(Error::new(ErrorCode::NoConvergence, format!("The integral from {} to {} does not converge.", a, b)));
+        }
+        intervals[worst] = (start, middle, kronrod(f, start, middle)?);
+        intervals.push((middle, end, kronrod(f, middle, end)?));
+    }
+}
+
+// The 15-point Kronrod estimate on one interval, off by at most its difference from the 7-point Gauss one.
+fn kronrod<F>(f: &mut F, a: f64, b: f64) -> Result<Estimate, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let center = (a + b) / 2f64;
+    let half = (b - a) / 2f64;
+    let middle = f(center)?;
+    let mut kronrod = middle * KRONROD_WEIGHTS[7];
+    let mut gauss = middle * GAUSS_WEIGHTS[3];
+    for (index, node) in KRONROD_NODES.iter().take(7).enumerate() {
+        let pair = f(center - half * node)? + f(center + half * node)?;
+        kronrod += pair * KRONROD_WEIGHTS[index];
+        if index % 2 == 1 {
+            gauss += pair * GAUSS_WEIGHTS[index / 2];
+        }
+    }
+    Ok(Estimate { value: kronrod * half, error: ((kronrod - gauss) * half).abs() })
+}
+
+// Ridders' method: differences over shrinking steps, extrapolated to a step of zero.
+pub fn derivative<F>(f: &mut F, x: f64) -> Result<Estimate, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    const SHRINK: f64 = 1.4;
+    const STEPS: usize = 10;
+    corner(f, x)?;
+    let (mut step, side) = first_step(f, x)?;
+    let center = f(x)?;
+    // Central differences are off by even powers of the step, one-sided ones by every power.
+    let growth = if side == 0f64 { SHRINK * SHRINK } else { SHRINK };
+    let mut table = [[0f64; STEPS]; STEPS];
+    let mut best = Estimate { value: f64::NAN, error: f64::INFINITY };
+    for i in 0..STEPS {
+        table[0][i] = if side == 0f64 { (f(x + step)? - f(x - step)?) / (2f64 * step) } else { (f(x + side * step)? - center) / (side * step) };
+        let mut factor = growth;
+        for j in 1..=i {
+            table[j][i] = (table[j - 1][i] * factor - table[j - 1][i - 1]) / (factor - 1f64);
+            factor *= growth;
+            let error = (table[j][i] - table[j - 1][i]).abs().max((table[j][i] - table[j - 1][i - 1]).abs());
+            if error <= best.error {
+                best = Estimate { value: table[j][i], error };
+            }
+        }
+        // Rounding has taken over once extrapolating stops helping.
+        if i > 0 && (table[i][i] - table[i - 1][i - 1]).abs() >= 2f64 * best.error {
+            break;
+        }
+        step /= SHRINK;
+    }
+    if !best.value.is_finite() || best.error > 1e-5 * best.value.abs().max(1f64) {
+        return Err(Error::new(ErrorCode::NoConvergence, format!("The derivative at {} does not converge.", x)));
+    }
+    Ok(best)
+}
+
+// The first step, in proportion to the point so that it stays inside the domain of `ln($x)` at 0.01. Near an edge of
+// the domain it shrinks until the function is defined on both sides, or failing that on one of them, as the sign of
+// the side to step to.
+fn first_step<F>(f: &mut F, x: f64) -> Result<(f64, f64), Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    const TRIES: usize = 8;
+    let mut defined = |x: f64| match f(x) {
+        Ok(value) => Ok(value.is_finite()),
+        Err(error) if solver::is_undefined_at(&error) => Ok(false),
+        Err(error) => Err(error),
+    };
+    for side in [0f64, 1f64, -1f64] {
+        let mut step = 0.1 * scale(x);
+        for _ in 0..TRIES {
+            let fits = if side == 0f64 { defined(x + step)? && defined(x - step)? } else { defined(x + side * step)? };
+            if fits {
+                return Ok((step, side));
+            }
+            step /= 8f64;
+        }
+    }
+    Err(Error::new(ErrorCode::DomainError, format!("The derivative at {} does not exist: the function is not defined next to it.", x)))
+}
+
+fn scale(x: f64) -> f64 {
+    x.abs().max(1e-2)
+}
+
+// Refuses points such as 0 for `abs`, where the slopes on either side differ however close to the point they are
+// taken. Elsewhere the difference shrinks with the step, which central differences would hide. Next to an edge of the
+// domain there is only one side to take.
+fn corner<F>(f: &mut F, x: f64) -> Result<(), Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let scale = scale(x);
+    let center = f(x)?;
+    let mut slopes = |step: f64| -> Result<Option<(f64, f64)>, Error> {
+        match (f(x - step), f(x + step)) {
+            (Ok(left), Ok(right)) => Ok(Some(((center - left) / step, (right - center) / step))),
+            (Err(error), _) | (_, Err(error)) if solver::is_undefined_at(&error) => Ok(None),
+            (Err(error), _) | (_, Err(error)) => Err(error),
+        }
+    };
+    let (Some((wide_left, wide_right)), Some((left, right))) = (slopes(1e-3 * scale)?, slopes(1e-4 * scale)?) else {
+        return Ok(());
+    };
+    let gap = (right - left).abs();
+    if gap > 0.5 * (wide_right - wide_left).abs() && gap > 1e-6 * left.abs().max(right.abs()).max(1f64) {
+        return Err(Error::new(
+            ErrorCode::DomainError,
+            format!("The derivative at {} does not exist: the slope is {} from the left and {} from the right.", x, round(left), round(right)),
+        ));
+    }
+    Ok(())
+}
+
+// Approaches the point from each side, extrapolating the values towards it, and checks that both sides agree.
+pub fn limit<F>(f: &mut F, a: f64) -> Result<f64, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let exact = f(a).ok();
+    let left = side(f, a, -1f64, exact);
+    let right = side(f, a, 1f64, exact);
+    let value = match (left, right) {
+        (Ok(Side::Finite(left)), Ok(Side::Finite(right))) => {
+            if (left - right).abs() > 1e-6 * left.abs().max(right.abs()).max(1f64) {
+                return Err(Error::new(ErrorCode::NoConvergence, format!("The limit at {} does not exist: it is {} from the left and {} from the right.", a, round(left), round(right))));
+            }
+            (left + right) / 2f64
+        }
+        // Only one side is defined, as with `sqrt` at 0.
+        (Ok(Side::Finite(value)), Err(_)) | (Err(_), Ok(Side::Finite(value))) => value,
+        (Ok(Side::Infinite(left)), Ok(Side::Infinite(right))) if left != right => {
+            return Err(Error::new(ErrorCode::NoConvergence, format!("The limit at {} does not exist: it is {}infinite from the left and {}infinite from the right.", a, sign(left), sign(right))));
+        }
+        (Ok(Side::Infinite(_)), _) | (_, Ok(Side::Infinite(_))) => {
+            return Err(Error::new(ErrorCode::NoConvergence, format!("The limit at {} is infinite.", a)));
+        }
+        (Ok(Side::Divergent), _) | (_, Ok(Side::Divergent)) => {
+            return Err(Error::new(ErrorCode::NoConvergence, format!("The limit at {} does not converge.", a)));
+        }
+        (Err(error), Err(_)) => return Err(error),
+    };
+    // A continuous function's limit is its value, which spares the rounding of the extrapolation.
+    match exact {
+        Some(exact) if (exact - value).abs() <= 1e-6 * exact.abs().max(1f64) => Ok(exact),
+        _ => Ok(round(value)),
+    }
+}
+
+enum Side {
+    Finite(f64),
+    // With the sign it grows towards.
+    Infinite(bool),
+    Divergent,
+}
+
+fn side<F>(f: &mut F, a: f64, direction: f64, exact: Option<f64>) -> Result<Side, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    const STEPS: usize = 14;
+    let scale = 0.1 * a.abs().max(1f64);
+    let mut values = Vec::<f64>::with_capacity(STEPS);
+    // Neville's table for the values as a polynomial in the step, evaluated at a step of zero.
+    let mut table = Vec::<Vec<f64>>::with_capacity(STEPS);
+    let mut best: Option<(f64, f64)> = None;
+    for i in 0..STEPS {
+        let step = scale * 0.5f64.powi(i as i32);
+        let value = f(a + direction * step)?;
+        values.push(value);
+        let mut row = vec![value];
+        for j in 1..=i {
+            let factor = 2f64.powi(j as i32);
+            row.push(row[j - 1] + (row[j - 1] - table[i - 1][j - 1]) / (factor - 1f64));
+        }
+        if i > 0 {
+            let change = (row[i] - table[i - 1][i - 1]).abs();
+            if best.is_none_or(|(_, error)| change < error) {
+                best = Some((row[i], change));
+            }
+        }
+        table.push(row);
+    }
+    // Steadily growing by a factor, as poles do.
+    let growing = values.windows(2).rev().take(8).all(|pair| pair[1].abs() > pair[0].abs() * 1.2 && pair[1].signum() == pair[0].signum());
+    // Or by steps that do not shrink, as logarithms do.
+    let steps: Vec<f64> = values.windows(2).map(|pair| pair[1].abs() - pair[0].abs()).collect();
+    let creeping = steps.iter().all(|step| *step > 0f64) && steps[steps.len() - 1] >= 0.5 * steps[0];
+    if growing && values[STEPS - 1].abs() > 1e3 * values[0].abs().max(1f64) || creeping {
+        return Ok(Side::Infinite(values[STEPS - 1] > 0f64));
+    }
+    match best {
+        Some((value, error)) if value.is_finite() && error <= 1e-7 * value.abs().max(1f64) => return Ok(Side::Finite(value)),
+        _ => {}
+    }
+    // Extrapolation fails on roots such as `sqrt($x)`, which still close in on their value at the point.
+    if let Some(exact) = exact {
+        let distances: Vec<f64> = values.iter().map(|value| (value - exact).abs()).collect();
+        if distances.windows(2).all(|pair| pair[1] < pair[0]) && distances[STEPS - 1] < 0.1 * distances[0] {
+            return Ok(Side::Finite(exact));
+        }
+    }
+    Ok(Side::Divergent)
+}
+
+fn sign(positive: bool) -> &'static str {
+    if positive {
+        ""
+    } else {
+        "-"
+    }
+}
+
+// Drops the noise extrapolation leaves on short decimals such as 0.5.
+fn round(value: f64) -> f64 {
+    for places in 0..=6 {
+        let scale = 10f64.powi(places);
+        let candidate = (value * scale).round() / scale;
+        if (candidate - value).abs() <= 1e-9 * value.abs().max(1f64) {
+            // Without the sign of a zero that came from below.
+            return candidate + 0f64;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integrates_within_its_error() {
+        let estimate = integrate(&mut |x: f64| Ok(x * x), 0f64, 1f64).unwrap();
+        assert!((estimate.value - 1f64 / 3f64).abs() <= 1e-12);
+        assert!(estimate.error <= INTEGRATION_TOLERANCE);
+        let estimate = integrate(&mut |x: f64| Ok(x.sin()), 0f64, std::f64::consts::PI).unwrap();
+        assert!((estimate.value - 2f64).abs() <= 1e-10);
+        assert_eq!(integrate(&mut |x: f64| Ok(x), 1f64, 1f64).unwrap().value, 0f64);
+    }
+
+    #[test]
+    fn integrates_backwards() {
+        let forward = integrate(&mut |x: f64| Ok(x.exp()), 0f64, 2f64).unwrap();
+        let backward = integrate(&mut |x: f64| Ok(x.exp()), 2f64, 0f64).unwrap();
+        assert!((forward.value + backward.value).abs() <= 1e-12);
+    }
+
+    #[test]
+    fn differentiates_smooth_functions() {
+        let estimate = derivative(&mut |x: f64| Ok(x.sin()), 0f64).unwrap();
+        assert!((estimate.value - 1f64).abs() <= 1e-10);
+        let estimate = derivative(&mut |x: f64| Ok(x.powi(3)), 2f64).unwrap();
+        assert!((estimate.value - 12f64).abs() <= 1e-8);
+        assert!(estimate.error <= 1e-5 * 12f64);
+    }
+
+    #[test]
+    fn differentiates_next_to_the_edge_of_the_domain() {
+        let undefined = || Error::new(ErrorCode::DomainError, "Out of domain.".to_string());
+        let mut ln = |x: f64| if x > 0f64 { Ok(x.ln()) } else { Err(undefined()) };
+        let estimate = derivative(&mut ln, 0.01).unwrap();
+        assert!((estimate.value - 100f64).abs() <= 1e-6 * 100f64);
+        let mut sqrt = |x: f64| if x >= 0f64 { Ok(x.sqrt()) } else { Err(undefined()) };
+        let estimate = derivative(&mut sqrt, 0.05).unwrap();
+        assert!((estimate.value - 0.5 / 0.05f64.sqrt()).abs() <= 1e-6);
+        // Only defined to the right of 1, so the differences are one-sided.
+        let mut root = |x: f64| if x >= 1f64 { Ok((x - 1f64).sqrt() + x * x) } else { Err(undefined()) };
+        assert_eq!(derivative(&mut root, 1f64 + 1e-12).unwrap_err().code, ErrorCode::NoConvergence);
+        let mut edge = |x: f64| if x >= 1f64 { Ok(x * x) } else { Err(undefined()) };
+        let estimate = derivative(&mut edge, 1f64).unwrap();
+        assert!((estimate.value - 2f64).abs() <= 1e-6);
+    }
+
+    #[test]
+    fn refuses_corners() {
+        let error = derivative(&mut |x: f64| Ok(x.abs()), 0f64).unwrap_err();
+        assert_eq!(error.code, ErrorCode::DomainError);
+        assert!(derivative(&mut |x: f64| Ok(x.abs()), 1f64).is_ok());
+    }
+
+    #[test]
+    fn passes_errors_on() {
+        let mut f = |x: f64| if x < 0.5 { Ok(x) } else { Err(Error::new(ErrorCode::DomainError, "Out of domain.".to_string())) };
+        assert_eq!(integrate(&mut f, 0f64, 1f64).unwrap_err().code, ErrorCode::DomainError);
+    }
+}
@@ -1,427 +1,863 @@
 use crate::grover::{error, token};
 use error::Error;
 use error::ErrorCode;
-use std::collections::HashSet;
+use error::Expected;
+use std::collections::BTreeSet;
 use token::Associativity;
+use token::Lexer;
+use token::Position;
 use token::Token;
-use token::TokenIterator;
 use token::Tokens;
 
-pub struct Parser {
-    stream: TokenIterator<'static>,
+pub struct Parser<L: Lexer> {
+    stream: L,
+    finished: bool,
+    expected: BTreeSet<Expected>,
+    // Where the last statement parsed began.
+    start: Position,
 }
 
-impl Parser {
-    pub fn new(stream: TokenIterator<'static>) -> Self {
-        Parser { stream }
+impl<L: Lexer> Parser<L> {
+    pub fn new(stream: L) -> Self {
+        Parser {
+            stream,
+            finished: false,
+            expected: Statement::new().expected,
+            start: Position { line: 1, column: 1 },
+        }
+    }
+    pub fn start(&self) -> Position {
+        self.start
+    }
+    // What may follow the last token parsed, e.g. for completion.
+    pub fn expected(&self) -> &BTreeSet<Expected> {
+        &self.expected
     }
+    // Parses the next statement, returning the first error found in it.
     pub fn intermediate(&mut self) -> Result<Tokens, Error> {
-        let mut tokens = Tokens::new();
+        match self.statement() {
+            Some(Ok(tokens)) => Ok(tokens),
+            Some(Err(mut errors)) => Err(errors.remove(0)),
+            None => Ok(Tokens::new()),
+        }
+    }
+    // Parses every remaining statement, reporting all errors at once.
+    pub fn script(&mut self) -> Result<Vec<Tokens>, Vec<Error>> {
+        let mut statements = Vec::<Tokens>::new();
+        let mut errors = Vec::<Error>::new();
+        while let Some(result) = self.statement() {
+            match result {
+                Ok(tokens) => statements.push(tokens),
+                Err(mut found) => errors.append(&mut found),
+            }
+        }
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(errors)
+        }
+    }
+    fn statement(&mut self) -> Option<Result<Tokens, Vec<Error>>> {
+        loop {
+            if self.finished {
+                return None;
+            }
 
-        let mut operators = Vec::<Token>::new();
+            let mut statement = Statement::new();
 
-        let mut parenthesis_depth = 0;
+            let mut errors = Vec::<Error>::new();
 
-        let mut expected = HashSet::<&str>::new();
+            let mut empty = true;
 
-        expected.insert("identifier");
-        expected.insert("number");
-        expected.insert("unary-plus");
-        expected.insert("unary-minus");
-        expected.insert("left-parenthesis");
+            // After a syntax error skip ahead to the next ')' or separator
+            // and carry on from there so later errors are reported too.
+            let mut recovering = false;
 
-        // Shunting Yard Algorithm
+            let mut skipped_depth = 0;
 
-        while let Some(token) = self.stream.next() {
-            match token {
-                Token::Identifier(_) => {
-                    if !(expected.contains("identifier")) {
-                        return Err(Error {
-                            code: ErrorCode::MalformedExpression,
-                            message: format!("Expected {:?} found identifier.", expected),
-                        });
-                    }
-                    expected.clear();
-                    expected.insert("assignment-operator");
-                    expected.insert("arithmetic-operator");
-                    expected.insert("right-parenthesis");
-                    tokens.push(token);
-                }
-                Token::Number(_) => {
-                    if !(expected.contains("number")) {
-                        return Err(Error {
-                            code: ErrorCode::MalformedExpression,
-                            message: format!("Expected {:?} found number.", expected),
-                        });
+            loop {
+                let token = match self.stream.next() {
+                    Some(Token::Separator) if !statement.in_block() && !statement.in_brackets() => break,
+                    Some(token) => token,
+                    None => {
+                        self.finished = true;
+                        break;
                     }
-                    expected.clear();
-                    expected.insert("assignment-operator");
-                    expected.insert("arithmetic-operator");
-                    expected.insert("right-parenthesis");
-                    tokens.push(token);
-                }
-                Token::Operator(operator) => {
-                    // Checks for next expected operator 
-                    // and handles unary plus and minus stack operations.
-                    match *operator {
-                        token::ADDITION_OPERATOR => {
-                            if !expected.contains("unary-plus")
-                                && !expected.contains("arithmetic-operator")
-                            {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!("Expected {:?} found unary-plus.", expected),
-                                });
-                            }
-                            if !expected.contains("arithmetic-operator")
-                                && !expected.contains("unary-plus")
-                            {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found plus-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            if expected.contains("unary-plus") {
-                                expected.clear();
-                                expected.insert("unary-plus");
-                                expected.insert("unary-minus");
-                                expected.insert("identifier");
-                                expected.insert("number");
-                                tokens.push(Token::Number(1f64));
-                                operators.push(token::MULTIPLICATION_TOKEN);
-                                continue;
-                            } else {
-                                expected.clear();
-                                expected.insert("unary-plus");
-                                expected.insert("unary-minus");
-                                expected.insert("identifier");
-                                expected.insert("number");
-                                expected.insert("left-parenthesis");
-                            }
+                };
+                let position = self.stream.position();
+                if empty {
+                    self.start = position;
+                }
+                empty = false;
+                // The rest of the statement is skipped too, as whatever follows a bad character is unlikely to fit.
+                if let Token::Error(message) = token {
+                    errors.push(Error::at(ErrorCode::LexerError, message, position));
+                    recovering = true;
+                    continue;
+                }
+                if recovering {
+                    match token {
+                        Token::LeftParenthesis | Token::LeftBrace => {
+                            skipped_depth += 1;
+                            continue;
                         }
-                        token::SUBTRACTION_OPERATOR => {
-                            if !expected.contains("unary-minus")
-                                && !expected.contains("arithmetic-operator")
-                            {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!("Expected {:?} found unary-minus.", expected),
-                                });
-                            }
-                            if !expected.contains("arithmetic-operator")
-                                && !expected.contains("unary-minus")
-                            {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found minus-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            if expected.contains("unary-minus") {
-                                expected.clear();
-                                expected.insert("unary-plus");
-                                expected.insert("unary-minus");
-                                expected.insert("identifier");
-                                expected.insert("number");
-                                expected.insert("left-parenthesis");
-                                tokens.push(Token::Number(-1f64));
-                                operators.push(token::MULTIPLICATION_TOKEN);
-                                continue;
-                            } else {
-                                expected.clear();
-                                expected.insert("unary-plus");
-                                expected.insert("unary-minus");
-                                expected.insert("identifier");
-                                expected.insert("number");
-                                expected.insert("left-parenthesis");
-                            }
+                        Token::RightParenthesis | Token::RightBrace if skipped_depth > 0 => {
+                            skipped_depth -= 1;
+                            continue;
                         }
-                        token::MULTIPLICATION_OPERATOR => {
-                            if !expected.contains("arithmetic-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found times-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                        Token::RightParenthesis if statement.parenthesis_depth > 0 => {
+                            statement.expect_operator(false);
+                            recovering = false;
                         }
-                        token::DIVISION_OPERATOR => {
-                            if !expected.contains("arithmetic-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found division-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                        Token::RightBrace | Token::Separator if statement.innermost() == Some(&Group::Block) => {
+                            statement.expect_operator(false);
+                            recovering = false;
                         }
-                        token::REMAINDER_OPERATOR => {
-                            if !expected.contains("arithmetic-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found remainder-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                        _ => continue,
+                    }
+                }
+                let closing = token == Token::RightParenthesis;
+                if let Err(mut error) = statement.push(token) {
+                    error.position = Some(position);
+                    errors.push(error);
+                    // A misplaced ')' is itself a synchronization point.
+                    if closing && statement.parenthesis_depth > 0 {
+                        statement.expect_operator(false);
+                        let _ = statement.push(Token::RightParenthesis);
+                    } else {
+                        recovering = true;
+                    }
+                }
+            }
+
+            self.expected = statement.expected.clone();
+
+            if empty {
+                continue;
+            }
+
+            if !recovering {
+                match statement.finish() {
+                    Ok(tokens) => {
+                        if errors.is_empty() {
+                            return Some(Ok(tokens));
                         }
-                        token::POWER_OPERATOR => {
-                            if !expected.contains("arithmetic-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found power-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                    }
+                    Err(mut error) => {
+                        error.position = Some(self.stream.position());
+                        errors.push(error);
+                    }
+                }
+            }
+
+            return Some(Err(errors));
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Group {
+    Parenthesis,
+    // A function call, counting the commas seen so far.
+    Call(usize),
+    Block,
+    // A matrix literal, counting the commas seen in each row so far.
+    Matrix(Vec<usize>),
+    // The indices of a value, counting the commas seen so far.
+    Index(usize),
+    // The parameters of a lambda, as in `($x, $y) -> $x * $y`.
+    Parameters(Vec<String>),
+    // The body of a lambda, with where it starts in the output.
+    Lambda(usize),
+}
+
+// Shunting Yard Algorithm state for a single statement.
+struct Statement {
+    tokens: Tokens,
+    operators: Vec<Token>,
+    parenthesis_depth: usize,
+    // One entry per open parenthesis or brace.
+    groups: Vec<Group>,
+    // Opening tokens, kept while the statement may still be a function definition.
+    head: Option<Vec<Token>>,
+    expected: BTreeSet<Expected>,
+    // Description of the last token accepted, used in error messages.
+    last: Option<String>,
+    // Parameters read in parentheses, waiting for the '->' of their lambda.
+    parameters: Option<Vec<String>>,
+}
+
+impl Statement {
+    fn new() -> Self {
+        let mut statement = Statement {
+            tokens: Tokens::new(),
+            operators: Vec::<Token>::new(),
+            parenthesis_depth: 0,
+            groups: Vec::<Group>::new(),
+            head: Some(Vec::<Token>::new()),
+            expected: BTreeSet::<Expected>::new(),
+            last: None,
+            parameters: None,
+        };
+        statement.expect_operand();
+        statement
+    }
+    fn expect_operand(&mut self) {
+        self.expected.clear();
+        self.expected.insert(Expected::Number);
+        self.expected.insert(Expected::Identifier);
+        self.expected.insert(Expected::Function);
+        self.expected.insert(Expected::UnaryOperator);
+        self.expected.insert(Expected::LeftParenthesis);
+        self.expected.insert(Expected::LeftBrace);
+        self.expected.insert(Expected::LeftBracket);
+    }
+    fn expect_operator(&mut self, assignable: bool) {
+        self.expected.clear();
+        self.expected.insert(Expected::ArithmeticOperator);
+        if assignable {
+            self.expected.insert(Expected::AssignmentOperator);
+        }
+        // A lambda ends wherever the group around it may.
+        match self.groups.iter().rev().find(|group| !matches!(group, Group::Lambda(_))) {
+            Some(Group::Call(_)) | Some(Group::Parameters(_)) => {
+                self.expected.insert(Expected::Comma);
+                self.expected.insert(Expected::RightParenthesis);
+            }
+            Some(Group::Parenthesis) => {
+                self.expected.insert(Expected::RightParenthesis);
+            }
+            Some(Group::Block) => {
+                self.expected.insert(Expected::Separator);
+                self.expected.insert(Expected::RightBrace);
+            }
+            Some(Group::Matrix(_)) => {
+                self.expected.insert(Expected::Comma);
+                self.expected.insert(Expected::Separator);
+                self.expected.insert(Expected::RightBracket);
+            }
+            Some(Group::Index(_)) => {
+                self.expected.insert(Expected::Comma);
+                self.expected.insert(Expected::RightBracket);
+            }
+            Some(Group::Lambda(_)) | None => {
+                self.expected.insert(Expected::End);
+            }
+        }
+    }
+    fn innermost(&self) -> Option<&Group> {
+        self.groups.last()
+    }
+    fn in_block(&self) -> bool {
+        self.groups.contains(&Group::Block)
+    }
+    fn in_brackets(&self) -> bool {
+        self.groups.iter().any(|group| matches!(group, Group::Matrix(_) | Group::Index(_)))
+    }
+    // Whether the next token starts a statement, at the top level or inside a block.
+    fn at_start(&self) -> bool {
+        match self.tokens.iter().last() {
+            None => self.operators.is_empty(),
+            Some(Token::LeftBrace) | Some(Token::Separator) => self.operators.last() == Some(&Token::LeftBrace),
+            Some(_) => false,
+        }
+    }
+    // Moves operators to the output up to the innermost open group.
+    fn unwind(&mut self) {
+        while let Some(last_token) = self.operators.last() {
+            if matches!(last_token, Token::LeftParenthesis | Token::LeftBrace | Token::LeftBracket | Token::Lambda(_, _)) {
+                break;
+            }
+            self.tokens.push(self.operators.pop().expect("Operator stack has valid last value but pop failed to retrieve it."));
+        }
+    }
+    // Ends the lambdas whose bodies the next token closes, moving each body into its lambda.
+    fn close_lambdas(&mut self) {
+        while let Some(Group::Lambda(start)) = self.groups.last() {
+            let start = *start;
+            self.unwind();
+            self.groups.pop();
+            if let Some(Token::Lambda(parameters, _)) = self.operators.pop() {
+                let body = self.tokens.split_off(start);
+                self.tokens.push(Token::Lambda(parameters, body));
+            }
+        }
+    }
+    // Whether the innermost parentheses hold nothing but a variable, so a ',' makes them the parameters of a lambda.
+    fn is_parameter(&self) -> bool {
+        self.innermost() == Some(&Group::Parenthesis)
+            && self.operators.last() == Some(&Token::LeftParenthesis)
+            && self.expected.contains(&Expected::AssignmentOperator)
+            && matches!(self.tokens.iter().last(), Some(Token::Identifier(_)))
+    }
+    fn expect_end(&mut self) {
+        self.expected.clear();
+        self.expected.insert(Expected::End);
+    }
+    // The keyword starting an import statement, when nothing else has been read yet.
+    fn directive(&self) -> Option<&str> {
+        if !self.tokens.is_empty() || self.operators.len() != 1 {
+            return None;
+        }
+        match self.operators.last() {
+            Some(Token::Function(name, _)) if name == "import" || name == "use" => Some(name.as_str()),
+            _ => None,
+        }
+    }
+    fn unexpected(&self, found: Option<&Token>) -> Error {
+        let context = match self.last {
+            Some(ref last) => format!("after {}", last),
+            None => "at the start of the expression".to_string(),
+        };
+        let found = match found {
+            Some(token) => describe(token),
+            None => "the end of the expression".to_string(),
+        };
+        let mut error = Error::new(
+            ErrorCode::MalformedExpression,
+            format!("Expected {} {}, found {}.", Expected::describe(&self.expected), context, found),
+        );
+        error.expected = self.expected.clone();
+        error
+    }
+    fn push(&mut self, token: Token) -> Result<(), Error> {
+        match token {
+            token::ASSIGNMENT_TOKEN => {}
+            Token::Number(_) | Token::Operator(_) => self.head = None,
+            _ => {
+                if let Some(ref mut head) = self.head {
+                    head.push(token.clone());
+                }
+            }
+        }
+        if matches!(token, Token::Comma | Token::RightParenthesis | Token::RightBracket | Token::RightBrace | Token::Separator)
+            && self.expected.contains(&Expected::ArithmeticOperator)
+        {
+            self.close_lambdas();
+        }
+        match token {
+            Token::Identifier(ref name) if self.operators.last() == Some(&Token::Let(String::new())) => {
+                self.operators.pop();
+                self.operators.push(Token::Let(name.clone()));
+                self.expected.clear();
+                self.expected.insert(Expected::AssignmentOperator);
+                self.last = Some(describe(&token));
+            }
+            Token::Identifier(ref name) if matches!(self.groups.last(), Some(Group::Parameters(_))) => {
+                if !(self.expected.contains(&Expected::Identifier)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                if let Some(Group::Parameters(parameters)) = self.groups.last_mut() {
+                    if parameters.contains(name) {
+                        return Err(Error::new(ErrorCode::MalformedExpression, format!("The parameter \'{}\' is repeated.", name)));
+                    }
+                    parameters.push(name.clone());
+                }
+                self.expected.clear();
+                self.expected.insert(Expected::Comma);
+                self.expected.insert(Expected::RightParenthesis);
+                self.last = Some(describe(&token));
+            }
+            Token::Identifier(_) => {
+                if !(self.expected.contains(&Expected::Identifier)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.expect_operator(true);
+                self.last = Some(describe(&token));
+                self.tokens.push(token);
+            }
+            Token::Range(_, _) if !matches!(self.groups.last(), Some(Group::Call(_))) => {
+                return Err(Error::new(ErrorCode::MalformedExpression, format!("The range {} can only be a function argument.", describe(&token))));
+            }
+            Token::Number(_) | Token::Cell(_) | Token::Range(_, _) => {
+                if !(self.expected.contains(&Expected::Number)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.expect_operator(false);
+                self.last = Some(describe(&token));
+                self.tokens.push(token);
+            }
+            Token::Function(ref name, _) if self.directive() == Some("use") => {
+                self.operators.clear();
+                self.tokens.push(Token::Use(name.clone()));
+                self.expect_end();
+                self.last = Some(describe(&token));
+            }
+            Token::Text(ref path) if self.directive() == Some("import") => {
+                self.operators.clear();
+                self.tokens.push(Token::Import(path.clone()));
+                self.expect_end();
+                self.last = Some(describe(&token));
+            }
+            Token::Text(_) => {
+                return Err(self.unexpected(Some(&token)));
+            }
+            Token::Function(ref name, _) if name == "let" && self.at_start() => {
+                // The name is filled in by the identifier that follows.
+                self.operators.push(Token::Let(String::new()));
+                self.expected.clear();
+                self.expected.insert(Expected::Identifier);
+                self.last = Some(describe(&token));
+            }
+            Token::Function(_, _) => {
+                if !(self.expected.contains(&Expected::Function)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.expected.clear();
+                self.expected.insert(Expected::LeftParenthesis);
+                self.last = Some(describe(&token));
+                self.operators.push(token);
+            }
+            Token::Comma if self.is_parameter() => {
+                if let Some(Token::Identifier(name)) = self.tokens.pop() {
+                    if let Some(group) = self.groups.last_mut() {
+                        *group = Group::Parameters(vec![name]);
+                    }
+                }
+                self.expected.clear();
+                self.expected.insert(Expected::Identifier);
+                self.last = Some(describe(&token));
+            }
+            Token::Comma if matches!(self.groups.last(), Some(Group::Parameters(_))) => {
+                if !(self.expected.contains(&Expected::Comma)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.expected.clear();
+                self.expected.insert(Expected::Identifier);
+                self.last = Some(describe(&token));
+            }
+            Token::Comma => {
+                if !(self.expected.contains(&Expected::Comma)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.unwind();
+                match self.groups.last_mut() {
+                    Some(Group::Call(commas)) | Some(Group::Index(commas)) => *commas += 1,
+                    Some(Group::Matrix(rows)) => {
+                        if let Some(commas) = rows.last_mut() {
+                            *commas += 1;
                         }
-                        token::ASSIGNMENT_OPERATOR => {
-                            if !expected.contains("assignment-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found assignment-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                    }
+                    _ => {}
+                }
+                self.expect_operand();
+                self.last = Some(describe(&token));
+            }
+            Token::Operator(operator) => {
+                // Checks for next expected operator 
+                // and handles unary plus and minus stack operations.
+                match *operator {
+                    token::ADDITION_OPERATOR | token::SUBTRACTION_OPERATOR => {
+                        if self.expected.contains(&Expected::UnaryOperator) {
+                            let sign = if *operator == token::ADDITION_OPERATOR { 1f64 } else { -1f64 };
+                            self.expect_operand();
+                            self.last = Some(describe(&token));
+                            self.tokens.push(Token::Number(sign));
+                            self.operators.push(token::MULTIPLICATION_TOKEN);
+                            return Ok(());
                         }
-                        token::ADDITION_ASSIGNMENT_OPERATOR => {
-                            if !expected.contains("assignment-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found addition-assignment-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                        if !self.expected.contains(&Expected::ArithmeticOperator) {
+                            return Err(self.unexpected(Some(&token)));
                         }
-                        token::SUBTRACTION_ASSIGNMENT_OPERATOR => {
-                            if !expected.contains("assignment-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found subtraction-assignment-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                    }
+                    token::MULTIPLICATION_OPERATOR
+                    | token::DIVISION_OPERATOR
+                    | token::REMAINDER_OPERATOR
+                    | token::POWER_OPERATOR
+                    | token::ELEMENTWISE_MULTIPLICATION_OPERATOR
+                    | token::ELEMENTWISE_DIVISION_OPERATOR
+                    | token::ELEMENTWISE_POWER_OPERATOR
+                    | token::RANGE_OPERATOR
+                    | token::LESS_OPERATOR
+                    | token::LESS_EQUAL_OPERATOR
+                    | token::GREATER_OPERATOR
+                    | token::GREATER_EQUAL_OPERATOR
+                    | token::EQUAL_OPERATOR
+                    | token::NOT_EQUAL_OPERATOR => {
+                        if !self.expected.contains(&Expected::ArithmeticOperator) {
+                            return Err(self.unexpected(Some(&token)));
                         }
-                        token::MULTIPLICATION_ASSIGNMENT_OPERATOR => {
-                            if !expected.contains("assignment-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found multiplication-assignment-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                    }
+                    // An equation, as in `solve($x ^ 2 - 2 = 0, $x)`.
+                    token::ASSIGNMENT_OPERATOR
+                        if self.expected.contains(&Expected::ArithmeticOperator) && matches!(self.groups.last(), Some(Group::Call(_))) => {}
+                    token::ASSIGNMENT_OPERATOR if !self.expected.contains(&Expected::AssignmentOperator) => {
+                        if let Some((name, parameters)) = self.head.as_deref().and_then(signature) {
+                            self.tokens.clear();
+                            self.operators.clear();
+                            self.groups.clear();
+                            self.parenthesis_depth = 0;
+                            self.tokens.push(Token::Definition(name, parameters));
+                            self.expect_operand();
+                            self.last = Some(describe(&token));
+                            return Ok(());
                         }
-                        token::DIVISION_ASSIGNMENT_OPERATOR => {
-                            if !expected.contains("assignment-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found division-assignment-operator.",
-                                        expected
-                                    ),
-                                });
-                            }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                        return Err(self.unexpected(Some(&token)));
+                    }
+                    token::BINDING_OPERATOR => {
+                        // Only a whole statement can be a binding, as in `$area := $w * $h`.
+                        let name = match self.tokens.first() {
+                            Some(Token::Identifier(name)) if self.tokens.len() == 1 && self.operators.is_empty() => name.clone(),
+                            _ => return Err(self.unexpected(Some(&token))),
+                        };
+                        self.tokens.clear();
+                        self.tokens.push(Token::Binding(name));
+                        self.expect_operand();
+                        self.last = Some(describe(&token));
+                        return Ok(());
+                    }
+                    token::ASSIGNMENT_OPERATOR if matches!(self.operators.last(), Some(Token::Let(_))) => {
+                        // The binding itself is emitted once the whole value has been.
+                        self.expect_operand();
+                        self.last = Some(describe(&token));
+                        return Ok(());
+                    }
+                    token::ASSIGNMENT_OPERATOR
+                    | token::ADDITION_ASSIGNMENT_OPERATOR
+                    | token::SUBTRACTION_ASSIGNMENT_OPERATOR
+                    | token::MULTIPLICATION_ASSIGNMENT_OPERATOR
+                    | token::DIVISION_ASSIGNMENT_OPERATOR
+                    | token::REMAINDER_ASSIGNMENT_OPERATOR => {
+                        if !self.expected.contains(&Expected::AssignmentOperator) {
+                            return Err(self.unexpected(Some(&token)));
                         }
-                        token::REMAINDER_ASSIGNMENT_OPERATOR => {
-                            if !expected.contains("assignment-operator") {
-                                return Err(Error {
-                                    code: ErrorCode::MalformedExpression,
-                                    message: format!(
-                                        "Expected {:?} found remainder-assignment-operator.",
-                                        expected
-                                    ),
-                                });
+                    }
+                    _ => {
+                        return Err(Error::new(ErrorCode::ParserError, format!("Unhandled operator {:?}", operator)));
+                    }
+                };
+                self.expect_operand();
+                while let Some(last_token) = self.operators.last() {
+                    match last_token {
+                        Token::Operator(last_operator) => {
+                            if last_operator.precedence < operator.precedence
+                                || last_operator.precedence == operator.precedence
+                                    && operator.associativity == Associativity::Left
+                            {
+                                self.tokens.push(self.operators.pop().expect("Operator stack has valid last value but pop failed to retrieve it."));
+                            } else {
+                                break;
                             }
-                            expected.clear();
-                            expected.insert("unary-minus");
-                            expected.insert("unary-plus");
-                            expected.insert("number");
-                            expected.insert("identifier");
-                            expected.insert("left-parenthesis");
+                        }
+                        // These Operators are Handled Elsewhere
+                        Token::LeftParenthesis | Token::LeftBrace | Token::LeftBracket | Token::Let(_) | Token::Lambda(_, _) => {
+                            break;
+                        }
+                        Token::RightParenthesis => {
+                            self.tokens.push(self.operators.pop().expect("Operator stack has valid last value but pop failed to retrieve it."));
                         }
                         _ => {
-                            return Err(Error {
-                                code: ErrorCode::ParserError,
-                                message: format!("Unhandled operator {:?}", operator),
-                            });
+                            return Err(Error::new(ErrorCode::ParserError, "Encountered a non-operator in operator stack.".to_string()));
                         }
                     };
-                    loop {
-                        if let Some(&ref last_token) = operators.last() {
-                            match last_token {
-                                Token::Operator(ref last_operator) => {
-                                    if last_operator.precedence < operator.precedence
-                                        || last_operator.precedence == operator.precedence
-                                            && operator.associativity == Associativity::Left
-                                    {
-                                        tokens.push(operators.pop().expect("Operator stack has valid last value but pop failed to retrieve it."));
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                // These Operators are Handled Elsewhere
-                                Token::LeftParenthesis => {
-                                    break;
-                                }
-                                Token::RightParenthesis => {
-                                    tokens.push(operators.pop().expect("Operator stack has valid last value but pop failed to retrieve it."));
-                                }
-                                _ => {
-                                    return Err(Error {
-                                        code: ErrorCode::ParserError,
-                                        message: format!(
-                                            "Encountered a non-operator in operator stack."
-                                        ),
-                                    });
-                                }
-                            };
-                        } else {
-                            break;
+                }
+                self.last = Some(describe(&token));
+                self.operators.push(token);
+            }
+            Token::LeftParenthesis => {
+                if !(self.expected.contains(&Expected::LeftParenthesis)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                let call = matches!(self.operators.last(), Some(Token::Function(_, _)));
+                self.parenthesis_depth += 1;
+                self.groups.push(if call { Group::Call(0) } else { Group::Parenthesis });
+                self.expect_operand();
+                // Function calls may have no arguments.
+                if call {
+                    self.expected.insert(Expected::RightParenthesis);
+                }
+                self.last = Some(describe(&token));
+                self.operators.push(token);
+            }
+            Token::RightParenthesis if matches!(self.groups.last(), Some(Group::Parameters(_))) => {
+                if !(self.expected.contains(&Expected::RightParenthesis)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                if let Some(Group::Parameters(parameters)) = self.groups.pop() {
+                    self.parameters = Some(parameters);
+                }
+                self.operators.pop();
+                self.parenthesis_depth -= 1;
+                self.expected.clear();
+                self.expected.insert(Expected::Arrow);
+                self.last = Some(describe(&token));
+            }
+            Token::Arrow => {
+                // Either parameters in parentheses, or the single variable just read.
+                let parameters = match self.parameters.take() {
+                    Some(parameters) => parameters,
+                    None if self.expected.contains(&Expected::ArithmeticOperator)
+                        && matches!(self.tokens.iter().last(), Some(Token::Identifier(_)))
+                        && !matches!(self.operators.last(), Some(Token::Operator(_)) | Some(Token::Let(_))) =>
+                    {
+                        match self.tokens.pop() {
+                            Some(Token::Identifier(name)) => vec![name],
+                            _ => return Err(Error::new(ErrorCode::ParserError, "Expected the parameter of a lambda.".to_string())),
                         }
                     }
-                    operators.push(token);
-                }
-                Token::LeftParenthesis => {
-                    if !(expected.contains("left-parenthesis")) {
-                        return Err(Error {
-                            code: ErrorCode::MalformedExpression,
-                            message: format!("Expected {:?} found left-parenthesis.", expected),
-                        });
-                    }
-                    parenthesis_depth += 1;
-                    operators.push(token);
-                }
-                Token::RightParenthesis => {
-                    if parenthesis_depth == 0 {
-                        return Err(Error {
-                            code: ErrorCode::MalformedExpression,
-                            message: format!("Dangling right parenthesis."),
-                        });
-                    }
-                    parenthesis_depth -= 1;
-                    loop {
-                        if let Some(&ref last_token) = operators.last() {
-                            if *last_token != Token::LeftParenthesis {
-                                tokens.push(                
-                                    match operators.pop() {
-                                        Some(operator) => operator,
-                                        None => {
-                                            return Err(Error{
-                                                code: ErrorCode::ParserError,
-                                                message: format!("Operator stack has valid last value but pop failed to retrieve it."),
-                                            });
-                                        }    
-                                    }
-                                );
-                            } else {
-                                break;
+                    None => return Err(self.unexpected(Some(&token))),
+                };
+                self.groups.push(Group::Lambda(self.tokens.len()));
+                self.operators.push(Token::Lambda(parameters, Tokens::new()));
+                self.expect_operand();
+                self.last = Some(describe(&token));
+            }
+            Token::RightParenthesis => {
+                if self.parenthesis_depth == 0 {
+                    return Err(Error::new(ErrorCode::MalformedExpression, "Dangling right parenthesis.".to_string()));
+                }
+                if !(self.expected.contains(&Expected::RightParenthesis)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                let empty = self.expected.contains(&Expected::Number);
+                let group = self.groups.pop();
+                self.parenthesis_depth -= 1;
+                self.expect_operator(false);
+                self.last = Some(describe(&token));
+                while let Some(last_token) = self.operators.last() {
+                    if *last_token != Token::LeftParenthesis {
+                        self.tokens.push(                
+                            match self.operators.pop() {
+                                Some(operator) => operator,
+                                None => {
+                                    return Err(Error::new(ErrorCode::ParserError, "Operator stack has valid last value but pop failed to retrieve it.".to_string()));
+                                }    
                             }
-                        } else {
-                            break;
-                        }
+                        );
+                    } else {
+                        break;
                     }
-                    if let Some(&ref last_token) = operators.last() {
-                        if *last_token == Token::LeftParenthesis {
-                            operators.pop();
-                        }
+                }
+                if let Some(last_token) = self.operators.last() {
+                    if *last_token == Token::LeftParenthesis {
+                        self.operators.pop();
                     }
                 }
-            };
+                if let Some(Group::Call(commas)) = group {
+                    if let Some(Token::Function(name, _)) = self.operators.pop() {
+                        let count = if empty { 0 } else { commas + 1 };
+                        self.tokens.push(Token::Function(name, count));
+                    }
+                }
+            }
+            Token::LeftBrace => {
+                if !(self.expected.contains(&Expected::LeftBrace)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.groups.push(Group::Block);
+                self.expect_operand();
+                self.last = Some(describe(&token));
+                self.operators.push(Token::LeftBrace);
+                self.tokens.push(token);
+            }
+            Token::Separator if matches!(self.groups.last(), Some(Group::Matrix(_))) => {
+                // The next row of a matrix.
+                if !(self.expected.contains(&Expected::Separator)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.unwind();
+                if let Some(Group::Matrix(rows)) = self.groups.last_mut() {
+                    rows.push(0);
+                }
+                self.expect_operand();
+                self.last = Some(describe(&token));
+            }
+            Token::LeftBracket if self.expected.contains(&Expected::ArithmeticOperator) => {
+                // Indices of the value just read, as in `$v[2]` or `$m[1, 2..3]`.
+                self.groups.push(Group::Index(0));
+                self.expect_operand();
+                self.last = Some(describe(&token));
+                self.operators.push(token);
+            }
+            Token::LeftBracket => {
+                if !(self.expected.contains(&Expected::LeftBracket)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.groups.push(Group::Matrix(vec![0]));
+                self.expect_operand();
+                // Matrices may be empty.
+                self.expected.insert(Expected::RightBracket);
+                self.last = Some(describe(&token));
+                self.operators.push(token);
+            }
+            Token::RightBracket => {
+                if !self.in_brackets() {
+                    return Err(Error::new(ErrorCode::MalformedExpression, "Dangling right bracket.".to_string()));
+                }
+                if !(self.expected.contains(&Expected::RightBracket)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                let empty = self.expected.contains(&Expected::Number);
+                self.unwind();
+                self.operators.pop();
+                let closed = match self.groups.pop() {
+                    Some(Group::Matrix(_)) if empty => Token::Array(Vec::new()),
+                    Some(Group::Matrix(rows)) => Token::Array(rows.iter().map(|commas| commas + 1).collect()),
+                    Some(Group::Index(commas)) => Token::Index(commas + 1),
+                    _ => return Err(Error::new(ErrorCode::ParserError, "Expected a matrix to close.".to_string())),
+                };
+                self.expect_operator(false);
+                self.last = Some(describe(&token));
+                self.tokens.push(closed);
+            }
+            Token::Separator => {
+                // Empty statements are skipped.
+                if matches!(self.tokens.iter().last(), Some(Token::LeftBrace) | Some(Token::Separator)) {
+                    return Ok(());
+                }
+                if !(self.expected.contains(&Expected::Separator)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.unwind();
+                self.expect_operand();
+                self.last = Some(describe(&token));
+                self.tokens.push(token);
+            }
+            Token::RightBrace => {
+                if !self.in_block() {
+                    return Err(Error::new(ErrorCode::MalformedExpression, "Dangling right brace.".to_string()));
+                }
+                // A block may end with a separator, but may not be empty.
+                if self.expected.contains(&Expected::Number) && self.tokens.iter().last() == Some(&Token::Separator) {
+                    self.tokens.pop();
+                    self.expect_operator(false);
+                }
+                if !(self.expected.contains(&Expected::RightBrace)) {
+                    return Err(self.unexpected(Some(&token)));
+                }
+                self.unwind();
+                self.operators.pop();
+                self.groups.pop();
+                self.expect_operator(false);
+                self.last = Some(describe(&token));
+                self.tokens.push(token);
+            }
+            _ => {
+                return Err(Error::new(ErrorCode::ParserError, format!("Unhandled token {:?}", token)));
+            }
         }
+        Ok(())
+    }
+    fn finish(mut self) -> Result<Tokens, Error> {
+        if !self.expected.contains(&Expected::End) {
+            return Err(self.unexpected(None));
+        }
+        self.close_lambdas();
 
-        while operators.len() > 0 {
-            tokens.push(
-                match operators.pop() {
+        while !self.operators.is_empty() {
+            self.tokens.push(
+                match self.operators.pop() {
                     Some(operator) => operator,
                     None => {
-                        return Err(Error{
-                            code: ErrorCode::ParserError,
-                            message: format!("Operator stack has valid last value but pop failed to retrieve it."),
-                        });
+                        return Err(Error::new(ErrorCode::ParserError, "Operator stack has valid last value but pop failed to retrieve it.".to_string()));
                     }    
                 }
             );
         }
 
-        if let Some(error) = self.stream.get_error() {
-            return Err(Error {
-                code: ErrorCode::LexerError,
-                message: format!("{}", error),
-            });
-        } else {
-            return Ok(tokens);
+        Ok(self.tokens)
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Identifier(identifier) => format!("'{}'", identifier),
+        Token::Number(number) => format!("'{}'", number),
+        Token::Function(name, _) => format!("'{}'", name),
+        Token::Comma => "','".to_string(),
+        Token::Operator(operator) => format!("'{}'", operator.symbol),
+        Token::LeftParenthesis => "'('".to_string(),
+        Token::RightParenthesis => "')'".to_string(),
+        Token::LeftBrace => "'{'".to_string(),
+        Token::RightBrace => "'}'".to_string(),
+        Token::LeftBracket => "'['".to_string(),
+        Token::RightBracket => "']'".to_string(),
+        Token::Arrow => "'->'".to_string(),
+        Token::Index(_) => "an index".to_string(),
+        Token::Array(rows) => format!("a matrix of {} rows", rows.len()),
+        Token::Matrix(matrix) => format!("a {} matrix", matrix.shape()),
+        Token::Let(name) if name.is_empty() => "'let'".to_string(),
+        Token::Let(name) => format!("'let {}'", name),
+        Token::Separator => "';'".to_string(),
+        Token::Error(_) => "an invalid token".to_string(),
+        Token::Definition(name, _) => format!("the definition of \'{}\'", name),
+        Token::Binding(name) => format!("the binding of \'{}\'", name),
+        Token::Cell(name) => format!("'{}'", name),
+        Token::Range(start, end) => format!("'{}:{}'", start, end),
+        Token::Text(text) => format!("{:?}", text),
+        Token::Import(path) => format!("the import of {:?}", path),
+        Token::Use(name) => format!("the use of \'{}\'", name),
+        Token::Lambda(parameters, _) => format!("a function of {}", parameters.join(", ")),
+    }
+}
+
+// Matches a definition head such as `f($x, $y)`.
+fn signature(head: &[Token]) -> Option<(String, Vec<String>)> {
+    let name = match head.first() {
+        Some(Token::Function(name, _)) => name.clone(),
+        _ => return None,
+    };
+    if head.get(1) != Some(&Token::LeftParenthesis) || head.last() != Some(&Token::RightParenthesis) {
+        return None;
+    }
+    let mut parameters = Vec::<String>::new();
+    for (index, token) in head[2..head.len() - 1].iter().enumerate() {
+        match token {
+            Token::Identifier(parameter) if index % 2 == 0 && !parameters.contains(parameter) => {
+                parameters.push(parameter.clone());
+            }
+            Token::Comma if index % 2 == 1 => {}
+            _ => return None,
         }
     }
+    if head.len() > 3 && head[head.len() - 2] == Token::Comma {
+        return None;
+    }
+    Some((name, parameters))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::{expected, parse, parse_script, TokenIterator};
+
+    fn errors(input: &str) -> Vec<(ErrorCode, String)> {
+        parse_script(input).unwrap_err().iter().map(|error| (error.code, error.position.map(|at| at.to_string()).unwrap_or_default())).collect()
+    }
+
+    #[test]
+    fn reports_every_error_in_a_script() {
+        let found = errors("1 + @\n2 * * 3\n$x = ~ + 1\nsin(1, @) + 2\n4");
+        let expected = [
+            (ErrorCode::LexerError, "1:5"),
+            (ErrorCode::MalformedExpression, "2:5"),
+            (ErrorCode::LexerError, "3:6"),
+            (ErrorCode::LexerError, "4:8"),
+        ];
+        assert_eq!(found, expected.map(|(code, at)| (code, at.to_string())));
+    }
+
+    #[test]
+    fn carries_on_after_a_bad_statement() {
+        let mut parser = Parser::new(TokenIterator::new("2 * * 3; 1 + 2", 10));
+        assert_eq!(parser.intermediate().unwrap_err().code, ErrorCode::MalformedExpression);
+        assert_eq!(parser.intermediate().unwrap(), parse("1 + 2").unwrap());
+        assert!(parser.intermediate().unwrap().is_empty());
+    }
+
+    #[test]
+    fn names_what_was_expected() {
+        let error = parse("(1 + 2").unwrap_err();
+        assert_eq!(error.expected, BTreeSet::from([Expected::ArithmeticOperator, Expected::RightParenthesis]));
+        assert_eq!(error.message, "Expected an operator or ')' after '2', found the end of the expression.");
+        let error = parse("2 * ").unwrap_err();
+        assert_eq!(error.message, "Expected a number, variable, function or '(' after '*', found the end of the expression.");
+    }
+
+    #[test]
+    fn offers_what_may_come_next() {
+        assert!(expected("2 * ").contains(&Expected::Number));
+        assert!(!expected("2 * ").contains(&Expected::ArithmeticOperator));
+        assert_eq!(expected("sin(1"), BTreeSet::from([Expected::ArithmeticOperator, Expected::RightParenthesis, Expected::Comma]));
+    }
 }
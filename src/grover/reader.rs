@@ -0,0 +1,121 @@
+use std::io::BufRead;
+use std::io::ErrorKind;
+
+pub trait Source: Iterator<Item = char> {
+    fn get_error(&self) -> Option<&String> {
+        None
+    }
+}
+
+impl Source for std::str::Chars<'_> {}
+
+// Decodes UTF-8 from a reader one character at a time so that
+// sequences split across buffer refills are reassembled.
+pub struct ReadChars<R: BufRead> {
+    reader: R,
+    error: Option<String>,
+}
+
+impl<R: BufRead> ReadChars<R> {
+    pub fn new(reader: R) -> Self {
+        ReadChars {
+            reader,
+            error: None,
+        }
+    }
+    fn fail(&mut self, message: String) -> Option<char> {
+        self.error = Some(message);
+        None
+    }
+}
+
+impl<R: BufRead> Source for ReadChars<R> {
+    fn get_error(&self) -> Option<&String> {
+        self.error.as_ref()
+    }
+}
+
+impl<R: BufRead> Iterator for ReadChars<R> {
+    type Item = char;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.error.is_some() {
+            return None;
+        }
+        let mut bytes = [0u8; 4];
+        let mut length = 0;
+        let mut width = 1;
+        while length < width {
+            let buffer = match self.reader.fill_buf() {
+                Ok(buffer) => buffer,
+                Err(ref error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return self.fail(format!("Could not read input: {}", error)),
+            };
+            if buffer.is_empty() {
+                if length > 0 {
+                    return self.fail("Input ended in the middle of a UTF-8 sequence".to_string());
+                }
+                return None;
+            }
+            if length == 0 {
+                width = match buffer[0] {
+                    0x00..=0x7F => 1,
+                    0xC0..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    0xF0..=0xF7 => 4,
+                    byte => {
+                        self.reader.consume(1);
+                        return self.fail(format!("Invalid UTF-8 byte 0x{:02X}", byte));
+                    }
+                };
+            }
+            let count = std::cmp::min(width - length, buffer.len());
+            bytes[length..length + count].copy_from_slice(&buffer[..count]);
+            length += count;
+            self.reader.consume(count);
+        }
+        match std::str::from_utf8(&bytes[..width]) {
+            Ok(string) => string.chars().next(),
+            Err(_) => self.fail(format!("Invalid UTF-8 sequence {:02X?}", &bytes[..width])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::{parse_reader, parse_script, Lexer, TokenIterator};
+    use std::io::BufReader;
+
+    // A reader that hands out one byte at a time, so every longer character is split across refills.
+    fn trickle(input: &[u8]) -> ReadChars<BufReader<&[u8]>> {
+        ReadChars::new(BufReader::with_capacity(1, input))
+    }
+
+    #[test]
+    fn reassembles_characters_split_across_reads() {
+        let input = "$α·β = 2 € 𝄞";
+        let mut chars = trickle(input.as_bytes());
+        assert_eq!(chars.by_ref().collect::<String>(), input);
+        assert_eq!(chars.get_error(), None);
+    }
+
+    #[test]
+    fn reports_invalid_and_truncated_utf8() {
+        let mut chars = trickle(b"1 \xFF 2");
+        assert_eq!(chars.by_ref().collect::<String>(), "1 ");
+        assert_eq!(chars.get_error().map(String::as_str), Some("Invalid UTF-8 byte 0xFF"));
+        let mut chars = trickle("2 €".as_bytes().split_last().unwrap().1);
+        assert_eq!(chars.by_ref().collect::<String>(), "2 ");
+        assert!(chars.get_error().is_some());
+        assert!(parse_reader(&b"1 + \xFF"[..]).is_err());
+    }
+
+    #[test]
+    fn lexes_the_same_as_a_string() {
+        let input = "$größe = 2\n$größe * 3\n";
+        assert_eq!(parse_reader(BufReader::with_capacity(1, input.as_bytes())).unwrap(), parse_script(input).unwrap());
+        let mut lexer = TokenIterator::from_reader(BufReader::with_capacity(1, input.as_bytes()), 10);
+        let positions: Vec<String> = std::iter::from_fn(|| lexer.next().map(|_| lexer.position().to_string())).collect();
+        assert_eq!(positions[4..6], ["2:1", "2:8"]);
+    }
+}
@@ -0,0 +1,425 @@
+use crate::grover::{csv, error, evaluator, parser, token};
+use error::Error;
+use error::ErrorCode;
+use evaluator::Evaluator;
+use parser::Parser;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use token::Token;
+use token::TokenIterator;
+use token::Tokens;
+
+// Zero-based row and column of a cell. Ordered row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CellRef {
+    pub row: u32,
+    pub column: u32,
+}
+
+impl CellRef {
+    pub fn parse(name: &str) -> Option<CellRef> {
+        if !token::is_cell(name) {
+            return None;
+        }
+        let letters = name.chars().take_while(|character| character.is_ascii_uppercase()).count();
+        let mut column = 0u32;
+        for character in name[..letters].chars() {
+            column = column.checked_mul(26)?.checked_add(character as u32 - 'A' as u32 + 1)?;
+        }
+        let row: u32 = name[letters..].parse().ok()?;
+        Some(CellRef { row: row - 1, column: column - 1 })
+    }
+}
+
+impl std::fmt::Display for CellRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut letters = Vec::<char>::new();
+        let mut column = self.column as u64 + 1;
+        while column > 0 {
+            column -= 1;
+            letters.push((b'A' + (column % 26) as u8) as char);
+            column /= 26;
+        }
+        let letters: String = letters.iter().rev().collect();
+        write!(f, "{}{}", letters, self.row as u64 + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Empty,
+    Number(f64),
+    Text(String),
+    Error(Error),
+}
+
+enum Content {
+    Number(f64),
+    Text(String),
+    // Everything after the leading '='.
+    Formula(Result<Tokens, Error>),
+}
+
+struct Cell {
+    input: String,
+    content: Content,
+    value: Value,
+}
+
+// A grid of cells holding numbers, text or formulas such as `=sum(A1:A10) * $rate`.
+// Formulas are evaluated by the sheet's evaluator, so they can use its variables and functions.
+pub struct Sheet {
+    cells: BTreeMap<CellRef, Cell>,
+    evaluator: Evaluator,
+}
+
+impl Default for Sheet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sheet {
+    pub fn new() -> Self {
+        Sheet::with_evaluator(Evaluator::new())
+    }
+    pub fn with_evaluator(evaluator: Evaluator) -> Self {
+        Sheet {
+            cells: BTreeMap::new(),
+            evaluator,
+        }
+    }
+    pub fn evaluator(&mut self) -> &mut Evaluator {
+        &mut self.evaluator
+    }
+    // Sets a cell and recalculates the sheet. Empty input clears the cell.
+    pub fn set(&mut self, cell: &str, input: &str) -> Result<(), Error> {
+        let at = reference(cell)?;
+        self.put(at, input);
+        self.recalculate();
+        Ok(())
+    }
+    pub fn value(&self, cell: &str) -> Result<Value, Error> {
+        let at = reference(cell)?;
+        Ok(match self.cells.get(&at) {
+            Some(cell) => cell.value.clone(),
+            None => Value::Empty,
+        })
+    }
+    pub fn input(&self, cell: &str) -> Result<Option<&str>, Error> {
+        let at = reference(cell)?;
+        Ok(self.cells.get(&at).map(|cell| cell.input.as_str()))
+    }
+    pub fn cells(&self) -> impl Iterator<Item = (CellRef, &Value)> {
+        self.cells.iter().map(|(at, cell)| (*at, &cell.value))
+    }
+    fn put(&mut self, at: CellRef, input: &str) {
+        if input.trim().is_empty() {
+            self.cells.remove(&at);
+            return;
+        }
+        let content = match input.strip_prefix('=') {
+            Some(formula) => Content::Formula(self.parse(formula)),
+            None => match input.trim().parse::<f64>() {
+                Ok(number) if number.is_finite() => Content::Number(number),
+                _ => Content::Text(input.to_string()),
+            },
+        };
+        let value = match content {
+            Content::Number(number) => Value::Number(number),
+            Content::Text(ref text) => Value::Text(text.clone()),
+            Content::Formula(_) => Value::Empty,
+        };
+        self.cells.insert(at, Cell { input: input.to_string(), content, value });
+    }
+    fn parse(&self, formula: &str) -> Result<Tokens, Error> {
+        let mut parser = Parser::new(TokenIterator::new(formula, self.evaluator.radix()));
+        let mut statements = parser.script().map_err(|mut errors| errors.remove(0))?;
+        match statements.pop() {
+            Some(tokens) if statements.is_empty() => Ok(tokens),
+            _ => Err(Error::new(ErrorCode::MalformedExpression, "A formula must be a single expression.".to_string())),
+        }
+    }
+    // Cells a formula reads, including the cells of its ranges that hold anything.
+    fn dependencies(&self, at: CellRef) -> Vec<CellRef> {
+        let tokens = match self.cells.get(&at) {
+            Some(Cell { content: Content::Formula(Ok(tokens)), .. }) => tokens,
+            _ => return Vec::new(),
+        };
+        let mut dependencies = Vec::<CellRef>::new();
+        for token in tokens.iter() {
+            match token {
+                Token::Cell(name) => dependencies.extend(CellRef::parse(name)),
+                Token::Range(start, end) => {
+                    if let (Some(start), Some(end)) = (CellRef::parse(start), CellRef::parse(end)) {
+                        dependencies.extend(self.within(start, end));
+                    }
+                }
+                _ => {}
+            }
+        }
+        dependencies
+    }
+    // The cells that are set in the rectangle with the given corners.
+    fn within(&self, start: CellRef, end: CellRef) -> impl Iterator<Item = CellRef> + '_ {
+        let columns = start.column.min(end.column)..=start.column.max(end.column);
+        let first = CellRef { row: start.row.min(end.row), column: 0 };
+        let last = CellRef { row: start.row.max(end.row), column: u32::MAX };
+        self.cells.range(first..=last).map(|(at, _)| *at).filter(move |at| columns.contains(&at.column))
+    }
+    fn is_formula(&self, at: CellRef) -> bool {
+        matches!(self.cells.get(&at), Some(Cell { content: Content::Formula(_), .. }))
+    }
+    // Evaluates every formula after the cells it reads. Cells on a cycle get an error naming it.
+    pub fn recalculate(&mut self) {
+        let formulas: Vec<CellRef> = self.cells.keys().cloned().filter(|at| self.is_formula(*at)).collect();
+        // false while a cell is being visited, true once it is ordered.
+        let mut visited = HashMap::<CellRef, bool>::new();
+        let mut order = Vec::<CellRef>::new();
+        let mut cycles = HashMap::<CellRef, Error>::new();
+        for start in formulas {
+            if visited.contains_key(&start) {
+                continue;
+            }
+            visited.insert(start, false);
+            let mut stack = vec![(start, self.dependencies(start), 0usize)];
+            while let Some((at, dependencies, next)) = stack.last_mut() {
+                if *next == dependencies.len() {
+                    let at = *at;
+                    stack.pop();
+                    visited.insert(at, true);
+                    order.push(at);
+                    continue;
+                }
+                let dependency = dependencies[*next];
+                *next += 1;
+                if !self.is_formula(dependency) {
+                    continue;
+                }
+                match visited.get(&dependency) {
+                    Some(true) => {}
+                    Some(false) => {
+                        let index = stack.iter().position(|(at, _, _)| *at == dependency).expect("Cells being visited are on the stack.");
+                        let cycle: Vec<CellRef> = stack[index..].iter().map(|(at, _, _)| *at).collect();
+                        for (offset, at) in cycle.iter().enumerate() {
+                            let path: Vec<String> = cycle[offset..].iter().chain(cycle[..=offset].iter()).map(|at| at.to_string()).collect();
+                            cycles.entry(*at).or_insert_with(|| {
+                                Error::new(ErrorCode::CyclicBinding, format!("{} depends on itself: {}.", at, path.join(" -> ")))
+                            });
+                        }
+                    }
+                    None => {
+                        visited.insert(dependency, false);
+                        let dependencies = self.dependencies(dependency);
+                        stack.push((dependency, dependencies, 0));
+                    }
+                }
+            }
+        }
+        for at in order {
+            let value = match cycles.remove(&at) {
+                Some(error) => Value::Error(error),
+                None => self.compute(at),
+            };
+            if let Some(cell) = self.cells.get_mut(&at) {
+                cell.value = value;
+            }
+        }
+    }
+    fn compute(&mut self, at: CellRef) -> Value {
+        let tokens = match self.cells.get(&at) {
+            Some(Cell { content: Content::Formula(Ok(tokens)), .. }) => tokens.clone(),
+            Some(Cell { content: Content::Formula(Err(error)), .. }) => return Value::Error(error.clone()),
+            _ => return Value::Empty,
+        };
+        let tokens = match self.substitute(tokens) {
+            Ok(tokens) => tokens,
+            Err(error) => return Value::Error(error),
+        };
+        match self.evaluator.evaluate(tokens).and_then(|value| value.number()) {
+            Ok(value) => Value::Number(value),
+            Err(error) => Value::Error(error),
+        }
+    }
+    // Replaces cell references with their values. A range becomes one argument per cell that is set,
+    // so the arity of the call it is passed to grows to match.
+    fn substitute(&self, tokens: Tokens) -> Result<Tokens, Error> {
+        let mut output = Tokens::new();
+        // How many arguments each value on the evaluation stack stands for.
+        let mut widths = Vec::<usize>::new();
+        let operand = |widths: &mut Vec<usize>| -> Result<(), Error> {
+            match widths.pop() {
+                Some(1) => Ok(()),
+                Some(_) => Err(Error::new(ErrorCode::MalformedExpression, "A range can only be a function argument.".to_string())),
+                None => Err(Error::new(ErrorCode::MalformedExpression, "Malformed formula.".to_string())),
+            }
+        };
+        for token in tokens {
+            match token {
+                Token::Cell(ref name) => {
+                    output.push(Token::Number(self.operand(name)?));
+                    widths.push(1);
+                }
+                Token::Range(ref start, ref end) => {
+                    let (start, end) = match (CellRef::parse(start), CellRef::parse(end)) {
+                        (Some(start), Some(end)) => (start, end),
+                        _ => return Err(Error::new(ErrorCode::MalformedExpression, format!("Invalid range {}:{}.", start, end))),
+                    };
+                    // Empty cells are left out, so they count towards neither `min` nor `mean`.
+                    let cells: Vec<CellRef> = self.within(start, end).filter(|at| self.cells[at].value != Value::Empty).collect();
+                    for at in &cells {
+                        output.push(Token::Number(self.operand(&at.to_string())?));
+                    }
+                    widths.push(cells.len());
+                }
+                Token::Function(name, arity) => {
+                    let mut count = 0;
+                    for _ in 0..arity {
+                        count += widths.pop().unwrap_or(1);
+                    }
+                    output.push(Token::Function(name, count));
+                    widths.push(1);
+                }
+                Token::Operator(_) => {
+                    operand(&mut widths)?;
+                    operand(&mut widths)?;
+                    widths.push(1);
+                    output.push(token);
+                }
+                Token::Separator => {
+                    operand(&mut widths)?;
+                    output.push(token);
+                }
+                Token::Let(_) | Token::RightBrace => {
+                    operand(&mut widths)?;
+                    widths.push(1);
+                    output.push(token);
+                }
+                Token::Number(_) | Token::Identifier(_) => {
+                    widths.push(1);
+                    output.push(token);
+                }
+                _ => output.push(token),
+            }
+        }
+        Ok(output)
+    }
+    fn operand(&self, name: &str) -> Result<f64, Error> {
+        let at = reference(name)?;
+        match self.cells.get(&at).map(|cell| &cell.value) {
+            None | Some(Value::Empty) => Ok(0f64),
+            Some(Value::Number(number)) => Ok(*number),
+            Some(Value::Text(_)) => Err(Error::new(ErrorCode::EvaluatorError, format!("{} holds text, not a number.", at))),
+            Some(Value::Error(error)) => Err(Error::new(error.code, format!("{} has an error.", at)).with_cause(error.clone())),
+        }
+    }
+    // Reads a sheet from CSV, where fields starting with '=' are formulas.
+    pub fn from_csv(input: &str) -> Result<Self, Error> {
+        let mut sheet = Sheet::new();
+        sheet.load_csv(input)?;
+        Ok(sheet)
+    }
+    pub fn load_csv(&mut self, input: &str) -> Result<(), Error> {
+        for (row, record) in csv::parse(input)?.iter().enumerate() {
+            for (column, field) in record.iter().enumerate() {
+                self.put(CellRef { row: row as u32, column: column as u32 }, field);
+            }
+        }
+        self.recalculate();
+        Ok(())
+    }
+    // Writes either what was entered in each cell or what it evaluates to.
+    // Errors are written as their code, e.g. `#E301`.
+    pub fn to_csv(&self, formulas: bool) -> String {
+        let rows = self.cells.keys().map(|at| at.row + 1).max().unwrap_or(0);
+        let columns = self.cells.keys().map(|at| at.column + 1).max().unwrap_or(0);
+        let mut records = vec![vec![String::new(); columns as usize]; rows as usize];
+        for (at, cell) in &self.cells {
+            records[at.row as usize][at.column as usize] = if formulas {
+                cell.input.clone()
+            } else {
+                match cell.value {
+                    Value::Empty => String::new(),
+                    Value::Number(number) => number.to_string(),
+                    Value::Text(ref text) => text.clone(),
+                    Value::Error(ref error) => format!("#{}", error.code),
+                }
+            };
+        }
+        csv::write(&records)
+    }
+}
+
+fn reference(name: &str) -> Result<CellRef, Error> {
+    CellRef::parse(name).ok_or_else(|| Error::new(ErrorCode::EvaluatorError, format!("\'{}\' is not a cell.", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(sheet: &Sheet, cell: &str) -> Option<ErrorCode> {
+        match sheet.value(cell).unwrap() {
+            Value::Error(error) => Some(error.code),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn finds_cycles() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", "=B1 + 1").unwrap();
+        sheet.set("B1", "=C1 * 2").unwrap();
+        sheet.set("C1", "=A1").unwrap();
+        sheet.set("D1", "=2 + 3").unwrap();
+        for cell in ["A1", "B1", "C1"] {
+            assert_eq!(code(&sheet, cell), Some(ErrorCode::CyclicBinding), "{}", cell);
+        }
+        match sheet.value("A1").unwrap() {
+            Value::Error(error) => assert_eq!(error.message, "A1 depends on itself: A1 -> B1 -> C1 -> A1."),
+            value => panic!("{:?}", value),
+        }
+        assert_eq!(sheet.value("D1").unwrap(), Value::Number(5f64));
+    }
+
+    #[test]
+    fn leaves_empty_cells_out_of_ranges() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", "4").unwrap();
+        sheet.set("A3", "2").unwrap();
+        sheet.set("B1", "=min(A1:A4)").unwrap();
+        sheet.set("B2", "=mean(A1:A4)").unwrap();
+        sheet.set("B3", "=sum(A1:A1000000)").unwrap();
+        assert_eq!(sheet.value("B1").unwrap(), Value::Number(2f64));
+        assert_eq!(sheet.value("B2").unwrap(), Value::Number(3f64));
+        assert_eq!(sheet.value("B3").unwrap(), Value::Number(6f64));
+    }
+
+    #[test]
+    fn finds_cells_that_refer_to_themselves() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", "=A1 + 1").unwrap();
+        assert_eq!(code(&sheet, "A1"), Some(ErrorCode::CyclicBinding));
+    }
+
+    #[test]
+    fn recovers_once_a_cycle_is_broken() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", "=B1 + 1").unwrap();
+        sheet.set("B1", "=A1").unwrap();
+        assert_eq!(code(&sheet, "B1"), Some(ErrorCode::CyclicBinding));
+        sheet.set("B1", "4").unwrap();
+        assert_eq!(sheet.value("A1").unwrap(), Value::Number(5f64));
+    }
+
+    #[test]
+    fn orders_chains_of_formulas() {
+        let mut sheet = Sheet::new();
+        sheet.set("A3", "=A2 * 2").unwrap();
+        sheet.set("A2", "=A1 + 1").unwrap();
+        sheet.set("A1", "1").unwrap();
+        sheet.set("B1", "=sum(A1:A3)").unwrap();
+        assert_eq!(sheet.value("A3").unwrap(), Value::Number(4f64));
+        assert_eq!(sheet.value("B1").unwrap(), Value::Number(7f64));
+    }
+}
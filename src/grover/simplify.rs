@@ -0,0 +1,340 @@
+use crate::grover::{error, evaluator, expression, format, function, token, value};
+use error::Error;
+use evaluator::Evaluator;
+use expression::Expression;
+use function::Definition;
+use token::Operator;
+use token::Tokens;
+use value::Value;
+
+// Functions defined for every real argument, which simplifying may drop when they are multiplied by zero.
+const TOTAL: [&str; 9] = ["abs", "sin", "cos", "atan", "tanh", "cbrt", "floor", "ceil", "round"];
+
+// A product of a coefficient and factors raised to numeric powers, as in `3 * $x ^ 2 * sin($y)`.
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    coefficient: f64,
+    // Kept in a canonical order, so equal products compare equal.
+    factors: Vec<(Expression, f64)>,
+}
+
+impl Term {
+    fn number(coefficient: f64) -> Self {
+        Term { coefficient, factors: Vec::new() }
+    }
+    fn factor(expression: Expression) -> Self {
+        Term { coefficient: 1f64, factors: vec![(expression, 1f64)] }
+    }
+    fn multiply(mut self, other: Term) -> Term {
+        self.coefficient *= other.coefficient;
+        for (base, exponent) in other.factors {
+            // A division only cancels into one that still divides, so `$x / $x` keeps failing at 0 rather than
+            // becoming 1.
+            let cancels = |power: f64| (power < 0f64) == (exponent < 0f64) || power + exponent < 0f64;
+            match self.factors.iter_mut().find(|(factor, power)| *factor == base && cancels(*power)) {
+                Some((_, power)) => *power += exponent,
+                None => self.factors.push((base, exponent)),
+            }
+        }
+        self.factors.retain(|(_, exponent)| *exponent != 0f64);
+        self.factors.sort_by_cached_key(|(base, _)| format::expression(base));
+        self
+    }
+    fn power(mut self, exponent: f64) -> Option<Term> {
+        let coefficient = self.coefficient.powf(exponent);
+        if !coefficient.is_finite() || exponent == 0f64 && !self.is_total() {
+            return None;
+        }
+        self.coefficient = coefficient;
+        for (_, power) in self.factors.iter_mut() {
+            *power *= exponent;
+        }
+        self.factors.retain(|(_, power)| *power != 0f64);
+        Some(self)
+    }
+    // Whether the term can be evaluated wherever its variables are defined, so that it may be dropped.
+    fn is_total(&self) -> bool {
+        self.factors.iter().all(|(base, power)| *power >= 0f64 && is_total(base))
+    }
+}
+
+// Whether an expression can be evaluated wherever its variables are defined. Division, roots, logarithms and the
+// like fail for some values, and simplifying must not drop them where they would.
+fn is_total(expression: &Expression) -> bool {
+    match expression {
+        Expression::Number(_) | Expression::Variable(_) | Expression::Cell(_) => true,
+        Expression::Negate(operand) => is_total(operand),
+        Expression::Call(name, arguments) => TOTAL.contains(&name.as_str()) && arguments.iter().all(is_total),
+        Expression::Binary(operator, lhs, rhs) => match operator.symbol {
+            "+" | "-" | "*" => is_total(lhs) && is_total(rhs),
+            "/" | "%" => is_total(lhs) && matches!(**rhs, Expression::Number(rhs) if rhs != 0f64),
+            "^" => is_total(lhs) && matches!(**rhs, Expression::Number(rhs) if rhs >= 0f64 && rhs.fract() == 0f64),
+            _ => false,
+        },
+        Expression::Matrix(rows) => rows.iter().flatten().all(is_total),
+        _ => false,
+    }
+}
+
+// Whether an expression may be a matrix, whose products do not commute and which does not cancel to the number 0.
+fn is_matrix(evaluator: &Evaluator, expression: &Expression) -> bool {
+    let is_matrix = |expression: &Expression| self::is_matrix(evaluator, expression);
+    match expression {
+        Expression::Number(_) | Expression::Cell(_) => false,
+        Expression::Variable(name) => matches!(evaluator.variable(name), Some(Value::Matrix(_))),
+        Expression::Negate(operand) => is_matrix(operand),
+        Expression::Binary(operator, lhs, rhs) if operator.symbol != ".." => is_matrix(lhs) || is_matrix(rhs),
+        // Functions on numbers apply element by element, except variadic ones such as max, which take in every element.
+        Expression::Call(name, arguments) => match evaluator.function(name) {
+            Some(function) if matches!(function.definition, Definition::Native(_)) => function.arity.is_some() && arguments.iter().any(is_matrix),
+            _ => true,
+        },
+        _ => true,
+    }
+}
+
+// Folds constants, drops the `1 *` and `-1 *` the parser uses for unary signs, applies identities
+// such as `x + 0`, `x * 1`, `x * 0` and `x ^ 1`, and collects like terms.
+pub fn simplify(evaluator: &Evaluator, expression: &Expression) -> Expression {
+    let simplify = |expression: &Expression| self::simplify(evaluator, expression);
+    match expression {
+        Expression::Number(_) | Expression::Variable(_) | Expression::Cell(_) | Expression::Range(_, _) => expression.clone(),
+        Expression::Call(name, arguments) => Expression::Call(name.clone(), arguments.iter().map(simplify).collect()),
+        Expression::Block(statements) => Expression::Block(statements.iter().map(simplify).collect()),
+        Expression::Let(name, value) => Expression::Let(name.clone(), Box::new(simplify(value))),
+        Expression::Lambda(parameters, body) => Expression::Lambda(parameters.clone(), Box::new(simplify(body))),
+        Expression::Matrix(rows) => Expression::Matrix(rows.iter().map(|row| row.iter().map(simplify).collect()).collect()),
+        Expression::Index(value, indices) => Expression::Index(Box::new(simplify(value)), indices.iter().map(simplify).collect()),
+        // Element-wise operators and ranges, products of matrices, which do not commute, and comparisons.
+        Expression::Binary(operator, lhs, rhs)
+            if operator.symbol.starts_with('.') || is_product_of_matrices(evaluator, operator, lhs, rhs) || operator.precedence == token::EQUAL_OPERATOR.precedence =>
+        {
+            Expression::Binary(operator, Box::new(simplify(lhs)), Box::new(simplify(rhs)))
+        }
+        Expression::Binary(operator, lhs, rhs) if operator.precedence == token::ASSIGNMENT_OPERATOR.precedence => {
+            Expression::Binary(operator, lhs.clone(), Box::new(simplify(rhs)))
+        }
+        Expression::Binary(operator, lhs, rhs) if **operator == token::REMAINDER_OPERATOR => {
+            match (simplify(lhs), simplify(rhs)) {
+                (Expression::Number(lhs), Expression::Number(rhs)) if rhs != 0f64 => Expression::Number(lhs % rhs),
+                (lhs, rhs) => Expression::Binary(operator, Box::new(lhs), Box::new(rhs)),
+            }
+        }
+        _ => sum(terms(evaluator, expression)),
+    }
+}
+
+// Simplifies a parsed statement, keeping what it defines or binds.
+pub fn statement(evaluator: &Evaluator, tokens: &Tokens) -> Result<Tokens, Error> {
+    expression::rewrite(tokens, |expression| Ok(simplify(evaluator, expression)))
+}
+
+// The expression as a sum of terms, with like terms combined.
+fn terms(evaluator: &Evaluator, expression: &Expression) -> Vec<Term> {
+    let mut terms = Vec::<Term>::new();
+    let mut add = |term: Term, sign: f64| match terms.iter_mut().find(|existing| existing.factors == term.factors) {
+        Some(existing) => existing.coefficient += sign * term.coefficient,
+        None => terms.push(Term { coefficient: sign * term.coefficient, ..term }),
+    };
+    match expression {
+        Expression::Binary(operator, lhs, rhs) if operator.symbol == "+" || operator.symbol == "-" => {
+            let sign = if operator.symbol == "+" { 1f64 } else { -1f64 };
+            for term in self::terms(evaluator, lhs) {
+                add(term, 1f64);
+            }
+            for term in self::terms(evaluator, rhs) {
+                add(term, sign);
+            }
+        }
+        Expression::Negate(operand) => {
+            for term in self::terms(evaluator, operand) {
+                add(term, -1f64);
+            }
+        }
+        _ => add(product(evaluator, expression), 1f64),
+    }
+    // Terms that cancel out are kept as `0 * ...` where they may fail or be matrices.
+    terms.retain(|term| term.coefficient != 0f64 || !term.is_total() || term.factors.iter().any(|(base, _)| is_matrix(evaluator, base)));
+    terms
+}
+
+// The expression as a single term, making sums an opaque factor.
+fn product(evaluator: &Evaluator, expression: &Expression) -> Term {
+    let product = |expression: &Expression| self::product(evaluator, expression);
+    match expression {
+        Expression::Number(number) => Term::number(*number),
+        Expression::Binary(operator, lhs, rhs) if is_product_of_matrices(evaluator, operator, lhs, rhs) => Term::factor(simplify(evaluator, expression)),
+        Expression::Binary(operator, lhs, rhs) if operator.symbol == "*" => product(lhs).multiply(product(rhs)),
+        Expression::Binary(operator, lhs, rhs) if operator.symbol == "/" => {
+            let denominator = product(rhs);
+            match denominator.clone().power(-1f64) {
+                Some(inverse) if denominator.coefficient != 0f64 && !is_matrix(evaluator, rhs) => product(lhs).multiply(inverse),
+                _ => Term::factor(Expression::Binary(operator, Box::new(simplify(evaluator, lhs)), Box::new(sum(vec![denominator])))),
+            }
+        }
+        Expression::Binary(operator, lhs, rhs) if operator.symbol == "^" => power(evaluator, operator, lhs, rhs),
+        Expression::Binary(operator, _, _) if operator.symbol == "+" || operator.symbol == "-" => sum_of(evaluator, expression),
+        Expression::Negate(_) => sum_of(evaluator, expression),
+        _ => match simplify(evaluator, expression) {
+            Expression::Number(number) => Term::number(number),
+            expression => Term::factor(expression),
+        },
+    }
+}
+
+// A sum as a single term, which is only a plain product if its terms combine into one.
+fn sum_of(evaluator: &Evaluator, expression: &Expression) -> Term {
+    let mut terms = terms(evaluator, expression);
+    match terms.len() {
+        0 => Term::number(0f64),
+        1 => terms.remove(0),
+        _ => {
+            // Ordered, so `$a + $b` and `$b + $a` are the same factor.
+            terms.sort_by_cached_key(|term| format::expression(&monomial(&Term { coefficient: 1f64, ..term.clone() })));
+            Term::factor(sum(terms))
+        }
+    }
+}
+
+fn power(evaluator: &Evaluator, operator: &'static Operator, base: &Expression, exponent: &Expression) -> Term {
+    let exponent = simplify(evaluator, exponent);
+    let base = product(evaluator, base);
+    if let Expression::Number(exponent) = exponent {
+        // `(x ^ 2) ^ 0.5` is `|x|`, not `x`, so only whole powers distribute.
+        if exponent.fract() == 0f64 || base.factors.is_empty() {
+            if let Some(term) = base.clone().power(exponent) {
+                return term;
+            }
+        }
+        if base.coefficient == 1f64 && base.factors.len() == 1 && base.factors[0].1 == 1f64 {
+            return Term { factors: vec![(base.factors[0].0.clone(), exponent)], ..base };
+        }
+        return Term::factor(Expression::Binary(operator, Box::new(sum(vec![base])), Box::new(Expression::Number(exponent))));
+    }
+    if base == Term::number(1f64) {
+        return base;
+    }
+    Term::factor(Expression::Binary(operator, Box::new(sum(vec![base])), Box::new(exponent)))
+}
+
+fn is_product_of_matrices(evaluator: &Evaluator, operator: &Operator, lhs: &Expression, rhs: &Expression) -> bool {
+    operator.symbol == "*" && is_matrix(evaluator, lhs) && is_matrix(evaluator, rhs)
+}
+
+// Rebuilds an expression from terms, subtracting the negative ones and putting the constant last.
+fn sum(mut terms: Vec<Term>) -> Expression {
+    if let Some(index) = terms.iter().position(|term| term.factors.is_empty()) {
+        let constant = terms.remove(index);
+        terms.push(constant);
+    }
+    let mut expression: Option<Expression> = None;
+    for term in terms {
+        expression = Some(match expression {
+            None => monomial(&term),
+            Some(sum) if term.coefficient < 0f64 => {
+                let term = Term { coefficient: -term.coefficient, ..term };
+                Expression::Binary(&token::SUBTRACTION_OPERATOR, Box::new(sum), Box::new(monomial(&term)))
+            }
+            Some(sum) => Expression::Binary(&token::ADDITION_OPERATOR, Box::new(sum), Box::new(monomial(&term))),
+        });
+    }
+    expression.unwrap_or(Expression::Number(0f64))
+}
+
+// Writes a term as `c * a * b ^ 2 / d`, with negative powers in the denominator.
+fn monomial(term: &Term) -> Expression {
+    let mut numerator: Option<Expression> = None;
+    let mut denominator: Option<Expression> = None;
+    for (base, exponent) in term.factors.iter() {
+        let factor = |exponent: f64| {
+            if exponent == 1f64 {
+                base.clone()
+            } else {
+                Expression::Binary(&token::POWER_OPERATOR, Box::new(base.clone()), Box::new(Expression::Number(exponent)))
+            }
+        };
+        if *exponent > 0f64 {
+            numerator = Some(multiply(numerator, factor(*exponent)));
+        } else {
+            denominator = Some(multiply(denominator, factor(-exponent)));
+        }
+    }
+    let mut coefficient = term.coefficient.abs();
+    // A coefficient like 0.5 reads better as division by 2.
+    let reciprocal = 1f64 / coefficient;
+    if coefficient < 1f64 && reciprocal.fract() == 0f64 && !term.factors.is_empty() {
+        coefficient = 1f64;
+        denominator = Some(match denominator {
+            Some(denominator) => multiply(Some(Expression::Number(reciprocal)), denominator),
+            None => Expression::Number(reciprocal),
+        });
+    }
+    // The sign goes on the first factor, as in `-2 * $x` or `-$x / 3`.
+    let negative = term.coefficient < 0f64;
+    let numerator = match numerator {
+        Some(numerator) if coefficient == 1f64 && negative => negate(numerator),
+        Some(numerator) if coefficient == 1f64 => numerator,
+        Some(numerator) => multiply(Some(Expression::Number(if negative { -coefficient } else { coefficient })), numerator),
+        None => Expression::Number(if negative { -coefficient } else { coefficient }),
+    };
+    match denominator {
+        Some(denominator) => Expression::Binary(&token::DIVISION_OPERATOR, Box::new(numerator), Box::new(denominator)),
+        None => numerator,
+    }
+}
+
+fn negate(expression: Expression) -> Expression {
+    match expression {
+        Expression::Binary(operator, lhs, rhs) if *operator == token::MULTIPLICATION_OPERATOR => {
+            Expression::Binary(operator, Box::new(negate(*lhs)), rhs)
+        }
+        Expression::Number(number) => Expression::Number(-number),
+        expression => Expression::Negate(Box::new(expression)),
+    }
+}
+
+fn multiply(product: Option<Expression>, factor: Expression) -> Expression {
+    match product {
+        Some(product) => Expression::Binary(&token::MULTIPLICATION_OPERATOR, Box::new(product), Box::new(factor)),
+        None => factor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::{error::ErrorCode, eval, matrix::Matrix};
+
+    fn simplified(input: &str, evaluator: &Evaluator) -> String {
+        let tokens = crate::grover::parse(input).unwrap();
+        format::statement(&statement(evaluator, &tokens).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn collects_like_terms_of_numbers() {
+        let mut evaluator = Evaluator::new();
+        eval("$x = 2", &mut evaluator).unwrap();
+        assert_eq!(simplified("$x + $x", &evaluator), "2 * $x");
+        assert_eq!(simplified("$x * $x / $x", &evaluator), "$x ^ 2 / $x");
+        assert_eq!(simplified("$x / $x ^ 2", &evaluator), "1 / $x");
+    }
+
+    #[test]
+    fn keeps_the_order_of_matrix_products() {
+        let mut evaluator = Evaluator::new();
+        eval("$a = [1, 2; 3, 4]; $b = [0, 1; 1, 0]", &mut evaluator).unwrap();
+        let expected = Value::Matrix(Matrix::new(2, 2, vec![-1f64, -3f64, 3f64, 1f64]));
+        assert_eq!(eval("simplify($a * $b - $b * $a)", &mut evaluator).unwrap(), expected);
+        assert_eq!(eval("simplify($a - $a)", &mut evaluator).unwrap(), Value::Matrix(Matrix::new(2, 2, vec![0f64; 4])));
+    }
+
+    #[test]
+    fn keeps_what_can_fail() {
+        let mut evaluator = Evaluator::new();
+        eval("$x = 0", &mut evaluator).unwrap();
+        assert_eq!(eval("simplify($x / $x)", &mut evaluator).unwrap_err().code, ErrorCode::DivisionByZero);
+        eval("$x = -1", &mut evaluator).unwrap();
+        assert_eq!(eval("simplify(0 * ln($x))", &mut evaluator).unwrap_err().code, ErrorCode::DomainError);
+        assert_eq!(eval("simplify(0 * sin($x))", &mut evaluator).unwrap(), Value::Number(0f64));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::grover::error;
+use error::Error;
+use error::ErrorCode;
+
+const MAX_ITERATIONS: usize = 200;
+
+// Newton's method from a guess, given the derivative.
+pub fn newton<F, D>(f: &mut F, derivative: &mut D, guess: f64) -> Result<f64, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+    D: FnMut(f64) -> Result<f64, Error>,
+{
+    let mut x = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let value = f(x)?;
+        if value == 0f64 {
+            return Ok(x);
+        }
+        let slope = derivative(x)?;
+        if slope == 0f64 || !slope.is_finite() {
+            break;
+        }
+        let step = value / slope;
+        x -= step;
+        if !x.is_finite() {
+            break;
+        }
+        if step.abs() <= 4f64 * f64::EPSILON * x.abs().max(1f64) {
+            return Ok(x);
+        }
+    }
+    Err(Error::new(ErrorCode::NoConvergence, format!("Newton's method does not converge from {}.", guess)))
+}
+
+// Brent's method, combining bisection with secant and inverse quadratic steps, between points where the sign differs.
+pub fn brent<F>(f: &mut F, mut a: f64, mut b: f64) -> Result<f64, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let (mut fa, mut fb) = (f(a)?, f(b)?);
+    if fa == 0f64 {
+        return Ok(a);
+    }
+    if fb == 0f64 {
+        return Ok(b);
+    }
+    if fa.signum() == fb.signum() {
+        return Err(Error::new(ErrorCode::NoConvergence, format!("The expression has the same sign at {} and {}.", a, b)));
+    }
+    let (mut c, mut fc) = (a, fa);
+    let mut d = b - a;
+    let mut e = d;
+    for _ in 0..MAX_ITERATIONS {
+        if fb.signum() == fc.signum() {
+            c = a;
+            fc = fa;
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            a = b;
+            b = c;
+            c = a;
+            fa = fb;
+            fb = fc;
+            fc = fa;
+        }
+        let tolerance = 2f64 * f64::EPSILON * b.abs() + f64::MIN_POSITIVE;
+        let middle = (c - b) / 2f64;
+        if middle.abs() <= tolerance || fb == 0f64 {
+            return Ok(b);
+        }
+        if e.abs() >= tolerance && fa.abs() > fb.abs() {
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2f64 * middle * s, 1f64 - s)
+            } else {
+                let q = fa / fc;
+                let r = fb / fc;
+                (s * (2f64 * middle * q * (q - r) - (b - a) * (r - 1f64)), (q - 1f64) * (r - 1f64) * (s - 1f64))
+            };
+            if p > 0f64 {
+                q = -q;
+            } else {
+                p = -p;
+            }
+            if 2f64 * p < (3f64 * middle * q - (tolerance * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = middle;
+                e = d;
+            }
+        } else {
+            d = middle;
+            e = d;
+        }
+        a = b;
+        fa = fb;
+        b += if d.abs() > tolerance { d } else { tolerance.copysign(middle) };
+        fb = f(b)?;
+    }
+    Err(Error::new(ErrorCode::NoConvergence, format!("Brent's method does not converge between {} and {}.", a, b)))
+}
+
+// Widens an interval around a guess until the sign changes across it, then narrows in with Brent's method.
+pub fn bracket<F>(f: &mut F, guess: f64) -> Result<f64, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+{
+    let center = f(guess)?;
+    let scale = guess.abs().max(1f64);
+    let mut width = 0.01 * scale;
+    while width < 1e9 * scale {
+        for x in [guess - width, guess + width] {
+            let value = match defined(f(x))? {
+                Some(value) if value.signum() != center.signum() => value,
+                _ => continue,
+            };
+            if let Some(root) = converged(brent(f, guess, x))? {
+                if defined(f(root))?.is_some_and(|found| found.abs() <= center.abs().max(value.abs())) {
+                    return Ok(root);
+                }
+            }
+        }
+        width *= 1.6;
+    }
+    Err(Error::new(ErrorCode::NoConvergence, format!("No root was found near {}.", guess)))
+}
+
+// Whether an error only means the expression is not defined at a point, as with `ln($x)` below zero, rather than
+// that it can not be evaluated at all.
+pub fn is_undefined_at(error: &Error) -> bool {
+    matches!(error.code, ErrorCode::ArithmeticError | ErrorCode::DivisionByZero | ErrorCode::DomainError | ErrorCode::Overflow)
+}
+
+// The value at a point, or none where the expression is not defined.
+fn defined(result: Result<f64, Error>) -> Result<Option<f64>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value).filter(|value| value.is_finite())),
+        Err(error) if is_undefined_at(&error) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+// The root a method converged to, or none where it did not.
+fn converged(result: Result<f64, Error>) -> Result<Option<f64>, Error> {
+    match result {
+        Ok(root) => Ok(Some(root)),
+        Err(error) if error.code == ErrorCode::NoConvergence || is_undefined_at(&error) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+// Every real root at or between the given points, found from sign changes and from dips that touch zero.
+pub fn roots<F, D>(f: &mut F, derivative: &mut D, points: &[f64]) -> Result<Vec<f64>, Error>
+where
+    F: FnMut(f64) -> Result<f64, Error>,
+    D: FnMut(f64) -> Result<f64, Error>,
+{
+    // Points the expression is not defined at are skipped.
+    let samples = points.iter().map(|x| Ok((*x, defined(f(*x))?))).collect::<Result<Vec<(f64, Option<f64>)>, Error>>()?;
+    let mut found = Vec::<f64>::new();
+    for (index, (x, value)) in samples.iter().enumerate() {
+        match value {
+            Some(value) if *value == 0f64 => found.push(*x),
+            _ => {}
+        }
+        // Zero at two samples in a row, as with `0 * $x`, is zero all the way between them, with no roots to list.
+        if let (Some(0f64), Some((next, Some(0f64)))) = (value, samples.get(index + 1)) {
+            return Err(Error::new(ErrorCode::DomainError, format!("The expression is 0 everywhere from {} to {}, so its roots can not be listed.", x, next)));
+        }
+        let (a, fa) = match samples.get(index + 1) {
+            Some((next, Some(next_value))) => (*next, *next_value),
+            _ => continue,
+        };
+        let fx = match value {
+            Some(value) if *value != 0f64 && fa != 0f64 => *value,
+            _ => continue,
+        };
+        if fx.signum() != fa.signum() {
+            // A pole changes sign too, but grows rather than shrinks on the way.
+            if let Some(root) = converged(brent(f, *x, a))? {
+                if defined(f(root))?.is_some_and(|value| value.abs() <= fx.abs().max(fa.abs())) {
+                    found.push(root);
+                }
+            }
+        } else if let Some((_, Some(previous))) = index.checked_sub(1).and_then(|previous| samples.get(previous)) {
+            // A root where the expression only touches zero, as with `$x ^ 2`.
+            if fx.abs() < previous.abs() && fx.abs() < fa.abs() {
+                if let Some(root) = converged(newton(f, derivative, *x))? {
+                    let scale = previous.abs().max(fa.abs());
+                    if root > points[index - 1] && root < a && defined(f(root))?.is_some_and(|value| value.abs() <= 1e-10 * scale.max(1f64)) {
+                        found.push(root);
+                    }
+                }
+            }
+        }
+    }
+    found.sort_by(f64::total_cmp);
+    found.dedup_by(|later, earlier| (*later - *earlier).abs() <= 1e-9 * later.abs().max(1f64));
+    Ok(found)
+}
+
+// Evenly spaced points from a to b.
+pub fn span(a: f64, b: f64, intervals: usize) -> Vec<f64> {
+    (0..=intervals).map(|index| a + (b - a) * index as f64 / intervals as f64).collect()
+}
+
+// Points for a search without bounds: closely spaced near zero and further apart further out.
+pub fn everywhere() -> Vec<f64> {
+    let mut far = Vec::<f64>::new();
+    let mut x = 10f64;
+    while x < 1e9 {
+        x *= 1.05;
+        far.push(x);
+    }
+    let mut points: Vec<f64> = far.iter().rev().map(|x| -x).collect();
+    points.extend(span(-10f64, 10f64, 2000));
+    points.extend(far);
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(found: &[f64], expected: &[f64]) -> bool {
+        found.len() == expected.len() && found.iter().zip(expected).all(|(a, b)| (a - b).abs() <= 1e-9 * b.abs().max(1f64))
+    }
+
+    #[test]
+    fn finds_every_root() {
+        let found = roots(&mut |x: f64| Ok(x * x - 2f64), &mut |x: f64| Ok(2f64 * x), &everywhere()).unwrap();
+        assert!(close(&found, &[-std::f64::consts::SQRT_2, std::f64::consts::SQRT_2]), "{:?}", found);
+        let found = roots(&mut |x: f64| Ok(x.sin()), &mut |x: f64| Ok(x.cos()), &span(-4f64, 4f64, 1000)).unwrap();
+        assert!(close(&found, &[-std::f64::consts::PI, 0f64, std::f64::consts::PI]), "{:?}", found);
+    }
+
+    #[test]
+    fn finds_roots_that_touch_zero() {
+        let found = roots(&mut |x: f64| Ok((x - 1f64).powi(2)), &mut |x: f64| Ok(2f64 * (x - 1f64)), &span(-5f64, 5f64, 1000)).unwrap();
+        assert_eq!(found.len(), 1);
+        assert!((found[0] - 1f64).abs() <= 1e-6);
+    }
+
+    #[test]
+    fn skips_poles_and_points_out_of_domain() {
+        let found = roots(&mut |x: f64| Ok(1f64 / x), &mut |x: f64| Ok(-1f64 / (x * x)), &span(-1f64, 1f64, 100)).unwrap();
+        assert!(found.is_empty(), "{:?}", found);
+        let mut ln = |x: f64| if x > 0f64 { Ok(x.ln() - 1f64) } else { Err(Error::new(ErrorCode::DomainError, "No logarithm.".to_string())) };
+        let found = roots(&mut ln, &mut |x: f64| Ok(1f64 / x), &span(-5f64, 5f64, 1000)).unwrap();
+        assert!(close(&found, &[std::f64::consts::E]), "{:?}", found);
+    }
+
+    #[test]
+    fn passes_other_errors_on() {
+        let mut f = |_: f64| Err(Error::new(ErrorCode::UndefinedVariable, "Undefined variable '$y'.".to_string()));
+        let error = roots(&mut f, &mut |_: f64| Ok(0f64), &span(-1f64, 1f64, 10)).unwrap_err();
+        assert_eq!(error.code, ErrorCode::UndefinedVariable);
+    }
+
+    #[test]
+    fn refuses_expressions_that_are_zero_throughout() {
+        let error = roots(&mut |_: f64| Ok(0f64), &mut |_: f64| Ok(0f64), &span(0f64, 1f64, 1000)).unwrap_err();
+        assert_eq!(error.code, ErrorCode::DomainError);
+        let mut ramp = |x: f64| Ok(x.max(0f64));
+        assert_eq!(roots(&mut ramp, &mut |_: f64| Ok(1f64), &span(-1f64, 1f64, 10)).unwrap_err().code, ErrorCode::DomainError);
+    }
+
+    #[test]
+    fn searches_everywhere_in_order() {
+        let points = everywhere();
+        assert!(points.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(points[0] <= -1e9 && points[points.len() - 1] >= 1e9);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::grover::{error, evaluator, parser, token};
+use error::Error;
+use error::ErrorCode;
+use evaluator::Evaluator;
+use parser::Parser;
+use std::rc::Rc;
+use token::TokenIterator;
+use token::Tokens;
+
+// A column to compute for every row, from `name = formula`.
+pub struct Column {
+    pub name: String,
+    pub formula: Tokens,
+}
+
+impl Column {
+    pub fn parse(definition: &str, radix: u32) -> Result<Self, Error> {
+        let (name, formula) = match definition.split_once('=') {
+            Some((name, formula)) if !name.trim().is_empty() => (name.trim(), formula),
+            _ => {
+                return Err(Error::new(ErrorCode::MalformedExpression, format!("Expected \'name = formula\', found \'{}\'.", definition)));
+            }
+        };
+        let mut parser = Parser::new(TokenIterator::new(formula, radix));
+        let mut statements = parser.script().map_err(|mut errors| errors.remove(0))?;
+        match statements.pop() {
+            Some(formula) if statements.is_empty() => Ok(Column { name: name.to_string(), formula }),
+            _ => Err(Error::new(ErrorCode::MalformedExpression, format!("The formula for \'{}\' must be a single expression.", name))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnError {
+    // Stop at the first bad row.
+    Fail,
+    // Leave bad rows out of the output.
+    Skip,
+    // Keep bad rows, with their computed columns empty.
+    Empty,
+}
+
+#[derive(Debug)]
+pub struct RowError {
+    // One-based, counting the header.
+    pub row: usize,
+    pub error: Error,
+}
+
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "row {}: {}", self.row, self.error)
+    }
+}
+
+// The variable a header binds, e.g. `unit price` becomes `$unit_price`.
+pub fn variable(header: &str) -> String {
+    let mut name: String = header
+        .trim()
+        .chars()
+        .map(|character| if character.is_alphanumeric() || character == '_' { character } else { '_' })
+        .collect();
+    if !name.starts_with(|character: char| character.is_alphabetic() || character == '_') {
+        name.insert(0, '_');
+    }
+    format!("${}", name)
+}
+
+// Appends the computed columns to every row. The first record is the header; the fields a formula reads
+// are bound to the variables their headers name, on a fresh child of the base evaluator, and earlier
+// computed columns can be used by later ones. Fields that are not numbers are an error if a formula reads them.
+pub fn add_columns(
+    base: Rc<Evaluator>,
+    mut records: Vec<Vec<String>>,
+    columns: &[Column],
+    on_error: OnError,
+) -> Result<(Vec<Vec<String>>, Vec<RowError>), RowError> {
+    if records.is_empty() {
+        return Ok((records, Vec::new()));
+    }
+    let mut header = records.remove(0);
+    let bind = |name: &str| {
+        let variable = variable(name);
+        let read = columns.iter().any(|column| column.formula.variables().contains(&variable));
+        Variable { header: name.trim().to_string(), name: variable, read }
+    };
+    let variables: Vec<Variable> = header.iter().map(|name| bind(name)).collect();
+    let computed: Vec<Variable> = columns.iter().map(|column| bind(&column.name)).collect();
+    // A column named after a constant such as `$e` can not be bound, which is the same for every row.
+    if let Some(clash) = variables.iter().chain(&computed).find(|variable| variable.read && base.is_constant(&variable.name)) {
+        let error = Error::new(ErrorCode::ReassignConstant, format!("Column \'{}\' can not be read as constant \'{}\'.", clash.header, clash.name));
+        return Err(RowError { row: 1, error });
+    }
+    header.extend(columns.iter().map(|column| column.name.clone()));
+
+    let mut output = vec![header];
+    let mut errors = Vec::<RowError>::new();
+    for (index, mut record) in records.into_iter().enumerate() {
+        let row = index + 2;
+        match compute(&base, &variables, &record, columns, &computed) {
+            Ok(values) => {
+                record.extend(values.iter().map(|value| value.to_string()));
+                output.push(record);
+            }
+            Err(error) => {
+                let error = RowError { row, error };
+                match on_error {
+                    OnError::Fail => return Err(error),
+                    OnError::Skip => {}
+                    OnError::Empty => {
+                        record.extend(columns.iter().map(|_| String::new()));
+                        output.push(record);
+                    }
+                }
+                errors.push(error);
+            }
+        }
+    }
+    Ok((output, errors))
+}
+
+// A column of the input, bound to a variable in every row.
+struct Variable {
+    header: String,
+    name: String,
+    // Whether any formula reads it.
+    read: bool,
+}
+
+fn compute(base: &Rc<Evaluator>, variables: &[Variable], record: &[String], columns: &[Column], computed: &[Variable]) -> Result<Vec<f64>, Error> {
+    let mut evaluator = Evaluator::child(base.clone());
+    for (variable, field) in variables.iter().zip(record).filter(|(variable, _)| variable.read) {
+        match field.trim().parse::<f64>() {
+            Ok(value) => evaluator.declare(&variable.name, value.into())?,
+            Err(_) => {
+                return Err(Error::new(ErrorCode::FormatError, format!("Column \'{}\' value \'{}\' is not a number.", variable.header, field)));
+            }
+        }
+    }
+    let mut values = Vec::<f64>::with_capacity(columns.len());
+    for (column, variable) in columns.iter().zip(computed) {
+        let value = evaluator
+            .evaluate(column.formula.clone())
+            .and_then(|value| value.number())
+            .map_err(|error| Error::new(error.code, format!("Could not compute \'{}\'.", column.name)).with_cause(error))?;
+        if variable.read {
+            evaluator.declare(&variable.name, value.into())?;
+        }
+        values.push(value);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|field| field.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn reports_text_read_inside_lambdas() {
+        let columns = [Column::parse("total = sum(map([1, 2], $n -> $n * $price))", 10).unwrap()];
+        let input = records(&[&["price"], &["2"], &["n/a"]]);
+        let (output, errors) = add_columns(Rc::new(Evaluator::new()), input, &columns, OnError::Empty).unwrap();
+        assert_eq!(output[1], ["2", "6"]);
+        assert_eq!(errors[0].row, 3);
+        assert_eq!(errors[0].error.code, ErrorCode::FormatError);
+    }
+
+    #[test]
+    fn leaves_out_columns_named_after_constants_unless_read() {
+        let base = Rc::new(Evaluator::new());
+        let columns = [Column::parse("double = 2 * $x", 10).unwrap(), Column::parse("pi = $double", 10).unwrap()];
+        let (output, errors) = add_columns(base.clone(), records(&[&["x", "e"], &["1", "2"]]), &columns, OnError::Fail).unwrap();
+        assert_eq!(output[1], ["1", "2", "2", "2"]);
+        assert!(errors.is_empty());
+        let columns = [Column::parse("double = 2 * $e", 10).unwrap()];
+        let error = add_columns(base, records(&[&["x", "e"], &["1", "2"], &["3", "4"]]), &columns, OnError::Skip).unwrap_err();
+        assert_eq!((error.row, error.error.code), (1, ErrorCode::ReassignConstant));
+    }
+}
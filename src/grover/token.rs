@@ -1,3 +1,11 @@
+use crate::grover::{matrix, reader};
+use matrix::Matrix;
+use reader::ReadChars;
+use reader::Source;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::io::BufRead;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Associativity {
     Left,
@@ -51,39 +59,108 @@ pub const REMAINDER_OPERATOR: Operator = Operator {
     associativity: Associativity::Left,
 };
 
+// Element by element, where `*` and `^` on matrices are the matrix product and power.
+pub const ELEMENTWISE_MULTIPLICATION_OPERATOR: Operator = Operator {
+    symbol: ".*",
+    precedence: 1,
+    associativity: Associativity::Left,
+};
+
+pub const ELEMENTWISE_DIVISION_OPERATOR: Operator = Operator {
+    symbol: "./",
+    precedence: 1,
+    associativity: Associativity::Left,
+};
+
+pub const ELEMENTWISE_POWER_OPERATOR: Operator = Operator {
+    symbol: ".^",
+    precedence: 0,
+    associativity: Associativity::Right,
+};
+
+// Inclusive, as in `1..10`.
+pub const RANGE_OPERATOR: Operator = Operator {
+    symbol: "..",
+    precedence: 3,
+    associativity: Associativity::Left,
+};
+
+// Comparisons are worth 1 when they hold and 0 otherwise.
+pub const LESS_OPERATOR: Operator = Operator {
+    symbol: "<",
+    precedence: 4,
+    associativity: Associativity::Left,
+};
+
+pub const LESS_EQUAL_OPERATOR: Operator = Operator {
+    symbol: "<=",
+    precedence: 4,
+    associativity: Associativity::Left,
+};
+
+pub const GREATER_OPERATOR: Operator = Operator {
+    symbol: ">",
+    precedence: 4,
+    associativity: Associativity::Left,
+};
+
+pub const GREATER_EQUAL_OPERATOR: Operator = Operator {
+    symbol: ">=",
+    precedence: 4,
+    associativity: Associativity::Left,
+};
+
+pub const EQUAL_OPERATOR: Operator = Operator {
+    symbol: "==",
+    precedence: 4,
+    associativity: Associativity::Left,
+};
+
+pub const NOT_EQUAL_OPERATOR: Operator = Operator {
+    symbol: "!=",
+    precedence: 4,
+    associativity: Associativity::Left,
+};
+
 pub const ASSIGNMENT_OPERATOR: Operator = Operator {
     symbol: "=",
-    precedence: 3,
+    precedence: 5,
+    associativity: Associativity::Right,
+};
+
+pub const BINDING_OPERATOR: Operator = Operator {
+    symbol: ":=",
+    precedence: 5,
     associativity: Associativity::Right,
 };
 
 pub const ADDITION_ASSIGNMENT_OPERATOR: Operator = Operator {
     symbol: "+=",
-    precedence: 3,
+    precedence: 5,
     associativity: Associativity::Right,
 };
 
 pub const SUBTRACTION_ASSIGNMENT_OPERATOR: Operator = Operator {
     symbol: "-=",
-    precedence: 3,
+    precedence: 5,
     associativity: Associativity::Right,
 };
 
 pub const MULTIPLICATION_ASSIGNMENT_OPERATOR: Operator = Operator {
     symbol: "*=",
-    precedence: 3,
+    precedence: 5,
     associativity: Associativity::Right,
 };
 
 pub const DIVISION_ASSIGNMENT_OPERATOR: Operator = Operator {
     symbol: "/=",
-    precedence: 3,
+    precedence: 5,
     associativity: Associativity::Right,
 };
 
 pub const REMAINDER_ASSIGNMENT_OPERATOR: Operator = Operator {
     symbol: "%=",
-    precedence: 3,
+    precedence: 5,
     associativity: Associativity::Right,
 };
 
@@ -103,8 +180,30 @@ pub const POWER_TOKEN: Token = Token::Operator(&POWER_OPERATOR);
 
 pub const REMAINDER_TOKEN: Token = Token::Operator(&REMAINDER_OPERATOR);
 
+pub const ELEMENTWISE_MULTIPLICATION_TOKEN: Token = Token::Operator(&ELEMENTWISE_MULTIPLICATION_OPERATOR);
+
+pub const ELEMENTWISE_DIVISION_TOKEN: Token = Token::Operator(&ELEMENTWISE_DIVISION_OPERATOR);
+
+pub const ELEMENTWISE_POWER_TOKEN: Token = Token::Operator(&ELEMENTWISE_POWER_OPERATOR);
+
+pub const RANGE_TOKEN: Token = Token::Operator(&RANGE_OPERATOR);
+
+pub const LESS_TOKEN: Token = Token::Operator(&LESS_OPERATOR);
+
+pub const LESS_EQUAL_TOKEN: Token = Token::Operator(&LESS_EQUAL_OPERATOR);
+
+pub const GREATER_TOKEN: Token = Token::Operator(&GREATER_OPERATOR);
+
+pub const GREATER_EQUAL_TOKEN: Token = Token::Operator(&GREATER_EQUAL_OPERATOR);
+
+pub const EQUAL_TOKEN: Token = Token::Operator(&EQUAL_OPERATOR);
+
+pub const NOT_EQUAL_TOKEN: Token = Token::Operator(&NOT_EQUAL_OPERATOR);
+
 pub const ASSIGNMENT_TOKEN: Token = Token::Operator(&ASSIGNMENT_OPERATOR);
 
+pub const BINDING_TOKEN: Token = Token::Operator(&BINDING_OPERATOR);
+
 pub const ADDITION_ASSIGNMENT_TOKEN: Token = Token::Operator(&ADDITION_ASSIGNMENT_OPERATOR);
 
 pub const SUBTRACTION_ASSIGNMENT_TOKEN: Token = Token::Operator(&SUBTRACTION_ASSIGNMENT_OPERATOR);
@@ -115,30 +214,153 @@ pub const DIVISION_ASSIGNMENT_TOKEN: Token = Token::Operator(&DIVISION_ASSIGNMEN
 
 pub const REMAINDER_ASSIGNMENT_TOKEN: Token = Token::Operator(&REMAINDER_ASSIGNMENT_OPERATOR);
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Identifier(String),
     Number(f64),
+    // Name and argument count, which the parser fills in.
+    Function(String, usize),
+    Comma,
     Operator(&'static Operator),
     LeftParenthesis,
     RightParenthesis,
+    // Blocks open a scope and are worth their last statement.
+    LeftBrace,
+    RightBrace,
+    // Matrix literals, with `,` between elements and `;` between rows, and indices after a value.
+    LeftBracket,
+    RightBracket,
+    // Separates the parameters of a lambda from its body.
+    Arrow,
+    Separator,
+    Error(String),
+    // Starts a statement defining a function with the given parameters.
+    Definition(String, Vec<String>),
+    // Starts a statement binding a variable to the formula that follows.
+    Binding(String),
+    // Binds the value on top of the stack in the innermost scope.
+    Let(String),
+    // A spreadsheet cell such as `B2`.
+    Cell(String),
+    // A rectangle of cells such as `A1:B10`, only valid as function arguments.
+    Range(String, String),
+    // Quoted text, only valid as the file name of an import.
+    Text(String),
+    // Brings the definitions of a file in under its own namespace.
+    Import(String),
+    // Brings the definitions of a module in without a namespace.
+    Use(String),
+    // An expression in the given parameters, evaluated by the function it is passed to.
+    Lambda(Vec<String>, Tokens),
+    // Builds a matrix from the values before it, given how many make up each row.
+    Array(Vec<usize>),
+    // Indexes the value before the given number of indices.
+    Index(usize),
+    // A matrix value, only ever found on the evaluator's stack.
+    Matrix(Matrix),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+pub trait Lexer: Iterator<Item = Token> {
+    // Position of the first character of the last token returned.
+    fn position(&self) -> Position;
+}
+
+// Peekable character stream that keeps track of where the next character is.
+pub struct Chars<S: Source> {
+    source: S,
+    peeked: VecDeque<Option<char>>,
+    position: Position,
+}
+
+impl<S: Source> Chars<S> {
+    pub fn new(source: S) -> Self {
+        Chars {
+            source,
+            peeked: VecDeque::new(),
+            position: Position { line: 1, column: 1 },
+        }
+    }
+    pub fn peek(&mut self) -> Option<&char> {
+        self.peek_nth(0)
+    }
+    pub fn peek_nth(&mut self, n: usize) -> Option<&char> {
+        while self.peeked.len() <= n {
+            let character = self.source.next();
+            self.peeked.push_back(character);
+        }
+        self.peeked[n].as_ref()
+    }
+    pub fn position(&self) -> Position {
+        self.position
+    }
+    pub fn get_error(&self) -> Option<&String> {
+        self.source.get_error()
+    }
+}
+
+impl<S: Source> Iterator for Chars<S> {
+    type Item = char;
+    fn next(&mut self) -> Option<Self::Item> {
+        let character = match self.peeked.pop_front() {
+            Some(character) => character,
+            None => self.source.next(),
+        };
+        match character {
+            Some('\n') => {
+                self.position.line += 1;
+                self.position.column = 1;
+            }
+            Some(_) => self.position.column += 1,
+            None => {}
+        }
+        character
+    }
 }
 
-pub struct TokenIterator<'a> {
-    chars: std::iter::Peekable<std::str::Chars<'a>>,
+pub struct TokenIterator<S: Source> {
+    chars: Chars<S>,
+    start: Position,
+    // Open brackets; newlines only separate statements outside parentheses and matrices.
+    brackets: Vec<char>,
     state: u8,
     error: String,
     radix: u32,
 }
 
-impl<'a> TokenIterator<'a> {
+impl<'a> TokenIterator<std::str::Chars<'a>> {
+    pub fn new(string: &'a str, radix: u32) -> Self {
+        TokenIterator::from_source(string.chars(), radix)
+    }
+}
+
+impl<R: BufRead> TokenIterator<ReadChars<R>> {
+    pub fn from_reader(reader: R, radix: u32) -> Self {
+        TokenIterator::from_source(ReadChars::new(reader), radix)
+    }
+}
+
+impl<S: Source> TokenIterator<S> {
     const GOOD: u8 = 0b1u8;
     const BAD: u8 = 0b10u8;
     const END: u8 = 0b100u8;
-    pub fn new(string: &'a str, radix: u32) -> TokenIterator<'a> {
+    pub fn from_source(source: S, radix: u32) -> Self {
         TokenIterator {
-            chars: string.chars().peekable(),
-            state: TokenIterator::GOOD,
+            chars: Chars::new(source),
+            start: Position { line: 1, column: 1 },
+            brackets: Vec::<char>::new(),
+            state: Self::GOOD,
             error: "".to_string(),
             radix
         }
@@ -150,13 +372,13 @@ impl<'a> TokenIterator<'a> {
         self.state &= !mask;
     }
     pub fn good(&self) -> bool {
-        self.state & TokenIterator::GOOD != 0
+        self.state & Self::GOOD != 0
     }
     pub fn bad(&self) -> bool {
-        self.state & TokenIterator::BAD != 0
+        self.state & Self::BAD != 0
     }
     pub fn end(&self) -> bool {
-        self.state & TokenIterator::END != 0
+        self.state & Self::END != 0
     }
     pub fn get_error(&self) -> Option<&String> {
         if self.bad() {
@@ -164,22 +386,67 @@ impl<'a> TokenIterator<'a> {
         }
         None
     }
-}   
+    // Records the error and hands it to the parser as a token so lexing can carry on.
+    fn fail(&mut self, message: String) -> Option<Token> {
+        self.clear(Self::GOOD);
+        self.state(Self::BAD);
+        self.error = message.clone();
+        Some(Token::Error(message))
+    }
+    // Reads the rest of a name, including `::` namespace separators.
+    fn name(&mut self, name: &mut String) -> Result<(), String> {
+        loop {
+            while let Some(&character) = self.chars.peek() {
+                if character.is_alphanumeric() || character == '_' {
+                    name.push(character);
+                    self.chars.next();
+                } else {
+                    break;
+                }
+            }
+            if self.chars.peek() != Some(&':') || self.chars.peek_nth(1) != Some(&':') {
+                return Ok(());
+            }
+            self.chars.next();
+            self.chars.next();
+            *name += "::";
+            match self.chars.peek() {
+                Some(&character) if character.is_alphabetic() || character == '_' => {}
+                _ => return Err(format!("Expected a name after '{}'.", name)),
+            }
+        }
+    }
+}
+
+impl<S: Source> Lexer for TokenIterator<S> {
+    fn position(&self) -> Position {
+        self.start
+    }
+}
 
-impl Iterator for TokenIterator<'_> {
+impl<S: Source> Iterator for TokenIterator<S> {
     type Item = Token;
-    fn next(&mut self) -> Option<<TokenIterator as Iterator>::Item> {
+    fn next(&mut self) -> Option<Self::Item> {
+        // Newlines only separate statements outside of parentheses and matrices.
         while let Some(character) = self.chars.peek() {
-            if *character == ' ' {
+            if character.is_whitespace() && (*character != '\n' || matches!(self.brackets.last(), Some('(' | '['))) {
                 self.chars.next();
             } else {
                 break;
             }
         }
-        let mut character: char = match self.chars.next() {
+        self.start = self.chars.position();
+        let character: char = match self.chars.next() {
             Some(character) => character,
             None => {
-                self.state(TokenIterator::END);
+                if self.end() {
+                    return None;
+                }
+                self.state(Self::END);
+                if let Some(error) = self.chars.get_error() {
+                    let message = format!("{}.", error);
+                    return self.fail(message);
+                }
                 return None;
             }
         };
@@ -189,44 +456,100 @@ impl Iterator for TokenIterator<'_> {
                 let mut identifier = String::from("$");
                 if let Some(character) = self.chars.peek() {
                     if !(character.is_alphabetic() || *character == '_') {
-                        self.state &= !TokenIterator::GOOD;
-                        self.state |= TokenIterator::BAD;
-                        self.error = format!("Variable name must start with a letter or undercore. Found \'{}\'.", character);
-                        return None;
+                        let message = format!("Variable name must start with a letter or undercore. Found \'{}\'.", character);
+                        return self.fail(message);
                     } else {
                         identifier += &character.to_string();
                         self.chars.next();
                     }
                 } else {
-                    self.clear(TokenIterator::GOOD);
-                    self.state(TokenIterator::BAD);
-                    self.error = format!("Variable name must be at least on character long.");
-                    return None;
+                    return self.fail("Variable name must be at least on character long.".to_string());
+                }
+                if let Err(message) = self.name(&mut identifier) {
+                    return self.fail(message);
                 }
+                Some(Token::Identifier(identifier))
+            }
+            '(' => {
+                self.brackets.push('(');
+                Some(Token::LeftParenthesis)
+            }
+            ')' => {
+                self.brackets.pop();
+                Some(Token::RightParenthesis)
+            }
+            '{' => {
+                self.brackets.push('{');
+                Some(Token::LeftBrace)
+            }
+            '}' => {
+                self.brackets.pop();
+                Some(Token::RightBrace)
+            }
+            '[' => {
+                self.brackets.push('[');
+                Some(Token::LeftBracket)
+            }
+            ']' => {
+                self.brackets.pop();
+                Some(Token::RightBracket)
+            }
+            ',' => Some(Token::Comma),
+            '"' => {
+                let mut text = String::new();
                 loop {
-                    if let Some(character) = self.chars.peek() {
-                        if character.is_alphanumeric() || *character == '_' {
-                            identifier += &character.to_string();
-                            self.chars.next();
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
+                    match self.chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match self.chars.next() {
+                            Some(character @ ('"' | '\\')) => text.push(character),
+                            Some(character) => return self.fail(format!("Invalid escape sequence '\\{}'.", character)),
+                            None => return self.fail("Missing the closing quote.".to_string()),
+                        },
+                        Some('\n') | None => return self.fail("Missing the closing quote.".to_string()),
+                        Some(character) => text.push(character),
                     }
                 }
-                return Some(Token::Identifier(identifier));
+                Some(Token::Text(text))
+            }
+            // Statement Separators
+            ';' | '\n' => Some(Token::Separator),
+            // Assignment and Comparison
+            '=' if self.chars.peek() == Some(&'=') => {
+                self.chars.next();
+                Some(EQUAL_TOKEN)
+            }
+            '=' => Some(ASSIGNMENT_TOKEN),
+            '!' if self.chars.peek() == Some(&'=') => {
+                self.chars.next();
+                Some(NOT_EQUAL_TOKEN)
+            }
+            '<' | '>' => {
+                let equal = self.chars.peek() == Some(&'=');
+                if equal {
+                    self.chars.next();
+                }
+                match (character, equal) {
+                    ('<', false) => Some(LESS_TOKEN),
+                    ('<', true) => Some(LESS_EQUAL_TOKEN),
+                    (_, false) => Some(GREATER_TOKEN),
+                    (_, true) => Some(GREATER_EQUAL_TOKEN),
+                }
+            }
+            // Lambdas
+            '-' if self.chars.peek() == Some(&'>') => {
+                self.chars.next();
+                Some(Token::Arrow)
+            }
+            ':' if self.chars.peek() == Some(&'=') => {
+                self.chars.next();
+                Some(BINDING_TOKEN)
             }
-            '(' => return Some(Token::LeftParenthesis),
-            ')' => return Some(Token::RightParenthesis),
-            // Assignment
-            '=' => return Some(ASSIGNMENT_TOKEN),
             // Operators
             '+' | '-' | '*' | '/' | '%' | '^' =>
             {
                 // Assignment Operators
                 if self.chars.peek() == Some(&'=') {
-                    return match character {
+                    match character {
                         '+' => {
                             self.chars.next();
                             Some(ADDITION_ASSIGNMENT_TOKEN)
@@ -249,15 +572,12 @@ impl Iterator for TokenIterator<'_> {
                         }
                         // Unhandled Assignment Operator
                         _ => {
-                            self.clear(TokenIterator::GOOD);
-                            self.state(TokenIterator::BAD);
-                            self.error = format!("Unhandled character \'{}\'", character);
-                            None
+                            self.fail(format!("Unhandled character \'{}\'.", character))
                         }
-                    };
+                    }
                 } else {
                     // Arithmetic Operators
-                    return match character {
+                    match character {
                         '+' => Some(ADDITION_TOKEN),
                         '-' => Some(SUBTRACTION_TOKEN),
                         '*' => Some(MULTIPLICATION_TOKEN),
@@ -266,25 +586,30 @@ impl Iterator for TokenIterator<'_> {
                         '^' => Some(POWER_TOKEN),
                         // Unhandled Operator
                         _ => {
-                            self.clear(TokenIterator::GOOD);
-                            self.state(TokenIterator::BAD);
-                            self.error = format!("Unhandled character \'{}\'", character);
-                            None
+                            self.fail(format!("Unhandled character \'{}\'.", character))
                         }
-                    };
+                    }
                 }
             }
+            // Ranges
+            '.' if self.chars.peek() == Some(&'.') => {
+                self.chars.next();
+                Some(RANGE_TOKEN)
+            }
+            // Element-wise Operators
+            '.' if matches!(self.chars.peek(), Some('*' | '/' | '^')) => match self.chars.next() {
+                Some('*') => Some(ELEMENTWISE_MULTIPLICATION_TOKEN),
+                Some('/') => Some(ELEMENTWISE_DIVISION_TOKEN),
+                _ => Some(ELEMENTWISE_POWER_TOKEN),
+            },
             // Numbers
-            _ if character.is_digit(self.radix) || if self.radix == 10 { true } else { false } && character == '.' => {
+            _ if character.is_digit(self.radix) || self.radix == 10 && character == '.' => {
                 let mut number = String::from(&character.to_string());
-                loop {
-                    if let Some(&character) = self.chars.peek() {
-                        if character.is_digit(self.radix) || if self.radix == 10 { true } else { false } && character == '.' {
-                            number += &character.to_string();
-                            self.chars.next();
-                        } else {
-                            break;
-                        }
+                while let Some(&character) = self.chars.peek() {
+                    // A number ends where a range begins, as in `1..10`.
+                    if character.is_digit(self.radix) || self.radix == 10 && character == '.' && self.chars.peek_nth(1) != Some(&'.') {
+                        number += &character.to_string();
+                        self.chars.next();
                     } else {
                         break;
                     }
@@ -293,44 +618,79 @@ impl Iterator for TokenIterator<'_> {
                     let number: f64 = match number.trim().parse() {
                         Ok(number) => number,
                         Err(_) => {
-                            self.clear(TokenIterator::GOOD);
-                            self.state(TokenIterator::BAD);
-                            self.error = format!("Could not parse \'{}\' to f64.", number);
-                            return None;
+                            return self.fail(format!("Could not parse \'{}\' to f64.", number));
                         }
                     };
-                    return Some(Token::Number(number));
+                    Some(Token::Number(number))
                 } else {
                     let number = match i64::from_str_radix(&number, self.radix) {
                         Ok(number) => number as f64,
                         Err(_) => {
-                            self.clear(TokenIterator::GOOD);
-                            self.state(TokenIterator::BAD);
-                            self.error = format!("Could not parse \'{}\' to f64.", number);
-                            return None;
+                            return self.fail(format!("Could not parse \'{}\' to f64.", number));
                         }
                     };
-                    return Some(Token::Number(number));
+                    Some(Token::Number(number))
+                }
+            }
+            // Functions
+            _ if character.is_alphabetic() || character == '_' => {
+                let mut name = character.to_string();
+                if let Err(message) = self.name(&mut name) {
+                    return self.fail(message);
+                }
+                if !is_cell(&name) || self.chars.peek() == Some(&'(') {
+                    return Some(Token::Function(name, 0));
+                }
+                // Cell references, alone or as the start of a range.
+                if self.chars.peek() != Some(&':') || !self.chars.peek_nth(1).is_some_and(|character| character.is_ascii_uppercase()) {
+                    return Some(Token::Cell(name));
+                }
+                self.chars.next();
+                let mut end = String::new();
+                while let Some(&character) = self.chars.peek() {
+                    if character.is_ascii_alphanumeric() {
+                        end.push(character);
+                        self.chars.next();
+                    } else {
+                        break;
+                    }
                 }
+                if !is_cell(&end) {
+                    return self.fail(format!("Expected a cell after '{}:', found '{}'.", name, end));
+                }
+                Some(Token::Range(name, end))
             }
             // Invalid Characters
             _ => {
-                self.clear(TokenIterator::GOOD);
-                self.state(TokenIterator::BAD);
-                self.error = format!("Invalid character \'{}\'", character);
-                return None;
+                self.fail(format!("Invalid character \'{}\'.", character))
             }
-        };
-        // No Characters Found
-        self.state(TokenIterator::END);
-        None
+        }
     }
 }
 
+// Column letters followed by a row number, as in `A1` or `AB12`.
+pub fn is_cell(name: &str) -> bool {
+    let letters = name.chars().take_while(|character| character.is_ascii_uppercase()).count();
+    let digits = &name[letters..];
+    letters > 0
+        && !digits.is_empty()
+        && !digits.starts_with('0')
+        && digits.chars().all(|character| character.is_ascii_digit())
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Tokens {
     raw: Vec<Token>,
 }
 
+impl std::iter::FromIterator<Token> for Tokens {
+    fn from_iter<I: IntoIterator<Item = Token>>(iter: I) -> Self {
+        Tokens {
+            raw: iter.into_iter().collect(),
+        }
+    }
+}
+
 impl IntoIterator for Tokens {
     type Item = Token;
     type IntoIter = std::vec::IntoIter<Token>;
@@ -340,6 +700,12 @@ impl IntoIterator for Tokens {
     }
 }
 
+impl Default for Tokens {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Tokens {
     pub fn new() -> Self {
         Tokens {
@@ -355,6 +721,46 @@ impl Tokens {
     pub fn len(&self) -> usize {
         self.raw.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.raw.clear();
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, Token> {
+        self.raw.iter()
+    }
+    pub fn first(&self) -> Option<&Token> {
+        self.raw.first()
+    }
+    pub fn split_off(&mut self, at: usize) -> Tokens {
+        Tokens {
+            raw: self.raw.split_off(at),
+        }
+    }
+    pub fn is_definition(&self) -> bool {
+        matches!(self.first(), Some(Token::Definition(_, _)))
+    }
+    pub fn is_binding(&self) -> bool {
+        matches!(self.first(), Some(Token::Binding(_)))
+    }
+    pub fn is_import(&self) -> bool {
+        matches!(self.first(), Some(Token::Import(_)) | Some(Token::Use(_)))
+    }
+    // The variables read, including those inside lambdas other than their parameters.
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut variables = BTreeSet::<String>::new();
+        for token in self.iter() {
+            match token {
+                Token::Identifier(name) => {
+                    variables.insert(name.clone());
+                }
+                Token::Lambda(parameters, body) => variables.extend(body.variables().into_iter().filter(|name| !parameters.contains(name))),
+                _ => {}
+            }
+        }
+        variables
+    }
 }
 
 impl std::fmt::Display for Tokens {
@@ -363,10 +769,31 @@ impl std::fmt::Display for Tokens {
 
         for token in &self.raw {
             match token {
-                Token::Identifier(identifier) => output += &identifier,
+                Token::Identifier(identifier) => output += identifier,
                 Token::Number(number) => output += &number.to_string(),
+                Token::Function(name, _) => output += name,
+                Token::Comma => output += ",",
                 Token::LeftParenthesis => output += "(",
                 Token::RightParenthesis => output += ")",
+                Token::LeftBrace => output += "{",
+                Token::RightBrace => output += "}",
+                Token::LeftBracket => output += "[",
+                Token::RightBracket => output += "]",
+                Token::Arrow => output += "->",
+                Token::Array(rows) => output += &format!("[{}]", rows.iter().map(|row| row.to_string()).collect::<Vec<_>>().join("; ")),
+                Token::Index(count) => output += &format!("[{}]", count),
+                Token::Matrix(matrix) => output += &matrix.to_string(),
+                Token::Let(name) => output += &format!("let {}", name),
+                Token::Separator => output += ";",
+                Token::Error(_) => output += "?",
+                Token::Definition(name, parameters) => output += &format!("{}({}) =", name, parameters.join(", ")),
+                Token::Binding(name) => output += &format!("{} :=", name),
+                Token::Cell(name) => output += name,
+                Token::Range(start, end) => output += &format!("{}:{}", start, end),
+                Token::Text(text) => output += &format!("{:?}", text),
+                Token::Import(path) => output += &format!("import {:?}", path),
+                Token::Use(name) => output += &format!("use {}", name),
+                Token::Lambda(parameters, body) => output += format!("({}) -> {}", parameters.join(", "), body).trim_end(),
                 Token::Operator(operator) => output += operator.symbol,
             }
             output += " ";
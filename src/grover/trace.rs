@@ -0,0 +1,96 @@
+use crate::grover::{error, evaluator, format, token, value};
+use error::Error;
+use evaluator::Evaluator;
+use token::Tokens;
+use value::Value;
+
+// One step of an evaluation, in the order the RPN walk took it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Read(String, Value),
+    Write(String, Value),
+    Operate(&'static str, Value, Value, Value),
+    // A built-in function applied to its arguments.
+    Apply(String, Vec<Value>, Value),
+    // A numerical result, such as an integral, with a bound on its error.
+    Estimate(String, Vec<Value>, Value, f64),
+    // A user function call or binding recomputation, whose steps follow until the matching `Leave`.
+    Enter(String, Vec<Value>),
+    Leave(Value),
+}
+
+// Evaluates a statement and renders how its value was derived, one indented line per step.
+pub fn explain(evaluator: &mut Evaluator, tokens: Tokens) -> Result<String, Error> {
+    let expression = format::statement(&tokens)?;
+    let tracing = evaluator.is_tracing();
+    evaluator.set_tracing(true);
+    evaluator.take_trace();
+    let result = evaluator.evaluate(tokens);
+    let steps = evaluator.take_trace();
+    evaluator.set_tracing(tracing);
+    Ok(render(&expression, &steps, &result?))
+}
+
+pub fn render(expression: &str, steps: &[Step], result: &Value) -> String {
+    let mut output = format!("{}\n", expression);
+    // Labels of the calls entered so far.
+    let mut calls = Vec::<String>::new();
+    for step in steps {
+        let indent = "  ".repeat(calls.len() + 1);
+        match step {
+            Step::Read(name, value) => output += &format!("{}{} = {}\n", indent, name, value),
+            Step::Write(name, value) => output += &format!("{}{} <- {}\n", indent, name, value),
+            Step::Operate(symbol, lhs, rhs, result) => output += &format!("{}{} {} {} = {}\n", indent, lhs, symbol, rhs, result),
+            Step::Apply(name, arguments, result) => output += &format!("{}{} = {}\n", indent, call(name, arguments), result),
+            Step::Estimate(name, arguments, result, error) => output += &format!("{}{} = {} ± {:.1e}\n", indent, call(name, arguments), result, error),
+            Step::Enter(name, arguments) => {
+                let label = if arguments.is_empty() { name.clone() } else { call(name, arguments) };
+                output += &format!("{}{}:\n", indent, label);
+                calls.push(label);
+            }
+            Step::Leave(result) => {
+                let label = calls.pop().unwrap_or_default();
+                output += &format!("{}{} = {}\n", "  ".repeat(calls.len() + 1), label, result);
+            }
+        }
+    }
+    output += &format!("= {}\n", result);
+    output
+}
+
+fn call(name: &str, arguments: &[Value]) -> String {
+    let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+    format!("{}({})", name, arguments.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grover::{eval, parse};
+
+    #[test]
+    fn explains_each_step() {
+        let mut evaluator = Evaluator::new();
+        eval("$x = 2; sq($y) = $y ^ 2", &mut evaluator).unwrap();
+        let explained = explain(&mut evaluator, parse("sq($x) + max(1, 3)").unwrap()).unwrap();
+        let expected = "sq($x) + max(1, 3)\n  $x = 2\n  sq(2):\n    $y = 2\n    2 ^ 2 = 4\n  sq(2) = 4\n  max(1, 3) = 3\n  4 + 3 = 7\n= 7\n";
+        assert_eq!(explained, expected);
+        assert!(!evaluator.is_tracing());
+    }
+
+    #[test]
+    fn records_reads_and_writes() {
+        let mut evaluator = Evaluator::new();
+        eval("$x = 2", &mut evaluator).unwrap();
+        evaluator.set_tracing(true);
+        eval("$z = $x * 3", &mut evaluator).unwrap();
+        let steps = evaluator.take_trace();
+        let number = |number: f64| Value::Number(number);
+        assert_eq!(steps, [
+            Step::Read("$x".to_string(), number(2f64)),
+            Step::Operate("*", number(2f64), number(3f64), number(6f64)),
+            Step::Write("$z".to_string(), number(6f64)),
+        ]);
+        assert!(evaluator.take_trace().is_empty());
+    }
+}
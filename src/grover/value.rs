@@ -0,0 +1,147 @@
+use crate::grover::{error, list, matrix, token};
+use error::Error;
+use error::ErrorCode;
+use matrix::Matrix;
+use token::Operator;
+use token::Token;
+
+// What an expression evaluates to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Matrix(Matrix),
+}
+
+impl Value {
+    // The number, or the single element of a 1 by 1 matrix.
+    pub fn number(&self) -> Result<f64, Error> {
+        match self {
+            Value::Number(number) => Ok(*number),
+            Value::Matrix(matrix) if matrix.rows() == 1 && matrix.columns() == 1 => Ok(matrix.get(0, 0)),
+            Value::Matrix(matrix) => {
+                Err(Error::new(ErrorCode::ShapeMismatch, format!("Expected a number, found a {} matrix.", matrix.shape())))
+            }
+        }
+    }
+    // A number as a 1 by 1 matrix.
+    pub fn matrix(self) -> Matrix {
+        match self {
+            Value::Number(number) => Matrix::new(1, 1, vec![number]),
+            Value::Matrix(matrix) => matrix,
+        }
+    }
+    // The value as it sits on the evaluator's stack.
+    pub fn into_token(self) -> Token {
+        match self {
+            Value::Number(number) => Token::Number(number),
+            Value::Matrix(matrix) => Token::Matrix(matrix),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(number: f64) -> Self {
+        Value::Number(number)
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Matrix(matrix) => write!(f, "{}", matrix),
+        }
+    }
+}
+
+// Applies an arithmetic operator. `*` and `^` are the matrix product and power, `..` makes a list, and the
+// other operators and their `.`-prefixed forms work element by element, with numbers standing in for every element.
+pub fn operate(operator: &Operator, lhs: &Value, rhs: &Value) -> Result<Value, Error> {
+    match (operator.symbol, lhs, rhs) {
+        ("..", _, _) => Ok(Value::Matrix(list::span(lhs.number()?, rhs.number()?)?)),
+        (_, Value::Number(lhs), Value::Number(rhs)) => Ok(Value::Number(arithmetic(operator, *lhs, *rhs)?)),
+        ("*", Value::Matrix(lhs), Value::Matrix(rhs)) => Ok(Value::Matrix(lhs.product(rhs)?)),
+        ("/", _, Value::Matrix(rhs)) => Err(Error::new(
+            ErrorCode::ShapeMismatch,
+            format!("Can not divide by a {} matrix. Use './' element by element, or linsolve(A, b).", rhs.shape()),
+        )),
+        ("^", Value::Matrix(lhs), Value::Number(rhs)) => Ok(Value::Matrix(lhs.power(*rhs)?)),
+        ("^", _, _) => Err(Error::new(ErrorCode::ShapeMismatch, "Only a square matrix can be raised to a power. Use '.^' element by element.".to_string())),
+        _ => elementwise(operator, lhs, rhs),
+    }
+}
+
+fn elementwise(operator: &Operator, lhs: &Value, rhs: &Value) -> Result<Value, Error> {
+    let (rows, columns) = match (lhs, rhs) {
+        (Value::Matrix(lhs), Value::Matrix(rhs)) if lhs.rows() != rhs.rows() || lhs.columns() != rhs.columns() => {
+            return Err(Error::new(
+                ErrorCode::ShapeMismatch,
+                format!("Can not apply '{}' to a {} matrix and a {} matrix.", operator.symbol, lhs.shape(), rhs.shape()),
+            ));
+        }
+        (Value::Matrix(matrix), _) | (_, Value::Matrix(matrix)) => (matrix.rows(), matrix.columns()),
+        (Value::Number(_), Value::Number(_)) => (1, 1),
+    };
+    let element = |value: &Value, index: usize| match value {
+        Value::Number(number) => *number,
+        Value::Matrix(matrix) => matrix.data()[index],
+    };
+    let mut data = Vec::<f64>::with_capacity(rows * columns);
+    for index in 0..rows * columns {
+        data.push(arithmetic(operator, element(lhs, index), element(rhs, index))?);
+    }
+    Ok(Value::Matrix(Matrix::new(rows, columns, data)))
+}
+
+// An arithmetic operator applied to numbers.
+pub fn arithmetic(operator: &Operator, lhs: f64, rhs: f64) -> Result<f64, Error> {
+    match operator.symbol {
+        "+" => finite(lhs + rhs),
+        "-" => finite(lhs - rhs),
+        "*" | ".*" => finite(lhs * rhs),
+        "/" | "./" => {
+            if rhs == 0f64 {
+                return Err(Error::new(ErrorCode::DivisionByZero, "Division by zero is undefined.".to_string()));
+            }
+            finite(lhs / rhs)
+        }
+        "%" => {
+            if rhs == 0f64 {
+                return Err(Error::new(ErrorCode::DivisionByZero, "Division by zero is undefined.".to_string()));
+            }
+            finite(lhs % rhs)
+        }
+        "^" | ".^" => {
+            if lhs == 0f64 && rhs < 0f64 {
+                return Err(Error::new(ErrorCode::DivisionByZero, "Division by zero is undefined.".to_string()));
+            }
+            let result = lhs.powf(rhs);
+            if result.is_nan() {
+                return Err(Error::new(ErrorCode::DomainError, format!("{} ^ {} is not a real number.", lhs, rhs)));
+            }
+            finite(result)
+        }
+        "<" => Ok(truth(lhs < rhs)),
+        "<=" => Ok(truth(lhs <= rhs)),
+        ">" => Ok(truth(lhs > rhs)),
+        ">=" => Ok(truth(lhs >= rhs)),
+        "==" => Ok(truth(lhs == rhs)),
+        "!=" => Ok(truth(lhs != rhs)),
+        _ => Err(Error::new(ErrorCode::EvaluatorError, "Unhandled operator.".to_string())),
+    }
+}
+
+fn truth(holds: bool) -> f64 {
+    if holds {
+        1f64
+    } else {
+        0f64
+    }
+}
+
+pub fn finite(result: f64) -> Result<f64, Error> {
+    if result.is_infinite() {
+        return Err(Error::new(ErrorCode::Overflow, "Result is too large to represent.".to_string()));
+    }
+    Ok(result)
+}
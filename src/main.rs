@@ -1,18 +1,299 @@
-mod grover;
+use oscar::grover;
 
-fn main() {
-    let mut lexer = grover::TokenIterator::new("(5   +4) * 2", 10);
-    let mut parser = grover::Parser::new(lexer);
-    let tokens = match parser.intermediate() {
-        Ok(tokens) => tokens,
+// `oscar sheet grid.csv [--formulas]` evaluates a spreadsheet and prints it as CSV.
+fn sheet(arguments: &[String]) {
+    let mut formulas = false;
+    let mut file = None;
+    for argument in arguments {
+        match argument.as_str() {
+            "--formulas" => formulas = true,
+            _ => file = Some(argument),
+        }
+    }
+    let file = match file {
+        Some(file) => file,
+        None => {
+            eprintln!("usage: oscar sheet <file.csv> [--formulas]");
+            std::process::exit(2);
+        }
+    };
+    let input = match std::fs::read_to_string(file) {
+        Ok(input) => input,
         Err(error) => {
-            println!("{}", error.message);
-            return;
+            eprintln!("Could not read '{}': {}", file, error);
+            std::process::exit(1);
+        }
+    };
+    match grover::Sheet::from_csv(&input) {
+        Ok(sheet) => print!("{}", sheet.to_csv(formulas)),
+        Err(error) => {
+            print!("{}", grover::diagnostic::render(&input, &error.in_file(file, input.as_str().into())));
+            std::process::exit(1);
+        }
+    }
+}
+
+// `oscar csv in.csv --add 'total = $price * $qty'` appends computed columns to a CSV file.
+fn csv(arguments: &[String]) {
+    let usage = "usage: oscar csv <file.csv | -> --add 'name = formula'... [--on-error fail|skip|empty] [--load file] [--output file]";
+    let mut file = None;
+    let mut additions = Vec::<String>::new();
+    let mut on_error = grover::table::OnError::Fail;
+    let mut load = None;
+    let mut output = None;
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--add" => additions.extend(arguments.next().cloned()),
+            "--on-error" => {
+                on_error = match arguments.next().map(|policy| policy.as_str()) {
+                    Some("fail") => grover::table::OnError::Fail,
+                    Some("skip") => grover::table::OnError::Skip,
+                    Some("empty") => grover::table::OnError::Empty,
+                    _ => {
+                        eprintln!("{}", usage);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            "--load" => load = arguments.next().cloned(),
+            "--output" => output = arguments.next().cloned(),
+            _ => file = Some(argument.clone()),
+        }
+    }
+    let file = match file {
+        Some(file) if !additions.is_empty() => file,
+        _ => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+    let input = if file == "-" {
+        let mut input = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut input).map(|_| input)
+    } else {
+        std::fs::read_to_string(&file)
+    };
+    let input = match input {
+        Ok(input) => input,
+        Err(error) => {
+            eprintln!("Could not read \'{}\': {}", file, error);
+            std::process::exit(1);
+        }
+    };
+    let mut base = grover::Evaluator::new();
+    if let Some(path) = load {
+        if let Err(error) = grover::environment::load_file(&mut base, &path) {
+            eprint!("{}", grover::diagnostic::render("", &error));
+            std::process::exit(1);
+        }
+    }
+    let mut columns = Vec::<grover::table::Column>::new();
+    for addition in &additions {
+        match grover::table::Column::parse(addition, base.radix()) {
+            Ok(column) => columns.push(column),
+            Err(error) => {
+                eprint!("{}", grover::diagnostic::render(addition, &error));
+                std::process::exit(2);
+            }
+        }
+    }
+    let records = match grover::csv::parse(&input) {
+        Ok(records) => records,
+        Err(error) => {
+            eprint!("{}", grover::diagnostic::render(&input, &error.in_file(&file, input.as_str().into())));
+            std::process::exit(1);
         }
     };
-    println!("{}", tokens);
+    let (records, errors) = match grover::table::add_columns(std::rc::Rc::new(base), records, &columns, on_error) {
+        Ok(result) => result,
+        Err(error) => {
+            report(&file, &error);
+            std::process::exit(1);
+        }
+    };
+    for error in &errors {
+        report(&file, error);
+    }
+    let text = grover::csv::write(&records);
+    match output {
+        Some(path) => {
+            if let Err(error) = std::fs::write(&path, text) {
+                eprintln!("Could not write \'{}\': {}", path, error);
+                std::process::exit(1);
+            }
+        }
+        None => print!("{}", text),
+    }
+}
+
+fn report(file: &str, error: &grover::table::RowError) {
+    eprintln!("{}: {}", file, error);
+    let mut cause = error.error.cause.as_deref();
+    while let Some(inner) = cause {
+        eprintln!("caused by: {}", inner);
+        cause = inner.cause.as_deref();
+    }
+}
+
+fn main() {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    match arguments.first().map(|argument| argument.as_str()) {
+        Some("sheet") => return sheet(&arguments[1..]),
+        Some("csv") => return csv(&arguments[1..]),
+        _ => {}
+    }
+    let mut lenient = false;
+    let mut explain = false;
+    let mut simplify = false;
+    let mut latex = false;
+    let mut notation = None;
+    let mut load = None;
+    let mut save = None;
+    let mut paths = Vec::<String>::new();
+    let mut words = Vec::<String>::new();
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--lenient" => lenient = true,
+            "--latex" => latex = true,
+            // `oscar :explain '2 * ($x + 1)'` shows how the value was derived.
+            "--explain" | ":explain" => explain = true,
+            // `oscar :simplify '2 * $x + $x * 1'` prints `3 * $x` without evaluating it.
+            "--simplify" | ":simplify" => simplify = true,
+            "--format" => {
+                notation = match arguments.next().as_deref() {
+                    Some("latex") => Some(grover::export::Notation::Latex),
+                    Some("mathml") => Some(grover::export::Notation::MathMl),
+                    _ => {
+                        eprintln!("usage: oscar --format latex|mathml <expression>");
+                        std::process::exit(2);
+                    }
+                }
+            }
+            "--load" => load = arguments.next(),
+            "--save" => save = arguments.next(),
+            "--path" => {
+                if let Some(path) = arguments.next() {
+                    paths.push(path);
+                }
+            }
+            _ => words.push(argument),
+        }
+    }
     let mut eval = grover::Evaluator::new();
-    let ans = eval.evaluate(tokens);
-    println!("{:?}", ans)
-    // println!("{}", token.get_error().unwrap());
+    for path in paths {
+        eval.modules_mut().add_path(path);
+    }
+    if let Some(path) = load {
+        if let Err(error) = grover::environment::load_file(&mut eval, &path) {
+            print!("{}", grover::diagnostic::render("", &error));
+            return;
+        }
+    }
+    if lenient {
+        eval.set_lenient(true);
+    }
+    let input = words.join(" ");
+    let statements = if latex {
+        let mut latex = String::new();
+        if input.is_empty() {
+            if let Err(error) = std::io::Read::read_to_string(&mut std::io::stdin(), &mut latex) {
+                eprintln!("Could not read the input: {}", error);
+                return;
+            }
+        }
+        let mut parser = grover::Parser::new(grover::LatexLexer::new(if input.is_empty() { &latex } else { &input }));
+        parser.script()
+    } else if input.is_empty() {
+        let mut parser = grover::Parser::new(grover::TokenIterator::from_reader(std::io::stdin().lock(), eval.radix()));
+        parser.script()
+    } else {
+        let mut parser = grover::Parser::new(grover::TokenIterator::new(&input, eval.radix()));
+        parser.script()
+    };
+    let statements = match statements {
+        Ok(statements) => statements,
+        Err(errors) => {
+            for error in errors {
+                print!("{}", grover::diagnostic::render(&input, &error));
+            }
+            return;
+        }
+    };
+    for tokens in statements {
+        let silent = tokens.is_definition() || tokens.is_import();
+        if simplify {
+            let simplified = grover::calculus::statement(&eval, &tokens)
+                .and_then(|tokens| grover::simplify::statement(&eval, &tokens))
+                .and_then(|tokens| grover::format::statement(&tokens));
+            match simplified {
+                Ok(simplified) => println!("{}", simplified),
+                Err(error) => {
+                    print!("{}", grover::diagnostic::render(&input, &error));
+                    return;
+                }
+            }
+            continue;
+        }
+        if explain && !silent {
+            match grover::trace::explain(&mut eval, tokens) {
+                Ok(derivation) => print!("{}", derivation),
+                Err(error) => {
+                    print!("{}", grover::diagnostic::render(&input, &error));
+                    return;
+                }
+            }
+            continue;
+        }
+        let statement = if notation.is_some() { Some(tokens.clone()) } else { None };
+        let rewrite = if grover::calculus::is_rewrite(&tokens) { Some(tokens.clone()) } else { None };
+        let estimate = grover::calculus::is_estimate(&tokens);
+        match eval.evaluate(tokens) {
+            Ok(ans) => {
+                if silent {
+                    continue;
+                }
+                match (notation, statement) {
+                    (Some(notation), Some(tokens)) => match grover::export::equation(&tokens, &ans, notation) {
+                        Ok(equation) => println!("{}", equation),
+                        Err(error) => {
+                            print!("{}", grover::diagnostic::render(&input, &error));
+                            return;
+                        }
+                    },
+                    _ => match eval.estimate().filter(|_| estimate) {
+                        Some(error) => println!("{} ± {:.1e}", ans, error),
+                        None => println!("{}", ans),
+                    },
+                }
+            }
+            // `simplify($x * 1 + 0)` prints `$x` when there is no `$x` to evaluate it with.
+            Err(error) if error.code == grover::ErrorCode::UndefinedVariable && rewrite.is_some() => {
+                let rewritten = rewrite
+                    .as_ref()
+                    .map_or(Err(error), |tokens| grover::calculus::statement(&eval, tokens))
+                    .and_then(|tokens| match notation {
+                        Some(notation) => grover::export::statement(&tokens, notation),
+                        None => grover::format::statement(&tokens),
+                    });
+                match rewritten {
+                    Ok(rewritten) => println!("{}", rewritten),
+                    Err(error) => {
+                        print!("{}", grover::diagnostic::render(&input, &error));
+                        return;
+                    }
+                }
+            }
+            Err(error) => {
+                print!("{}", grover::diagnostic::render(&input, &error));
+                return;
+            }
+        }
+    }
+    if let Some(path) = save {
+        if let Err(error) = grover::environment::save_file(&eval, &path) {
+            print!("{}", grover::diagnostic::render("", &error));
+        }
+    }
 }